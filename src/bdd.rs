@@ -0,0 +1,460 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// A small reduced ordered binary decision diagram package. Variables are ordered by their
+// index, nodes are never freed and all operations go through a memoized if-then-else.
+// Operations fail once they would exceed the node budget. The recursion descends one
+// variable at a time, thus its depth is bounded by the limit on the number of variables.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bdd(u32);
+
+pub const FALSE: Bdd = Bdd(0);
+pub const TRUE: Bdd = Bdd(1);
+
+/// Level of the terminals, below all variables.
+const TERMINAL: u32 = u32::MAX;
+/// Computed tables are cleared once they grow beyond this number of entries.
+const MAX_CACHE: usize = 1 << 22;
+/// Upper bound on the number of nodes, nodes are never freed.
+pub const MAX_NODES: usize = 1 << 22;
+/// Upper bound on the variable index, which keeps the recursion of all operations shallow.
+pub const MAX_VARS: u32 = 1 << 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    OutOfNodes,
+    TooManyVariables,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::OutOfNodes => write!(f, "exceeded the limit of {MAX_NODES} BDD nodes"),
+            Error::TooManyVariables => write!(f, "more than {MAX_VARS} BDD variables"),
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> Self {
+        e.to_string()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Node {
+    var: u32,
+    lo: Bdd,
+    hi: Bdd,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manager {
+    nodes: Vec<Node>,
+    unique: HashMap<Node, Bdd>,
+    ite_cache: HashMap<(Bdd, Bdd, Bdd), Bdd>,
+    exists_cache: HashMap<(Bdd, Bdd), Bdd>,
+    and_exists_cache: HashMap<(Bdd, Bdd, Bdd), Bdd>,
+    max_nodes: usize,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        let terminal = |b| Node {
+            var: TERMINAL,
+            lo: b,
+            hi: b,
+        };
+        Self {
+            nodes: vec![terminal(FALSE), terminal(TRUE)],
+            unique: HashMap::new(),
+            ite_cache: HashMap::new(),
+            exists_cache: HashMap::new(),
+            and_exists_cache: HashMap::new(),
+            max_nodes: MAX_NODES,
+        }
+    }
+
+    pub fn var(&mut self, var: u32) -> Result<Bdd> {
+        self.make(var, FALSE, TRUE)
+    }
+
+    /// Conjunction of positive variables, used to describe which variables to quantify.
+    pub fn cube(&mut self, vars: &[u32]) -> Result<Bdd> {
+        let mut vars = vars.to_vec();
+        vars.sort_unstable();
+        vars.dedup();
+        vars.iter()
+            .rev()
+            .try_fold(TRUE, |acc, var| self.make(*var, FALSE, acc))
+    }
+
+    fn level(&self, f: Bdd) -> u32 {
+        self.nodes[f.0 as usize].var
+    }
+
+    fn make(&mut self, var: u32, lo: Bdd, hi: Bdd) -> Result<Bdd> {
+        if var >= MAX_VARS {
+            return Err(Error::TooManyVariables);
+        }
+        if lo == hi {
+            return Ok(lo);
+        }
+        let node = Node { var, lo, hi };
+        if let Some(f) = self.unique.get(&node) {
+            return Ok(*f);
+        }
+        if self.nodes.len() >= self.max_nodes {
+            return Err(Error::OutOfNodes);
+        }
+        let f = Bdd(self.nodes.len() as u32);
+        self.nodes.push(node);
+        self.unique.insert(node, f);
+        Ok(f)
+    }
+
+    /// Negative and positive cofactor with respect to `var`, which is at or above `f`.
+    fn cofactors(&self, f: Bdd, var: u32) -> (Bdd, Bdd) {
+        let node = self.nodes[f.0 as usize];
+        if node.var == var {
+            (node.lo, node.hi)
+        } else {
+            (f, f)
+        }
+    }
+
+    fn clear_caches_if_full(&mut self) {
+        let size = self.ite_cache.len() + self.exists_cache.len() + self.and_exists_cache.len();
+        if size > MAX_CACHE {
+            self.ite_cache.clear();
+            self.exists_cache.clear();
+            self.and_exists_cache.clear();
+        }
+    }
+
+    pub fn ite(&mut self, f: Bdd, g: Bdd, h: Bdd) -> Result<Bdd> {
+        self.clear_caches_if_full();
+        self.ite_rec(f, g, h)
+    }
+
+    fn ite_rec(&mut self, f: Bdd, g: Bdd, h: Bdd) -> Result<Bdd> {
+        if f == TRUE || g == h {
+            return Ok(g);
+        }
+        if f == FALSE {
+            return Ok(h);
+        }
+        if g == TRUE && h == FALSE {
+            return Ok(f);
+        }
+        if let Some(r) = self.ite_cache.get(&(f, g, h)) {
+            return Ok(*r);
+        }
+        let var = self.level(f).min(self.level(g)).min(self.level(h));
+        let (f0, f1) = self.cofactors(f, var);
+        let (g0, g1) = self.cofactors(g, var);
+        let (h0, h1) = self.cofactors(h, var);
+        let lo = self.ite_rec(f0, g0, h0)?;
+        let hi = self.ite_rec(f1, g1, h1)?;
+        let r = self.make(var, lo, hi)?;
+        self.ite_cache.insert((f, g, h), r);
+        Ok(r)
+    }
+
+    pub fn not(&mut self, f: Bdd) -> Result<Bdd> {
+        self.ite(f, FALSE, TRUE)
+    }
+
+    pub fn and(&mut self, f: Bdd, g: Bdd) -> Result<Bdd> {
+        self.ite(f, g, FALSE)
+    }
+
+    pub fn or(&mut self, f: Bdd, g: Bdd) -> Result<Bdd> {
+        self.ite(f, TRUE, g)
+    }
+
+    pub fn xor(&mut self, f: Bdd, g: Bdd) -> Result<Bdd> {
+        let not_g = self.not(g)?;
+        self.ite(f, not_g, g)
+    }
+
+    /// Existentially quantifies all variables in `cube`.
+    pub fn exists(&mut self, f: Bdd, cube: Bdd) -> Result<Bdd> {
+        self.clear_caches_if_full();
+        self.exists_rec(f, cube)
+    }
+
+    fn exists_rec(&mut self, f: Bdd, mut cube: Bdd) -> Result<Bdd> {
+        let var = self.level(f);
+        while self.level(cube) < var {
+            cube = self.nodes[cube.0 as usize].hi;
+        }
+        if var == TERMINAL || cube == TRUE {
+            return Ok(f);
+        }
+        if let Some(r) = self.exists_cache.get(&(f, cube)) {
+            return Ok(*r);
+        }
+        let (f0, f1) = self.cofactors(f, var);
+        let r = if self.level(cube) == var {
+            let rest = self.nodes[cube.0 as usize].hi;
+            let lo = self.exists_rec(f0, rest)?;
+            if lo == TRUE {
+                TRUE
+            } else {
+                let hi = self.exists_rec(f1, rest)?;
+                self.ite_rec(lo, TRUE, hi)?
+            }
+        } else {
+            let lo = self.exists_rec(f0, cube)?;
+            let hi = self.exists_rec(f1, cube)?;
+            self.make(var, lo, hi)?
+        };
+        self.exists_cache.insert((f, cube), r);
+        Ok(r)
+    }
+
+    /// Computes `exists(and(f, g), cube)` without building the conjunction first.
+    pub fn and_exists(&mut self, f: Bdd, g: Bdd, cube: Bdd) -> Result<Bdd> {
+        self.clear_caches_if_full();
+        self.and_exists_rec(f, g, cube)
+    }
+
+    fn and_exists_rec(&mut self, f: Bdd, g: Bdd, mut cube: Bdd) -> Result<Bdd> {
+        if f == FALSE || g == FALSE {
+            return Ok(FALSE);
+        }
+        if f == TRUE || f == g {
+            return self.exists_rec(g, cube);
+        }
+        if g == TRUE {
+            return self.exists_rec(f, cube);
+        }
+        let (f, g) = (f.min(g), f.max(g));
+        let var = self.level(f).min(self.level(g));
+        while self.level(cube) < var {
+            cube = self.nodes[cube.0 as usize].hi;
+        }
+        if cube == TRUE {
+            return self.ite_rec(f, g, FALSE);
+        }
+        if let Some(r) = self.and_exists_cache.get(&(f, g, cube)) {
+            return Ok(*r);
+        }
+        let (f0, f1) = self.cofactors(f, var);
+        let (g0, g1) = self.cofactors(g, var);
+        let r = if self.level(cube) == var {
+            let rest = self.nodes[cube.0 as usize].hi;
+            let lo = self.and_exists_rec(f0, g0, rest)?;
+            if lo == TRUE {
+                TRUE
+            } else {
+                let hi = self.and_exists_rec(f1, g1, rest)?;
+                self.ite_rec(lo, TRUE, hi)?
+            }
+        } else {
+            let lo = self.and_exists_rec(f0, g0, cube)?;
+            let hi = self.and_exists_rec(f1, g1, cube)?;
+            self.make(var, lo, hi)?
+        };
+        self.and_exists_cache.insert((f, g, cube), r);
+        Ok(r)
+    }
+
+    /// Replaces every variable `v` in `f` with `rename[v]`.
+    pub fn rename(&mut self, f: Bdd, rename: &HashMap<u32, u32>) -> Result<Bdd> {
+        self.clear_caches_if_full();
+        let mut done = HashMap::new();
+        self.rename_rec(f, rename, &mut done)
+    }
+
+    fn rename_rec(
+        &mut self,
+        f: Bdd,
+        rename: &HashMap<u32, u32>,
+        done: &mut HashMap<Bdd, Bdd>,
+    ) -> Result<Bdd> {
+        let node = self.nodes[f.0 as usize];
+        if node.var == TERMINAL {
+            return Ok(f);
+        }
+        if let Some(r) = done.get(&f) {
+            return Ok(*r);
+        }
+        let lo = self.rename_rec(node.lo, rename, done)?;
+        let hi = self.rename_rec(node.hi, rename, done)?;
+        let var = rename.get(&node.var).copied().unwrap_or(node.var);
+        let var = self.var(var)?;
+        let r = self.ite_rec(var, hi, lo)?;
+        done.insert(f, r);
+        Ok(r)
+    }
+
+    /// Variables on a path to `TRUE`, all other variables can take any value.
+    pub fn pick(&self, f: Bdd) -> Option<Vec<(u32, bool)>> {
+        if f == FALSE {
+            return None;
+        }
+        let mut out = Vec::new();
+        let mut current = f;
+        while current != TRUE {
+            let node = self.nodes[current.0 as usize];
+            let value = node.lo == FALSE;
+            out.push((node.var, value));
+            current = if value { node.hi } else { node.lo };
+        }
+        Some(out)
+    }
+
+    pub fn eval(&self, f: Bdd, assignment: impl Fn(u32) -> bool) -> bool {
+        let mut current = f;
+        while self.level(current) != TERMINAL {
+            let node = self.nodes[current.0 as usize];
+            current = if assignment(node.var) {
+                node.hi
+            } else {
+                node.lo
+            };
+        }
+        current == TRUE
+    }
+
+    /// Variable and children of an inner node.
+    pub fn node(&self, f: Bdd) -> Option<(u32, Bdd, Bdd)> {
+        let node = self.nodes[f.0 as usize];
+        (node.var != TERMINAL).then_some((node.var, node.lo, node.hi))
+    }
+
+    pub fn support(&self, f: Bdd) -> Vec<u32> {
+        let mut todo = vec![f];
+        let mut visited = std::collections::HashSet::new();
+        let mut vars = Vec::new();
+        while let Some(f) = todo.pop() {
+            if let Some((var, lo, hi)) = self.node(f) {
+                if visited.insert(f) {
+                    vars.push(var);
+                    todo.push(lo);
+                    todo.push(hi);
+                }
+            }
+        }
+        vars.sort_unstable();
+        vars.dedup();
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    const NUM_VARS: u32 = 6;
+
+    /// Truth table over all assignments of `NUM_VARS` variables.
+    fn table(bdds: &Manager, f: Bdd) -> Vec<bool> {
+        (0..1u32 << NUM_VARS)
+            .map(|a| bdds.eval(f, |v| (a >> v) & 1 == 1))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_with_truth_tables() -> Result<()> {
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        let mut bdds = Manager::new();
+        let mut funcs = (0..NUM_VARS)
+            .map(|v| bdds.var(v))
+            .collect::<Result<Vec<_>>>()?;
+        for _ in 0..200 {
+            let a = funcs[rng.gen_range(0..funcs.len())];
+            let b = funcs[rng.gen_range(0..funcs.len())];
+            let (ta, tb) = (table(&bdds, a), table(&bdds, b));
+            let (f, expected): (Bdd, Vec<bool>) = match rng.gen_range(0..4) {
+                0 => (
+                    bdds.and(a, b)?,
+                    ta.iter().zip(&tb).map(|(a, b)| *a && *b).collect(),
+                ),
+                1 => (
+                    bdds.or(a, b)?,
+                    ta.iter().zip(&tb).map(|(a, b)| *a || *b).collect(),
+                ),
+                2 => (
+                    bdds.xor(a, b)?,
+                    ta.iter().zip(&tb).map(|(a, b)| a != b).collect(),
+                ),
+                _ => (bdds.not(a)?, ta.iter().map(|a| !a).collect()),
+            };
+            assert_eq!(table(&bdds, f), expected);
+            funcs.push(f);
+
+            // quantify two random variables
+            let vars = [rng.gen_range(0..NUM_VARS), rng.gen_range(0..NUM_VARS)];
+            let cube = bdds.cube(&vars)?;
+            let exists = |t: &[bool]| -> Vec<bool> {
+                (0..1usize << NUM_VARS)
+                    .map(|a| {
+                        (0..4).any(|q| {
+                            let mut a = a;
+                            for (ii, v) in vars.iter().enumerate() {
+                                a = (a & !(1 << v)) | (((q >> ii) & 1) << v);
+                            }
+                            t[a]
+                        })
+                    })
+                    .collect()
+            };
+            let quantified = bdds.exists(f, cube)?;
+            assert_eq!(table(&bdds, quantified), exists(&expected));
+            let product = bdds.and_exists(a, b, cube)?;
+            let both = ta
+                .iter()
+                .zip(&tb)
+                .map(|(a, b)| *a && *b)
+                .collect::<Vec<_>>();
+            assert_eq!(table(&bdds, product), exists(&both));
+
+            // every picked assignment satisfies the function
+            if let Some(assignment) = bdds.pick(f) {
+                let value = |v: u32| assignment.iter().any(|(var, val)| *var == v && *val);
+                assert!(bdds.eval(f, value));
+            } else {
+                assert!(expected.iter().all(|v| !v));
+            }
+        }
+
+        // swap two variables
+        let (x, y) = (bdds.var(0)?, bdds.var(1)?);
+        let not_y = bdds.not(y)?;
+        let f = bdds.and(x, not_y)?;
+        let swapped = bdds.rename(f, &HashMap::from([(0, 1), (1, 0)]))?;
+        let not_x = bdds.not(x)?;
+        assert_eq!(swapped, bdds.and(not_x, y)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut bdds = Manager::new();
+        assert_eq!(bdds.var(MAX_VARS), Err(Error::TooManyVariables));
+        // the parity of many variables needs one node per variable and value
+        bdds.max_nodes = 100;
+        let mut parity = Ok(FALSE);
+        for v in 0..64 {
+            parity = bdds.var(v).and_then(|x| bdds.xor(parity?, x));
+        }
+        assert_eq!(parity, Err(Error::OutOfNodes));
+        assert!(bdds.nodes.len() <= 100);
+    }
+}
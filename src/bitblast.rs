@@ -0,0 +1,594 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Translates bit-vector expressions into CNF. Gates are folded when an input is constant
+// and shared when the same gate is requested twice.
+
+use crate::sat::{Lit, Solver};
+use patronus::ir::*;
+use std::collections::HashMap;
+
+/// Bits of a bit-vector value, least significant bit first.
+pub type Bits = Vec<Lit>;
+
+/// Definition of a variable that was introduced for a gate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    And(Lit, Lit),
+    Xor(Lit, Lit),
+    Ite(Lit, Lit, Lit),
+}
+
+#[derive(Debug, Clone)]
+pub struct BitBlaster {
+    tru: Lit,
+    ands: HashMap<(Lit, Lit), Lit>,
+    xors: HashMap<(Lit, Lit), Lit>,
+    /// allows other engines to rebuild the circuit behind a literal
+    gates: HashMap<u32, Gate>,
+}
+
+impl BitBlaster {
+    pub fn new(solver: &mut Solver) -> Self {
+        let tru = solver.new_var();
+        solver.add_clause(&[tru]);
+        Self {
+            tru,
+            ands: HashMap::new(),
+            xors: HashMap::new(),
+            gates: HashMap::new(),
+        }
+    }
+
+    pub fn constant(&self, value: bool) -> Lit {
+        if value {
+            self.tru
+        } else {
+            !self.tru
+        }
+    }
+
+    pub fn const_value(&self, lit: Lit) -> Option<bool> {
+        (lit.var() == self.tru.var()).then_some(lit == self.tru)
+    }
+
+    /// Returns `None` for constants and fresh variables.
+    pub fn gate(&self, var: u32) -> Option<Gate> {
+        self.gates.get(&var).copied()
+    }
+
+    pub fn fresh(&self, solver: &mut Solver, width: WidthInt) -> Bits {
+        (0..width).map(|_| solver.new_var()).collect()
+    }
+
+    pub fn and(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        match (self.const_value(a), self.const_value(b)) {
+            (Some(false), _) | (_, Some(false)) => return self.constant(false),
+            (Some(true), _) => return b,
+            (_, Some(true)) => return a,
+            _ => {}
+        }
+        if a == b {
+            return a;
+        }
+        if a == !b {
+            return self.constant(false);
+        }
+        let key = (a.min(b), a.max(b));
+        if let Some(out) = self.ands.get(&key) {
+            return *out;
+        }
+        let out = solver.new_var();
+        solver.add_clause(&[!out, a]);
+        solver.add_clause(&[!out, b]);
+        solver.add_clause(&[out, !a, !b]);
+        self.ands.insert(key, out);
+        self.gates.insert(out.var(), Gate::And(a, b));
+        out
+    }
+
+    pub fn or(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        !self.and(solver, !a, !b)
+    }
+
+    pub fn xor(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        match (self.const_value(a), self.const_value(b)) {
+            (Some(a), _) => return if a { !b } else { b },
+            (_, Some(b)) => return if b { !a } else { a },
+            _ => {}
+        }
+        if a == b {
+            return self.constant(false);
+        }
+        if a == !b {
+            return self.constant(true);
+        }
+        // negations are pulled out of the gate: a ^ !b = !(a ^ b)
+        let negated = a.is_negated() ^ b.is_negated();
+        let (a, b) = (Lit::new(a.var(), false), Lit::new(b.var(), false));
+        let key = (a.min(b), a.max(b));
+        let out = match self.xors.get(&key) {
+            Some(out) => *out,
+            None => {
+                let out = solver.new_var();
+                solver.add_clause(&[!out, a, b]);
+                solver.add_clause(&[!out, !a, !b]);
+                solver.add_clause(&[out, !a, b]);
+                solver.add_clause(&[out, a, !b]);
+                self.xors.insert(key, out);
+                self.gates.insert(out.var(), Gate::Xor(a, b));
+                out
+            }
+        };
+        if negated {
+            !out
+        } else {
+            out
+        }
+    }
+
+    pub fn ite(&mut self, solver: &mut Solver, cond: Lit, tru: Lit, fals: Lit) -> Lit {
+        if let Some(c) = self.const_value(cond) {
+            return if c { tru } else { fals };
+        }
+        if tru == fals {
+            return tru;
+        }
+        match (self.const_value(tru), self.const_value(fals)) {
+            (Some(true), _) => return self.or(solver, cond, fals),
+            (Some(false), _) => return self.and(solver, !cond, fals),
+            (_, Some(true)) => return self.or(solver, !cond, tru),
+            (_, Some(false)) => return self.and(solver, cond, tru),
+            _ => {}
+        }
+        let out = solver.new_var();
+        solver.add_clause(&[!cond, !tru, out]);
+        solver.add_clause(&[!cond, tru, !out]);
+        solver.add_clause(&[cond, !fals, out]);
+        solver.add_clause(&[cond, fals, !out]);
+        self.gates.insert(out.var(), Gate::Ite(cond, tru, fals));
+        out
+    }
+
+    pub fn and_all(&mut self, solver: &mut Solver, lits: &[Lit]) -> Lit {
+        lits.iter()
+            .fold(self.constant(true), |acc, l| self.and(solver, acc, *l))
+    }
+
+    pub fn or_all(&mut self, solver: &mut Solver, lits: &[Lit]) -> Lit {
+        lits.iter()
+            .fold(self.constant(false), |acc, l| self.or(solver, acc, *l))
+    }
+
+    pub fn equal(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> Lit {
+        let same = a
+            .iter()
+            .zip(b.iter())
+            .map(|(a, b)| !self.xor(solver, *a, *b))
+            .collect::<Vec<_>>();
+        self.and_all(solver, &same)
+    }
+
+    /// Returns the sum and the carry out.
+    fn add(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit], carry_in: Lit) -> (Bits, Lit) {
+        let mut carry = carry_in;
+        let mut sum = Vec::with_capacity(a.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            let half = self.xor(solver, *a, *b);
+            sum.push(self.xor(solver, half, carry));
+            let generate = self.and(solver, *a, *b);
+            let propagate = self.and(solver, half, carry);
+            carry = self.or(solver, generate, propagate);
+        }
+        (sum, carry)
+    }
+
+    fn sub(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> Bits {
+        let not_b = b.iter().map(|b| !*b).collect::<Vec<_>>();
+        self.add(solver, a, &not_b, self.constant(true)).0
+    }
+
+    fn negate(&mut self, solver: &mut Solver, a: &[Lit]) -> Bits {
+        let zero = vec![self.constant(false); a.len()];
+        self.sub(solver, &zero, a)
+    }
+
+    /// Unsigned `a < b`, which holds iff `a - b` borrows.
+    fn less_than(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> Lit {
+        let not_b = b.iter().map(|b| !*b).collect::<Vec<_>>();
+        !self.add(solver, a, &not_b, self.constant(true)).1
+    }
+
+    /// Signed `a < b`, flipping the sign bits turns this into an unsigned comparison.
+    fn less_than_signed(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> Lit {
+        let flip = |bits: &[Lit]| {
+            let mut bits = bits.to_vec();
+            let msb = bits.len() - 1;
+            bits[msb] = !bits[msb];
+            bits
+        };
+        self.less_than(solver, &flip(a), &flip(b))
+    }
+
+    fn mul(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> Bits {
+        let width = a.len();
+        let mut acc = vec![self.constant(false); width];
+        for (ii, b) in b.iter().enumerate() {
+            let partial = a[..width - ii]
+                .iter()
+                .map(|a| self.and(solver, *a, *b))
+                .collect::<Vec<_>>();
+            let (sum, _) = self.add(solver, &acc[ii..], &partial, self.constant(false));
+            acc[ii..].copy_from_slice(&sum);
+        }
+        acc
+    }
+
+    /// Restoring division with the SMT-LIB semantics: `x / 0 = ~0` and `x % 0 = x`.
+    fn div_rem(&mut self, solver: &mut Solver, a: &[Lit], b: &[Lit]) -> (Bits, Bits) {
+        let width = a.len();
+        let mut quotient = vec![self.constant(false); width];
+        let mut remainder = vec![self.constant(false); width];
+        let mut divisor = b.to_vec();
+        divisor.push(self.constant(false));
+        for ii in (0..width).rev() {
+            let mut shifted = vec![a[ii]];
+            shifted.extend_from_slice(&remainder);
+            let fits = !self.less_than(solver, &shifted, &divisor);
+            let difference = self.sub(solver, &shifted, &divisor);
+            quotient[ii] = fits;
+            remainder = (0..width)
+                .map(|jj| self.ite(solver, fits, difference[jj], shifted[jj]))
+                .collect();
+        }
+        (quotient, remainder)
+    }
+
+    /// Absolute value and sign of a two's complement number.
+    fn abs(&mut self, solver: &mut Solver, a: &[Lit]) -> (Bits, Lit) {
+        let sign = *a.last().unwrap();
+        let negated = self.negate(solver, a);
+        (self.mux(solver, sign, &negated, a), sign)
+    }
+
+    fn mux(&mut self, solver: &mut Solver, cond: Lit, tru: &[Lit], fals: &[Lit]) -> Bits {
+        tru.iter()
+            .zip(fals.iter())
+            .map(|(t, f)| self.ite(solver, cond, *t, *f))
+            .collect()
+    }
+
+    fn shift(&mut self, solver: &mut Solver, a: &[Lit], by: &[Lit], kind: Shift) -> Bits {
+        let width = a.len();
+        let fill = match kind {
+            Shift::ArithmeticRight => *a.last().unwrap(),
+            _ => self.constant(false),
+        };
+        let mut out = a.to_vec();
+        let mut overflow = self.constant(false);
+        for (stage, bit) in by.iter().enumerate() {
+            let amount = 1usize.checked_shl(stage as u32).unwrap_or(usize::MAX);
+            if amount >= width {
+                overflow = self.or(solver, overflow, *bit);
+                continue;
+            }
+            let shifted = (0..width)
+                .map(|ii| match kind {
+                    Shift::Left => ii.checked_sub(amount).map(|src| out[src]).unwrap_or(fill),
+                    _ => out.get(ii + amount).copied().unwrap_or(fill),
+                })
+                .collect::<Vec<_>>();
+            out = self.mux(solver, *bit, &shifted, &out);
+        }
+        let filled = vec![fill; width];
+        self.mux(solver, overflow, &filled, &out)
+    }
+
+    /// Returns the bits of a bit-vector expression. Symbols that have no value yet are
+    /// assigned fresh variables.
+    pub fn blast(
+        &mut self,
+        ctx: &Context,
+        solver: &mut Solver,
+        values: &mut HashMap<ExprRef, Bits>,
+        expr: ExprRef,
+    ) -> Bits {
+        let mut todo = vec![(expr, false)];
+        while let Some((e, children_done)) = todo.pop() {
+            if values.contains_key(&e) {
+                continue;
+            }
+            if !children_done {
+                todo.push((e, true));
+                ctx.get(e).for_each_child(|c| {
+                    if !values.contains_key(c) {
+                        todo.push((*c, false));
+                    }
+                });
+                continue;
+            }
+            let bits = self.blast_node(ctx, solver, values, e);
+            values.insert(e, bits);
+        }
+        values[&expr].clone()
+    }
+
+    /// Converts a single node, all children need to be converted already.
+    fn blast_node(
+        &mut self,
+        ctx: &Context,
+        solver: &mut Solver,
+        values: &HashMap<ExprRef, Bits>,
+        e: ExprRef,
+    ) -> Bits {
+        let v = |e: &ExprRef| values[e].clone();
+        match ctx.get(e).clone() {
+            Expr::BVSymbol { width, .. } => self.fresh(solver, width),
+            Expr::BVLiteral { value, width } => (0..width)
+                .map(|ii| self.constant(ii < 64 && (value >> ii) & 1 == 1))
+                .collect(),
+            Expr::BVZeroExt { e, by, .. } => {
+                let mut bits = v(&e);
+                bits.extend(std::iter::repeat_n(self.constant(false), by as usize));
+                bits
+            }
+            Expr::BVSignExt { e, by, .. } => {
+                let mut bits = v(&e);
+                let msb = *bits.last().unwrap();
+                bits.extend(std::iter::repeat_n(msb, by as usize));
+                bits
+            }
+            Expr::BVSlice { e, hi, lo } => v(&e)[lo as usize..=hi as usize].to_vec(),
+            Expr::BVNot(e, _) => v(&e).into_iter().map(|b| !b).collect(),
+            Expr::BVNegate(e, _) => self.negate(solver, &v(&e)),
+            Expr::BVEqual(a, b) => vec![self.equal(solver, &v(&a), &v(&b))],
+            Expr::BVImplies(a, b) => vec![self.or(solver, !v(&a)[0], v(&b)[0])],
+            Expr::BVGreater(a, b) => vec![self.less_than(solver, &v(&b), &v(&a))],
+            Expr::BVGreaterSigned(a, b, _) => vec![self.less_than_signed(solver, &v(&b), &v(&a))],
+            Expr::BVGreaterEqual(a, b) => vec![!self.less_than(solver, &v(&a), &v(&b))],
+            Expr::BVGreaterEqualSigned(a, b, _) => {
+                vec![!self.less_than_signed(solver, &v(&a), &v(&b))]
+            }
+            Expr::BVConcat(a, b, _) => {
+                // `a` is the most significant part
+                let mut bits = v(&b);
+                bits.extend(v(&a));
+                bits
+            }
+            Expr::BVAnd(a, b, _) => self.bitwise(solver, &v(&a), &v(&b), Self::and),
+            Expr::BVOr(a, b, _) => self.bitwise(solver, &v(&a), &v(&b), Self::or),
+            Expr::BVXor(a, b, _) => self.bitwise(solver, &v(&a), &v(&b), Self::xor),
+            Expr::BVShiftLeft(a, b, _) => self.shift(solver, &v(&a), &v(&b), Shift::Left),
+            Expr::BVShiftRight(a, b, _) => self.shift(solver, &v(&a), &v(&b), Shift::Right),
+            Expr::BVArithmeticShiftRight(a, b, _) => {
+                self.shift(solver, &v(&a), &v(&b), Shift::ArithmeticRight)
+            }
+            Expr::BVAdd(a, b, _) => self.add(solver, &v(&a), &v(&b), self.constant(false)).0,
+            Expr::BVSub(a, b, _) => self.sub(solver, &v(&a), &v(&b)),
+            Expr::BVMul(a, b, _) => self.mul(solver, &v(&a), &v(&b)),
+            Expr::BVUnsignedDiv(a, b, _) => self.div_rem(solver, &v(&a), &v(&b)).0,
+            Expr::BVUnsignedRem(a, b, _) => self.div_rem(solver, &v(&a), &v(&b)).1,
+            Expr::BVSignedDiv(a, b, _) => {
+                let (a, a_neg) = self.abs(solver, &v(&a));
+                let (b, b_neg) = self.abs(solver, &v(&b));
+                let (quotient, _) = self.div_rem(solver, &a, &b);
+                let negated = self.negate(solver, &quotient);
+                let signs_differ = self.xor(solver, a_neg, b_neg);
+                self.mux(solver, signs_differ, &negated, &quotient)
+            }
+            Expr::BVSignedRem(a, b, _) => {
+                // the result takes the sign of the dividend
+                let (a, a_neg) = self.abs(solver, &v(&a));
+                let (b, _) = self.abs(solver, &v(&b));
+                let (_, remainder) = self.div_rem(solver, &a, &b);
+                let negated = self.negate(solver, &remainder);
+                self.mux(solver, a_neg, &negated, &remainder)
+            }
+            Expr::BVSignedMod(a, b, _) => {
+                // the result takes the sign of the divisor
+                let divisor = v(&b);
+                let (a, a_neg) = self.abs(solver, &v(&a));
+                let (b, b_neg) = self.abs(solver, &divisor);
+                let (_, remainder) = self.div_rem(solver, &a, &b);
+                let negated = self.negate(solver, &remainder);
+                let zero = vec![self.constant(false); remainder.len()];
+                let is_zero = self.equal(solver, &remainder, &zero);
+                let negated_plus_b = self.add(solver, &negated, &divisor, self.constant(false)).0;
+                let plus_b = self
+                    .add(solver, &remainder, &divisor, self.constant(false))
+                    .0;
+                let pos = self.mux(solver, b_neg, &plus_b, &remainder);
+                let neg = self.mux(solver, b_neg, &negated, &negated_plus_b);
+                let result = self.mux(solver, a_neg, &neg, &pos);
+                self.mux(solver, is_zero, &remainder, &result)
+            }
+            Expr::BVIte { cond, tru, fals } => {
+                let cond = v(&cond)[0];
+                self.mux(solver, cond, &v(&tru), &v(&fals))
+            }
+            other => panic!("arrays are not supported: {other:?}"),
+        }
+    }
+
+    fn bitwise(
+        &mut self,
+        solver: &mut Solver,
+        a: &[Lit],
+        b: &[Lit],
+        gate: fn(&mut Self, &mut Solver, Lit, Lit) -> Lit,
+    ) -> Bits {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| gate(self, solver, *a, *b))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shift {
+    Left,
+    Right,
+    ArithmeticRight,
+}
+
+/// Reads a value from the last satisfying assignment, in the word format of [`Value`].
+pub fn words(solver: &Solver, bits: &[Lit]) -> Vec<Word> {
+    bits.chunks(Word::BITS as usize)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |w, (ii, b)| w | ((solver.value(*b) as Word) << ii))
+        })
+        .collect()
+}
+
+/// Checks that a system only uses bit-vectors, since arrays are not supported.
+pub fn check_supported(ctx: &Context, sys: &TransitionSystem) -> Result<(), String> {
+    let mut todo = sys
+        .states()
+        .flat_map(|(_, s)| [Some(s.symbol), s.init, s.next])
+        .flatten()
+        .chain(sys.get_signals(|_| true).into_iter().map(|(e, _)| e))
+        .collect::<Vec<_>>();
+    let mut visited = std::collections::HashSet::new();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        let node = ctx.get(e);
+        if e.get_bv_type(ctx).is_none() || matches!(node, Expr::BVArrayRead { .. }) {
+            return Err("arrays are not supported".to_string());
+        }
+        node.for_each_child(|c| todo.push(*c));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::ir::value::mask;
+    use patronus::mc::Simulator;
+    use patronus::sim::interpreter::Interpreter;
+    use rand::{Rng, SeedableRng};
+
+    /// Evaluates an expression over two 8-bit symbols by fixing their bits.
+    fn eval(ctx: &Context, expr: ExprRef, a: ExprRef, b: ExprRef, values: (u64, u64)) -> u64 {
+        let mut solver = Solver::new();
+        let mut blaster = BitBlaster::new(&mut solver);
+        let mut cache = HashMap::new();
+        for (symbol, value) in [(a, values.0), (b, values.1)] {
+            let bits = (0..8)
+                .map(|ii| blaster.constant((value >> ii) & 1 == 1))
+                .collect();
+            cache.insert(symbol, bits);
+        }
+        let bits = blaster.blast(ctx, &mut solver, &mut cache, expr);
+        assert!(solver.solve(&[]));
+        words(&solver, &bits)[0]
+    }
+
+    #[test]
+    fn test_compare_with_interpreter() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let small = ctx.bv_lit(3, 8);
+        let b_small = ctx.and(b, small);
+        let exprs = [
+            ctx.add(a, b),
+            ctx.sub(a, b),
+            ctx.mul(a, b),
+            ctx.shift_left(a, b_small),
+            ctx.shift_left(a, b),
+            ctx.shift_right(a, b),
+            ctx.greater(a, b),
+            ctx.greater_signed(a, b),
+            ctx.greater_or_equal(a, b),
+            ctx.greater_or_equal_signed(a, b),
+            ctx.bv_equal(a, b),
+            ctx.concat(a, b),
+            ctx.slice(a, 6, 2),
+            ctx.negate(a),
+            ctx.xor(a, b),
+        ];
+        for e in exprs {
+            sys.add_signal(e, SignalKind::Node, SignalLabels::output(), None);
+        }
+        let mut sim = Interpreter::new(&ctx, &sys);
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        for _ in 0..20 {
+            let values = (rng.gen::<u8>() as u64, rng.gen::<u8>() as u64);
+            sim.set(a, ValueRef::new(&[values.0], 8));
+            sim.set(b, ValueRef::new(&[values.1], 8));
+            sim.update();
+            for e in exprs {
+                let expected = sim.get(e).unwrap().to_u64().unwrap();
+                let actual = eval(&ctx, e, a, b, values);
+                assert_eq!(
+                    actual,
+                    expected,
+                    "{} with {values:?}",
+                    ctx.get(e).serialize_to_str(&ctx)
+                );
+            }
+        }
+    }
+
+    /// The interpreter does not support division or arithmetic shifts and gets sign extension wrong.
+    #[test]
+    fn test_compare_with_rust() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let ops = [
+            ctx.div(a, b),
+            ctx.remainder(a, b),
+            ctx.signed_div(a, b),
+            ctx.signed_remainder(a, b),
+            ctx.signed_mod(a, b),
+            ctx.arithmetic_shift_right(a, b),
+        ];
+        let sign_ext = ctx.sign_extend(a, 3);
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        for ii in 0..40 {
+            let x = rng.gen::<u8>();
+            let y = match ii {
+                0..4 => 0,
+                4..12 => ii - 4,
+                _ => rng.gen::<u8>(),
+            };
+            let (sx, sy) = (x as i8, y as i8);
+            let expected = match x.checked_div(y) {
+                None => [0xff, x, if sx < 0 { 1 } else { 0xff }, x, x, x],
+                Some(quotient) => {
+                    let srem = sx.wrapping_rem(sy);
+                    let smod = if srem != 0 && (srem < 0) != (sy < 0) {
+                        srem.wrapping_add(sy)
+                    } else {
+                        srem
+                    };
+                    [
+                        quotient,
+                        x % y,
+                        sx.wrapping_div(sy) as u8,
+                        srem as u8,
+                        smod as u8,
+                        (sx >> y.min(7)) as u8,
+                    ]
+                }
+            };
+            for (op, expected) in ops.iter().zip(expected) {
+                let actual = eval(&ctx, *op, a, b, (x as u64, y as u64));
+                assert_eq!(actual, expected as u64 & mask(8), "{x} {y} {op:?}");
+            }
+            let actual = eval(&ctx, sign_ext, a, b, (x as u64, y as u64));
+            assert_eq!(actual, sx as u64 & mask(11));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Bounded model checking: unrolls the system one step at a time and asks the SAT solver
+// whether a bad state can be reached in the newest step.
+
+use crate::sat::Solver;
+use crate::unroll::Unroller;
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::*;
+
+/// Incremental search for counterexamples of increasing length.
+pub struct Bmc {
+    solver: Solver,
+    unroller: Unroller,
+}
+
+impl Bmc {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Result<Self, String> {
+        let mut solver = Solver::new();
+        let unroller = Unroller::new(ctx, sys, &mut solver, true)?;
+        Ok(Self { solver, unroller })
+    }
+
+    /// Checks for a counterexample of exactly `k` steps, `k` needs to increase by one with
+    /// every call.
+    pub fn check(&mut self, ctx: &Context, k: usize) -> Option<Witness> {
+        let solver = &mut self.solver;
+        self.unroller.add_frame(ctx, solver);
+        let bad = self.unroller.any_bad(ctx, solver, k);
+        if solver.solve(&[bad]) {
+            return Some(self.unroller.witness(ctx, solver, k));
+        }
+        // no need to look at this frame again
+        solver.add_clause(&[!bad]);
+        None
+    }
+}
+
+/// Searches for a counterexample of up to `max_k` steps. Returns `Unknown` if there is none.
+pub fn bmc(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    max_k: StepInt,
+    verbose: bool,
+) -> ModelCheckResult {
+    if sys.bad_states().is_empty() {
+        return ModelCheckResult::Unknown;
+    }
+    let mut bmc = match Bmc::new(ctx, sys) {
+        Ok(bmc) => bmc,
+        Err(e) => {
+            println!("BMC: {e}");
+            return ModelCheckResult::Unknown;
+        }
+    };
+    for k in 0..=max_k as usize {
+        if let Some(wit) = bmc.check(ctx, k) {
+            return ModelCheckResult::Sat(wit);
+        }
+    }
+    if verbose {
+        println!("BMC: no counterexample up to k={max_k}");
+    }
+    ModelCheckResult::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::mc::Simulator;
+    use patronus::sim::interpreter::{InitKind, Interpreter};
+
+    #[test]
+    fn test_bmc_counter() {
+        // counter that only increments when enabled, starts at 3 and is bad once it reaches 7
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let counter = ctx.bv_symbol("counter", 4);
+        let state = sys.add_state(&ctx, counter);
+        let one = ctx.one(4);
+        let inc = ctx.add(counter, one);
+        let next = ctx.bv_ite(en, inc, counter);
+        let three = ctx.bv_lit(3, 4);
+        sys.modify_state(state, |s| {
+            s.next = Some(next);
+            s.init = Some(three);
+        });
+        let seven = ctx.bv_lit(7, 4);
+        let bad = ctx.bv_equal(counter, seven);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        assert!(matches!(
+            bmc(&ctx, &sys, 3, false),
+            ModelCheckResult::Unknown
+        ));
+        let ModelCheckResult::Sat(wit) = bmc(&ctx, &sys, 10, false) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.k, 4);
+        assert_eq!(wit.state_init, [3]);
+        assert_eq!(wit.failed_safety, [0]);
+
+        // replay the witness
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        sim.set(counter, ValueRef::new(&wit.state_init, 4));
+        for k in 0..=wit.k as usize {
+            sim.set(en, ValueRef::new(&wit.input_data[k..k + 1], 1));
+            sim.update();
+            let is_bad = sim.get(bad).unwrap().to_u64().unwrap() == 1;
+            assert_eq!(is_bad, k == 4);
+            sim.step();
+        }
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Proof certificates that can be checked independently of the engine that produced them.
+
+use crate::sat::{Lit, Solver};
+use crate::unroll::Unroller;
+use crate::StepInt;
+use patronus::btor2;
+use patronus::ir::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const HEADER: &str = "; patron certificate:";
+
+/// Evidence that no bad state is reachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Certificate {
+    /// The system is k-inductive for this depth.
+    KInduction(StepInt),
+    /// BTOR2 system with one 1-bit output over the states that is inductive and
+    /// excludes all bad states.
+    Invariant(String),
+}
+
+impl Certificate {
+    pub fn invariant(ctx: &Context, sys: &TransitionSystem, invariant: ExprRef) -> Self {
+        let mut inv_sys = TransitionSystem::new("invariant".to_string());
+        for (_, state) in sys.states() {
+            inv_sys.add_state(ctx, state.symbol);
+        }
+        inv_sys.add_signal(invariant, SignalKind::Node, SignalLabels::output(), None);
+        Self::Invariant(serialize(ctx, &inv_sys))
+    }
+
+    pub fn serialize(&self) -> String {
+        match self {
+            Certificate::KInduction(k) => format!("{HEADER} k-induction {k}\n"),
+            Certificate::Invariant(btor) => format!("{HEADER} invariant\n{btor}"),
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let (first, rest) = src.split_once('\n').unwrap_or((src, ""));
+        let Some(kind) = first.strip_prefix(HEADER) else {
+            return Err("not a patron certificate".to_string());
+        };
+        match kind.split_whitespace().collect::<Vec<_>>()[..] {
+            ["k-induction", k] => k
+                .parse()
+                .map(Certificate::KInduction)
+                .map_err(|_| format!("invalid k-induction depth `{k}`")),
+            ["invariant"] => Ok(Certificate::Invariant(rest.to_string())),
+            _ => Err(format!("unknown certificate kind `{}`", kind.trim())),
+        }
+    }
+}
+
+/// Checks initiation, consecution and safety of an invariant, or the base case and inductive
+/// step of k-induction at the claimed depth.
+pub fn check_certificate(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    cert: &Certificate,
+) -> Result<(), String> {
+    match cert {
+        Certificate::KInduction(k) => check_k_induction(ctx, sys, *k as usize),
+        Certificate::Invariant(btor) => check_invariant(ctx, sys, btor),
+    }
+}
+
+fn check_k_induction(ctx: &Context, sys: &TransitionSystem, k: usize) -> Result<(), String> {
+    // base case: no bad state within the first k steps
+    let mut solver = Solver::new();
+    let mut base = Unroller::new(ctx, sys, &mut solver, true)?;
+    for ii in 0..=k {
+        base.add_frame(ctx, &mut solver);
+        let bad = base.any_bad(ctx, &mut solver, ii);
+        if solver.solve(&[bad]) {
+            return Err("a bad state is reachable".to_string());
+        }
+    }
+
+    // inductive step: a loop-free path of k good states cannot be followed by a bad state
+    let mut solver = Solver::new();
+    let mut step = Unroller::new(ctx, sys, &mut solver, false)?;
+    for ii in 0..=k {
+        step.add_frame(ctx, &mut solver);
+        for other in 0..ii {
+            let same = step.same_states(&mut solver, other, ii);
+            solver.add_clause(&[!same]);
+        }
+    }
+    for ii in 0..k {
+        let bad = step.any_bad(ctx, &mut solver, ii);
+        solver.add_clause(&[!bad]);
+    }
+    let bad = step.any_bad(ctx, &mut solver, k);
+    if solver.solve(&[bad]) {
+        return Err(format!("the system is not {k}-inductive"));
+    }
+    Ok(())
+}
+
+fn check_invariant(ctx: &Context, sys: &TransitionSystem, btor: &str) -> Result<(), String> {
+    // the invariant lives in its own context and is connected to the design by state names
+    let mut inv_ctx = Context::default();
+    let inv_sys = btor2::parse_str(&mut inv_ctx, btor, Some("invariant"))
+        .ok_or("failed to parse the invariant")?;
+    let [(invariant, _)] = inv_sys.get_signals(|s| s.labels.is_output())[..] else {
+        return Err("expected exactly one output with the invariant".to_string());
+    };
+    if invariant.get_bv_type(&inv_ctx) != Some(1) {
+        return Err("the invariant needs to be a 1-bit expression".to_string());
+    }
+    let design = sys.states().map(|(_, s)| s.symbol).collect::<Vec<_>>();
+    // the parser turns states without a next or init into inputs
+    let symbols = inv_sys.states().map(|(_, s)| s.symbol).chain(
+        inv_sys
+            .get_signals(|s| s.is_input())
+            .into_iter()
+            .map(|(e, _)| e),
+    );
+    let mut states = Vec::new();
+    for symbol in symbols {
+        let name = symbol.get_symbol_name(&inv_ctx).unwrap();
+        let ii = design
+            .iter()
+            .position(|s| s.get_symbol_name(ctx) == Some(name))
+            .ok_or_else(|| format!("unknown state `{name}`"))?;
+        if symbol.get_type(&inv_ctx) != design[ii].get_type(ctx) {
+            return Err(format!("state `{name}` has a different type in the design"));
+        }
+        states.push((symbol, ii));
+    }
+    let holds = |unroller: &mut Unroller, solver: &mut Solver, k: usize| -> Lit {
+        let mut bits = states
+            .iter()
+            .map(|(symbol, ii)| (*symbol, unroller.state(k, *ii).to_vec()))
+            .collect::<HashMap<_, _>>();
+        unroller
+            .blaster
+            .blast(&inv_ctx, solver, &mut bits, invariant)[0]
+    };
+
+    // initiation: all initial states are included
+    let mut solver = Solver::new();
+    let mut unroller = Unroller::new(ctx, sys, &mut solver, true)?;
+    unroller.add_frame(ctx, &mut solver);
+    let inv = holds(&mut unroller, &mut solver, 0);
+    if solver.solve(&[!inv]) {
+        return Err("the invariant does not hold in all initial states".to_string());
+    }
+
+    // safety: no bad state is included
+    let mut solver = Solver::new();
+    let mut unroller = Unroller::new(ctx, sys, &mut solver, false)?;
+    unroller.add_frame(ctx, &mut solver);
+    let inv = holds(&mut unroller, &mut solver, 0);
+    solver.add_clause(&[inv]);
+    let bad = unroller.any_bad(ctx, &mut solver, 0);
+    if solver.solve(&[bad]) {
+        return Err("the invariant includes a bad state".to_string());
+    }
+
+    // consecution: every successor of an included state is included
+    unroller.add_frame(ctx, &mut solver);
+    let inv = holds(&mut unroller, &mut solver, 1);
+    if solver.solve(&[!inv]) {
+        return Err("the invariant is not inductive".to_string());
+    }
+    Ok(())
+}
+
+/// Serializes a system to BTOR2. `btor2::serialize_to_str` in `patronus` 0.18.5 only writes a
+/// header comment, thus the body is written by us for as long as it is missing.
+fn serialize(ctx: &Context, sys: &TransitionSystem) -> String {
+    let mut out = btor2::serialize_to_str(ctx, sys);
+    if out.lines().all(|line| line.starts_with(';')) {
+        out.push_str(&Btor2Writer::system(ctx, sys));
+    }
+    out
+}
+
+/// Writes the states, inputs, outputs and bad states of a system as BTOR2 lines.
+#[derive(Default)]
+struct Btor2Writer {
+    out: String,
+    count: usize,
+    sorts: HashMap<WidthInt, usize>,
+    ids: HashMap<ExprRef, usize>,
+}
+
+impl Btor2Writer {
+    fn system(ctx: &Context, sys: &TransitionSystem) -> String {
+        let mut out = Self::default();
+        for (input, _) in sys.get_signals(|s| s.is_input()) {
+            out.symbol(ctx, input, "input");
+        }
+        for (_, state) in sys.states() {
+            out.symbol(ctx, state.symbol, "state");
+        }
+        for (_, state) in sys.states() {
+            let sort = out.sort(state.symbol.get_bv_type(ctx).unwrap());
+            let id = out.ids[&state.symbol];
+            if let Some(init) = state.init {
+                let init = out.expr(ctx, init);
+                out.line(format!("init {sort} {id} {init}"));
+            }
+            if let Some(next) = state.next {
+                let next = out.expr(ctx, next);
+                out.line(format!("next {sort} {id} {next}"));
+            }
+        }
+        for (e, info) in sys.get_signals(|s| s.labels.is_output() || s.labels.is_bad()) {
+            let id = out.expr(ctx, e);
+            let name = info
+                .name
+                .map(|n| format!(" {}", ctx.get(n)))
+                .unwrap_or_default();
+            let kind = if info.labels.is_bad() {
+                "bad"
+            } else {
+                "output"
+            };
+            out.line(format!("{kind} {id}{name}"));
+        }
+        out.out
+    }
+
+    fn symbol(&mut self, ctx: &Context, symbol: ExprRef, kind: &str) {
+        let sort = self.sort(symbol.get_bv_type(ctx).unwrap());
+        let name = symbol.get_symbol_name(ctx).unwrap();
+        let id = self.line(format!("{kind} {sort} {name}"));
+        self.ids.insert(symbol, id);
+    }
+
+    fn line(&mut self, line: String) -> usize {
+        self.count += 1;
+        writeln!(self.out, "{} {line}", self.count).unwrap();
+        self.count
+    }
+
+    fn sort(&mut self, width: WidthInt) -> usize {
+        if let Some(id) = self.sorts.get(&width) {
+            return *id;
+        }
+        let id = self.line(format!("sort bitvec {width}"));
+        self.sorts.insert(width, id);
+        id
+    }
+
+    fn expr(&mut self, ctx: &Context, root: ExprRef) -> usize {
+        let mut todo = vec![(root, false)];
+        while let Some((e, children_done)) = todo.pop() {
+            if self.ids.contains_key(&e) {
+                continue;
+            }
+            if !children_done {
+                todo.push((e, true));
+                ctx.get(e).for_each_child(|c| todo.push((*c, false)));
+                continue;
+            }
+            let sort = self.sort(e.get_bv_type(ctx).unwrap());
+            let line = match ctx.get(e) {
+                Expr::BVSymbol { .. } => {
+                    panic!("undeclared symbol {}", e.get_symbol_name(ctx).unwrap())
+                }
+                Expr::BVLiteral { value, width } => {
+                    format!("const {sort} {value:0w$b}", w = *width as usize)
+                }
+                Expr::BVZeroExt { e, by, .. } => format!("uext {sort} {} {by}", self.ids[e]),
+                Expr::BVSignExt { e, by, .. } => format!("sext {sort} {} {by}", self.ids[e]),
+                Expr::BVSlice { e, hi, lo } => format!("slice {sort} {} {hi} {lo}", self.ids[e]),
+                Expr::BVNot(e, _) => format!("not {sort} {}", self.ids[e]),
+                Expr::BVNegate(e, _) => format!("neg {sort} {}", self.ids[e]),
+                Expr::BVIte { cond, tru, fals } => format!(
+                    "ite {sort} {} {} {}",
+                    self.ids[cond], self.ids[tru], self.ids[fals]
+                ),
+                other => {
+                    let (op, a, b) = match other {
+                        Expr::BVEqual(a, b) => ("eq", a, b),
+                        Expr::BVImplies(a, b) => ("implies", a, b),
+                        Expr::BVGreater(a, b) => ("ugt", a, b),
+                        Expr::BVGreaterSigned(a, b, _) => ("sgt", a, b),
+                        Expr::BVGreaterEqual(a, b) => ("ugte", a, b),
+                        Expr::BVGreaterEqualSigned(a, b, _) => ("sgte", a, b),
+                        Expr::BVConcat(a, b, _) => ("concat", a, b),
+                        Expr::BVAnd(a, b, _) => ("and", a, b),
+                        Expr::BVOr(a, b, _) => ("or", a, b),
+                        Expr::BVXor(a, b, _) => ("xor", a, b),
+                        Expr::BVShiftLeft(a, b, _) => ("sll", a, b),
+                        Expr::BVArithmeticShiftRight(a, b, _) => ("sra", a, b),
+                        Expr::BVShiftRight(a, b, _) => ("srl", a, b),
+                        Expr::BVAdd(a, b, _) => ("add", a, b),
+                        Expr::BVMul(a, b, _) => ("mul", a, b),
+                        Expr::BVSignedDiv(a, b, _) => ("sdiv", a, b),
+                        Expr::BVUnsignedDiv(a, b, _) => ("udiv", a, b),
+                        Expr::BVSignedMod(a, b, _) => ("smod", a, b),
+                        Expr::BVSignedRem(a, b, _) => ("srem", a, b),
+                        Expr::BVUnsignedRem(a, b, _) => ("urem", a, b),
+                        Expr::BVSub(a, b, _) => ("sub", a, b),
+                        _ => panic!("arrays are not supported"),
+                    };
+                    format!("{op} {sort} {} {}", self.ids[a], self.ids[b])
+                }
+            };
+            let id = self.line(line);
+            self.ids.insert(e, id);
+        }
+        self.ids[&root]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ic3::ic3;
+    use crate::kind::k_induction;
+    use crate::ModelCheckResult;
+
+    /// Counter modulo 3 that starts at zero, bad once it reaches `bad`.
+    fn counter(ctx: &mut Context, bad: u64) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("test".to_string());
+        let count = ctx.bv_symbol("count", 2);
+        let state = sys.add_state(ctx, count);
+        let one = ctx.one(2);
+        let inc = ctx.add(count, one);
+        let two = ctx.bv_lit(2, 2);
+        let wrap = ctx.bv_equal(count, two);
+        let zero = ctx.zero(2);
+        let next = ctx.bv_ite(wrap, zero, inc);
+        sys.modify_state(state, |s| {
+            s.next = Some(next);
+            s.init = Some(zero);
+        });
+        let bad = ctx.bv_lit(bad, 2);
+        let bad = ctx.bv_equal(count, bad);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+        sys
+    }
+
+    #[test]
+    fn test_check_certificates() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx, 3);
+        for result in [ic3(&ctx, &sys), k_induction(&ctx, &sys, 5, false)] {
+            let ModelCheckResult::UnSat(Some(cert)) = result else {
+                panic!("expected a proof");
+            };
+            let cert = Certificate::parse(&cert.serialize()).unwrap();
+            check_certificate(&ctx, &sys, &cert).unwrap();
+        }
+
+        // the bad state 3 is not reachable from any other state, but it is a state
+        assert_eq!(
+            check_certificate(&ctx, &sys, &Certificate::KInduction(0)),
+            Err("the system is not 0-inductive".to_string())
+        );
+        check_certificate(&ctx, &sys, &Certificate::KInduction(1)).unwrap();
+
+        // `count != 3` is safe and inductive, `count == 0` is not inductive
+        let count = ctx.bv_symbol("count", 2);
+        let three = ctx.bv_lit(3, 2);
+        let is_three = ctx.bv_equal(count, three);
+        let not_three = ctx.not(is_three);
+        let safe = Certificate::invariant(&ctx, &sys, not_three);
+        check_certificate(&ctx, &sys, &safe).unwrap();
+        let zero = ctx.zero(2);
+        let is_zero = ctx.bv_equal(count, zero);
+        let cert = Certificate::invariant(&ctx, &sys, is_zero);
+        assert_eq!(
+            check_certificate(&ctx, &sys, &cert),
+            Err("the invariant is not inductive".to_string())
+        );
+
+        // the certificates do not carry over to a system in which the bad state is reachable
+        let sys = counter(&mut ctx, 2);
+        assert_eq!(
+            check_certificate(&ctx, &sys, &safe),
+            Err("the invariant includes a bad state".to_string())
+        );
+        assert_eq!(
+            check_certificate(&ctx, &sys, &Certificate::KInduction(5)),
+            Err("a bad state is reachable".to_string())
+        );
+        assert!(Certificate::parse("; patron certificate: magic").is_err());
+    }
+
+    #[test]
+    fn test_anonymous_input() {
+        // an anonymous input drives the bad state, thus no certificate may be accepted
+        let mut ctx = Context::default();
+        let sys =
+            btor2::parse_str(&mut ctx, "1 sort bitvec 1\n2 input 1\n3 bad 2\n", None).unwrap();
+        assert_eq!(
+            check_certificate(&ctx, &sys, &Certificate::KInduction(0)),
+            Err("a bad state is reachable".to_string())
+        );
+        let tru = ctx.one(1);
+        let cert = Certificate::invariant(&ctx, &sys, tru);
+        assert_eq!(
+            check_certificate(&ctx, &sys, &cert),
+            Err("the invariant includes a bad state".to_string())
+        );
+    }
+}
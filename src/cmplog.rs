@@ -0,0 +1,195 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Comparison operand logging: we record the values that input dependent comparisons
+// are evaluated against and feed them back into the inputs that influence the comparison.
+
+use crate::random::make_observable;
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::Interpreter;
+use rand::Rng;
+use smallvec::SmallVec;
+use std::collections::HashMap;
+
+/// Upper bound on the number of comparisons that we observe in every cycle.
+const MAX_COMPARISONS: usize = 256;
+/// Number of observed values that we remember for every input.
+const MAX_VALUES_PER_INPUT: usize = 64;
+
+type InputVec = SmallVec<[ExprRef; 2]>;
+
+#[derive(Debug, Clone)]
+struct Comparison {
+    operands: [ExprRef; 2],
+    /// Inputs that influence `operands[ii]` and thus want to take on the value of the other operand.
+    targets: [InputVec; 2],
+}
+
+/// Comparisons in the design that depend on inputs.
+#[derive(Debug, Clone, Default)]
+pub struct CmpLog {
+    comparisons: Vec<Comparison>,
+}
+
+impl CmpLog {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let states = sys.state_map();
+        let mut todo = sys
+            .get_signals(|s| !s.labels.is_none())
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        for (_, state) in sys.states() {
+            todo.extend(state.next);
+        }
+        let mut visited = ExprMetaData::default();
+        let mut comparisons = Vec::new();
+        while let Some(e) = todo.pop() {
+            if *visited.get(e) {
+                continue;
+            }
+            *visited.get_mut(e) = true;
+            ctx.get(e).for_each_child(|c| todo.push(*c));
+
+            let operands = match *ctx.get(e) {
+                Expr::BVEqual(a, b)
+                | Expr::BVGreater(a, b)
+                | Expr::BVGreaterEqual(a, b)
+                | Expr::BVGreaterSigned(a, b, _)
+                | Expr::BVGreaterEqualSigned(a, b, _) => [a, b],
+                _ => continue,
+            };
+            let targets = operands.map(|op| {
+                cone_of_influence_comb(ctx, sys, op)
+                    .into_iter()
+                    .filter(|i| !states.contains_key(i))
+                    .filter(|i| i.get_bv_type(ctx).map(|w| w <= 64).unwrap_or(false))
+                    .collect::<InputVec>()
+            });
+            let is_literal = operands.map(|op| op.is_bv_lit(ctx));
+            // constants are already covered by the dictionary
+            let has_target = (!targets[0].is_empty() && !is_literal[1])
+                || (!targets[1].is_empty() && !is_literal[0]);
+            let is_bv = operands[0]
+                .get_bv_type(ctx)
+                .map(|w| w <= 64)
+                .unwrap_or(false);
+            if has_target && is_bv {
+                comparisons.push(Comparison { operands, targets });
+            }
+            if comparisons.len() >= MAX_COMPARISONS {
+                break;
+            }
+        }
+        comparisons.sort_by_key(|c| c.operands);
+        Self { comparisons }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comparisons.is_empty()
+    }
+
+    /// Makes sure that all comparison operands can be read from the simulator.
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        for cmp in self.comparisons.iter() {
+            for op in cmp.operands {
+                make_observable(ctx, sys, op);
+            }
+        }
+    }
+
+    /// Records the operands of all comparisons in the current cycle.
+    pub fn observe(&self, sim: &Interpreter, obs: &mut Observations) {
+        for cmp in self.comparisons.iter() {
+            for (targets, other) in cmp.targets.iter().zip(cmp.operands.iter().rev()) {
+                if targets.is_empty() {
+                    continue;
+                }
+                let Some(value) = sim.get(*other).and_then(|v| v.to_u64()) else {
+                    continue;
+                };
+                for input in targets.iter() {
+                    obs.add(*input, value);
+                    // counters tend to move on by the time we see the value again
+                    obs.add(*input, value.wrapping_add(1));
+                }
+            }
+        }
+    }
+}
+
+/// Operand values observed for each input.
+#[derive(Debug, Clone, Default)]
+pub struct Observations {
+    values: HashMap<ExprRef, Pool>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Pool {
+    values: Vec<u64>,
+    /// position to overwrite once the pool is full
+    next: usize,
+}
+
+impl Observations {
+    fn add(&mut self, input: ExprRef, value: u64) {
+        let pool = self.values.entry(input).or_default();
+        if pool.values.contains(&value) {
+            return;
+        }
+        if pool.values.len() < MAX_VALUES_PER_INPUT {
+            pool.values.push(value);
+        } else {
+            pool.values[pool.next] = value;
+            pool.next = (pool.next + 1) % MAX_VALUES_PER_INPUT;
+        }
+    }
+
+    pub fn has_values(&self, input: ExprRef) -> bool {
+        self.values.contains_key(&input)
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng, input: ExprRef, width: WidthInt) -> Option<u64> {
+        let pool = self.values.get(&input)?;
+        Some(pool.values[rng.gen_range(0..pool.values.len())] & mask(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::sim::interpreter::InitKind;
+
+    #[test]
+    fn test_observe_state_comparison() {
+        // input == counter
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let input = ctx.bv_symbol("in", 8);
+        sys.add_input(&ctx, input);
+        let counter = ctx.bv_symbol("counter", 8);
+        let state = sys.add_state(&ctx, counter);
+        let one = ctx.one(8);
+        let next = ctx.add(counter, one);
+        sys.modify_state(state, |s| s.next = Some(next));
+        let eq = ctx.bv_equal(input, counter);
+        sys.add_signal(eq, SignalKind::Node, SignalLabels::bad(), None);
+
+        let cmplog = CmpLog::new(&ctx, &sys);
+        assert!(!cmplog.is_empty());
+        cmplog.instrument(&ctx, &mut sys);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut obs = Observations::default();
+        for _ in 0..3 {
+            sim.update();
+            cmplog.observe(&sim, &mut obs);
+            sim.step();
+        }
+        assert_eq!(obs.values[&input].values, [0, 1, 2, 3]);
+        assert!(!obs.has_values(counter));
+    }
+}
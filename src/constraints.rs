@@ -51,7 +51,7 @@ pub fn analyze_constraints(
     init: bool,
 ) -> Vec<ConstraintCluster> {
     use petgraph::visit::NodeIndexable;
-    let graph = extract_constraint_graph(ctx, sys, init);
+    let (graph, fixed) = extract_constraint_graph(ctx, sys, init);
 
     // extract connected components from graph
     let groups = connected_components(&graph);
@@ -76,18 +76,25 @@ pub fn analyze_constraints(
         out.push(ConstraintCluster::new(exprs, states, inputs));
     }
 
+    // constraints without any leaves can only be checked, not fulfilled
+    if !fixed.is_empty() {
+        out.push(ConstraintCluster::new(fixed, smallvec![], smallvec![]));
+    }
+
     out
 }
 
 type ConstraintGraph = petgraph::Graph<ExprRef, ExprRef, petgraph::Undirected>;
 
+/// Returns the graph and all constraints without any leaves.
 fn extract_constraint_graph(
     ctx: &mut Context,
     sys: &TransitionSystem,
     init: bool,
-) -> ConstraintGraph {
+) -> (ConstraintGraph, ExprRefVec) {
     let state_map = sys.state_map();
     let mut out = petgraph::Graph::new_undirected();
+    let mut fixed = smallvec![];
     let mut var_to_node = HashMap::new();
 
     // we want to see which constraints depends on which inputs
@@ -112,6 +119,13 @@ fn extract_constraint_graph(
             // constraints connect all their leaves together
             leaves.sort();
             leaves.dedup();
+            if leaves.is_empty() {
+                // trivially fulfilled constraints need no checking
+                if !matches!(ctx.get(expr_ref), Expr::BVLiteral { value: 1, .. }) {
+                    fixed.push(expr_ref);
+                }
+                continue;
+            }
 
             // make sure all leaves are represented as nodes
             for leaf in leaves.iter() {
@@ -133,7 +147,7 @@ fn extract_constraint_graph(
             }
         }
     }
-    out
+    (out, fixed)
 }
 
 /// extracts connected components, based on petgraph::algo::connected_components
@@ -166,7 +180,7 @@ fn connected_components(g: &ConstraintGraph) -> Vec<SmallVec<[usize; 2]>> {
     out
 }
 
-fn split_conjunction(ctx: &mut Context, e: ExprRef) -> ExprRefVec {
+pub fn split_conjunction(ctx: &mut Context, e: ExprRef) -> ExprRefVec {
     let mut out = smallvec![];
     let mut todo: ExprRefVec = smallvec![e];
     while let Some(e) = todo.pop() {
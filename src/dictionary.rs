@@ -0,0 +1,151 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Mines interesting input values from the constants that appear in the design.
+
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use std::collections::{HashMap, HashSet};
+
+/// How many expressions away from an input we look for constants.
+const MAX_FANOUT_DEPTH: u32 = 8;
+
+/// Constants that appear close to an input in its fan-out, shifted and truncated to
+/// the input width, together with their direct neighbours.
+pub fn mine_constants(ctx: &Context, sys: &TransitionSystem) -> HashMap<ExprRef, Vec<u64>> {
+    let parents = collect_parents(ctx, sys);
+    let mut out = HashMap::new();
+    for (input, _) in sys.get_signals(|s| s.is_input()) {
+        let Some(width) = input.get_bv_type(ctx).filter(|w| *w <= 64) else {
+            continue;
+        };
+        let mut values = Vec::new();
+        // bit offset of the input in each expression that we visit
+        let mut todo = vec![(input, 0i64, 0u32)];
+        let mut visited = HashSet::new();
+        while let Some((e, offset, depth)) = todo.pop() {
+            if !visited.insert((e, offset)) || depth > MAX_FANOUT_DEPTH {
+                continue;
+            }
+            for parent in parents.get(&e).into_iter().flatten() {
+                let parent_offset = offset_in_parent(ctx, *parent, e, offset);
+                ctx.get(*parent).for_each_child(|c| {
+                    if let Expr::BVLiteral { value, .. } = ctx.get(*c) {
+                        values.push(align(*value, parent_offset, width));
+                    }
+                });
+                todo.push((*parent, parent_offset, depth + 1));
+            }
+        }
+        let mut dict = Vec::with_capacity(values.len() * 3);
+        for value in values {
+            dict.push(value);
+            dict.push(value.wrapping_add(1) & mask(width));
+            dict.push(value.wrapping_sub(1) & mask(width));
+        }
+        dict.sort_unstable();
+        dict.dedup();
+        if !dict.is_empty() {
+            out.insert(input, dict);
+        }
+    }
+    out
+}
+
+/// Maps every expression to the expressions that use it. We do not follow states,
+/// since their next value is only visible in the following cycle.
+fn collect_parents(ctx: &Context, sys: &TransitionSystem) -> HashMap<ExprRef, Vec<ExprRef>> {
+    let mut todo = sys
+        .get_signals(|s| !s.labels.is_none())
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    for (_, state) in sys.states() {
+        todo.extend(state.next);
+        todo.extend(state.init);
+    }
+    let mut parents: HashMap<ExprRef, Vec<ExprRef>> = HashMap::new();
+    let mut visited = ExprMetaData::default();
+    while let Some(e) = todo.pop() {
+        if *visited.get(e) {
+            continue;
+        }
+        *visited.get_mut(e) = true;
+        ctx.get(e).for_each_child(|c| {
+            parents.entry(*c).or_default().push(e);
+            todo.push(*c);
+        });
+    }
+    for uses in parents.values_mut() {
+        uses.sort_unstable();
+        uses.dedup();
+    }
+    parents
+}
+
+/// Computes where bit zero of the input ends up in `parent`, given its offset in `child`.
+fn offset_in_parent(ctx: &Context, parent: ExprRef, child: ExprRef, offset: i64) -> i64 {
+    match *ctx.get(parent) {
+        // the second argument of a concat is the least significant part
+        Expr::BVConcat(msb, lsb, _) if msb == child && lsb != child => {
+            offset + lsb.get_bv_type(ctx).unwrap() as i64
+        }
+        Expr::BVSlice { lo, .. } => offset - lo as i64,
+        _ => offset,
+    }
+}
+
+/// Moves a constant from the coordinates of the parent expression into the input's.
+fn align(value: u64, offset: i64, width: WidthInt) -> u64 {
+    let shifted = if offset >= 64 || offset <= -64 {
+        0
+    } else if offset >= 0 {
+        value >> offset
+    } else {
+        value << -offset
+    };
+    shifted & mask(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::btor2;
+
+    #[test]
+    fn test_mine_easy() {
+        let (ctx, sys) = btor2::parse_file("inputs/easy.btor").unwrap();
+        let dict = mine_constants(&ctx, &sys);
+        let input = sys.get_signals(|s| s.is_input())[0].0;
+        assert_eq!(
+            dict[&input],
+            [0b000001111010, 0b000001111011, 0b000001111100]
+        );
+    }
+
+    #[test]
+    fn test_mine_concat_and_slice() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        // {a, b} == 0x1234
+        let ab = ctx.concat(a, b);
+        let lit = ctx.bv_lit(0x1234, 16);
+        let eq = ctx.bv_equal(ab, lit);
+        sys.add_signal(eq, SignalKind::Node, SignalLabels::bad(), None);
+        // b[3:0] == 0xf
+        let b_lsb = ctx.slice(b, 3, 0);
+        let lit = ctx.bv_lit(0xf, 4);
+        let eq = ctx.bv_equal(b_lsb, lit);
+        sys.add_signal(eq, SignalKind::Node, SignalLabels::bad(), None);
+
+        let dict = mine_constants(&ctx, &sys);
+        assert!(dict[&a].contains(&0x12));
+        assert!(dict[&b].contains(&0x34));
+        assert!(dict[&b].contains(&0x0f));
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Estimates how close the current cycle is to reaching a bad state.
+
+use crate::constraints::split_conjunction;
+use crate::random::make_observable;
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::Interpreter;
+
+/// A single condition that needs to hold for a bad state to be reached.
+#[derive(Debug, Clone, Copy)]
+enum Conjunct {
+    /// `a == b`
+    Equal(ExprRef, ExprRef, WidthInt),
+    /// `a != b`
+    NotEqual(ExprRef, ExprRef, WidthInt),
+    /// `a > b` or `a >= b`
+    Greater {
+        a: ExprRef,
+        b: ExprRef,
+        width: WidthInt,
+        signed: bool,
+        or_equal: bool,
+    },
+    /// any other 1-bit expression
+    Bool(ExprRef),
+}
+
+impl Conjunct {
+    fn new(ctx: &Context, e: ExprRef) -> Self {
+        let fits = |a: ExprRef| a.get_bv_type(ctx).filter(|w| *w <= 64);
+        let greater = |a, b, signed, or_equal| match fits(a) {
+            Some(width) => Conjunct::Greater {
+                a,
+                b,
+                width,
+                signed,
+                or_equal,
+            },
+            None => Conjunct::Bool(e),
+        };
+        match *ctx.get(e) {
+            Expr::BVEqual(a, b) => match fits(a) {
+                Some(width) => Conjunct::Equal(a, b, width),
+                None => Conjunct::Bool(e),
+            },
+            Expr::BVGreater(a, b) => greater(a, b, false, false),
+            Expr::BVGreaterEqual(a, b) => greater(a, b, false, true),
+            Expr::BVGreaterSigned(a, b, _) => greater(a, b, true, false),
+            Expr::BVGreaterEqualSigned(a, b, _) => greater(a, b, true, true),
+            // negated comparisons are turned around
+            Expr::BVNot(inner, 1) => match *ctx.get(inner) {
+                Expr::BVEqual(a, b) => match fits(a) {
+                    Some(width) => Conjunct::NotEqual(a, b, width),
+                    None => Conjunct::Bool(e),
+                },
+                Expr::BVGreater(a, b) => greater(b, a, false, true),
+                Expr::BVGreaterEqual(a, b) => greater(b, a, false, false),
+                Expr::BVGreaterSigned(a, b, _) => greater(b, a, true, true),
+                Expr::BVGreaterEqualSigned(a, b, _) => greater(b, a, true, false),
+                _ => Conjunct::Bool(e),
+            },
+            _ => Conjunct::Bool(e),
+        }
+    }
+
+    fn exprs(&self) -> Vec<ExprRef> {
+        match *self {
+            Conjunct::Equal(a, b, _) | Conjunct::NotEqual(a, b, _) => vec![a, b],
+            Conjunct::Greater { a, b, .. } => vec![a, b],
+            Conjunct::Bool(e) => vec![e],
+        }
+    }
+
+    /// Returns a value between zero and one, zero iff the conjunct holds.
+    fn distance(&self, ctx: &Context, sim: &Interpreter) -> f64 {
+        // literals are not evaluated by the simulator
+        let value = |e: ExprRef| match *ctx.get(e) {
+            Expr::BVLiteral { value, .. } => value,
+            _ => sim.get(e).unwrap().to_u64().unwrap(),
+        };
+        match *self {
+            Conjunct::Equal(a, b, width) => {
+                let (a, b) = (value(a), value(b));
+                // average of the number of bits that differ and the magnitude of the difference
+                let hamming = (a ^ b).count_ones() as f64;
+                let arithmetic = bits(a.abs_diff(b)) as f64;
+                (hamming + arithmetic) / (2 * width) as f64
+            }
+            Conjunct::NotEqual(a, b, width) => {
+                if value(a) != value(b) {
+                    0.0
+                } else {
+                    // a single bit flip is enough
+                    1.0 / width as f64
+                }
+            }
+            Conjunct::Greater {
+                a,
+                b,
+                width,
+                signed,
+                or_equal,
+            } => {
+                let (mut a, mut b) = (value(a), value(b));
+                if signed {
+                    // offset binary preserves the order of signed values
+                    a ^= 1 << (width - 1);
+                    b ^= 1 << (width - 1);
+                }
+                let missing = if or_equal {
+                    b.saturating_sub(a)
+                } else if a > b {
+                    0
+                } else {
+                    // `b + 1` would overflow for the largest value
+                    (b - a).saturating_add(1)
+                };
+                bits(missing) as f64 / width as f64
+            }
+            Conjunct::Bool(e) => {
+                if value(e) == 1 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+/// Number of bits needed to represent `value`.
+fn bits(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+/// Splits every bad state into conjuncts which are evaluated separately.
+pub struct BadDistance {
+    bads: Vec<Vec<Conjunct>>,
+}
+
+impl BadDistance {
+    pub fn new(ctx: &mut Context, sys: &TransitionSystem) -> Self {
+        let bads = sys
+            .bad_states()
+            .into_iter()
+            .map(|(e, _)| {
+                split_conjunction(ctx, e)
+                    .into_iter()
+                    .map(|c| Conjunct::new(ctx, c))
+                    .collect()
+            })
+            .collect();
+        Self { bads }
+    }
+
+    /// Makes sure that all conjuncts and their operands can be read from the simulator.
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        for conjunct in self.bads.iter().flatten() {
+            for e in conjunct.exprs() {
+                make_observable(ctx, sys, e);
+            }
+        }
+    }
+
+    /// Fitness of the current cycle: the best ratio of (partially) satisfied conjuncts
+    /// over all bad states. Reaches one iff a bad state is reached.
+    pub fn fitness(&self, ctx: &Context, sim: &Interpreter) -> f64 {
+        self.bads
+            .iter()
+            .map(|conjuncts| {
+                let distance = conjuncts.iter().map(|c| c.distance(ctx, sim)).sum::<f64>();
+                1.0 - distance / conjuncts.len() as f64
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::sim::interpreter::InitKind;
+
+    #[test]
+    fn test_fitness() {
+        // bad: a == 0x80 && b > 3
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let lit = ctx.bv_lit(0x80, 8);
+        let a_eq = ctx.bv_equal(a, lit);
+        let three = ctx.bv_lit(3, 8);
+        let b_gt = ctx.greater(b, three);
+        let bad = ctx.and(a_eq, b_gt);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let distance = BadDistance::new(&mut ctx, &sys);
+        distance.instrument(&ctx, &mut sys);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut fitness = |a_value: u64, b_value: u64| {
+            sim.set(a, ValueRef::new(&[a_value], 8));
+            sim.set(b, ValueRef::new(&[b_value], 8));
+            sim.update();
+            distance.fitness(&ctx, &sim)
+        };
+        let far = fitness(0, 0);
+        let closer = fitness(0x7f, 3);
+        let one_satisfied = fitness(0x7f, 4);
+        let almost = fitness(0x81, 4);
+        assert!(far < closer);
+        assert!(closer < one_satisfied);
+        assert!(one_satisfied < almost);
+        assert_eq!(fitness(0x80, 4), 1.0);
+    }
+
+    #[test]
+    fn test_greater_than_max() {
+        // bad: a > 0xffff_ffff_ffff_ffff, which can never hold
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 64);
+        sys.add_input(&ctx, a);
+        let max = ctx.bv_lit(u64::MAX, 64);
+        let bad = ctx.greater(a, max);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let distance = BadDistance::new(&mut ctx, &sys);
+        distance.instrument(&ctx, &mut sys);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        sim.set(a, ValueRef::new(&[u64::MAX], 64));
+        sim.update();
+        let fitness = distance.fitness(&ctx, &sim);
+        assert!((0.0..1.0).contains(&fitness), "{fitness}");
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Explicit-state reachability: breadth-first search over the concrete states of the
+// interpreter that tries every input valuation in every state.
+
+use crate::bitblast::check_supported;
+use crate::certificate::Certificate;
+use crate::random::check_for_bad_states;
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::{InitKind, Interpreter};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Upper bound on the number of input bits that we enumerate in every state.
+const MAX_INPUT_BITS: WidthInt = 16;
+/// Upper bound on the number of bits of states without an initial value.
+const MAX_UNINIT_BITS: WidthInt = 16;
+/// Upper bound on the number of states that we visit before giving up.
+const MAX_STATES: usize = 1 << 22;
+/// Upper bound on the number of reachable states that we list in a certificate.
+const MAX_CERTIFICATE_STATES: usize = 1 << 12;
+
+/// A visited state together with how we first got there.
+struct Node {
+    snapshot: u32,
+    /// `None` for initial states, otherwise the predecessor and the inputs applied in it
+    parent: Option<(usize, u64)>,
+}
+
+pub struct Bfs<'a> {
+    sim: Interpreter<'a>,
+    states: Vec<(ExprRef, WidthInt)>,
+    uninit: Vec<(ExprRef, WidthInt)>,
+    /// all inputs in the order of the witness
+    inputs: Vec<(ExprRef, WidthInt)>,
+    /// inputs that the system actually depends on, all others are always zero
+    enumerated: Vec<(ExprRef, WidthInt)>,
+    bad_states: Vec<ExprRef>,
+    constraints: Vec<ExprRef>,
+    /// values of all states mapped to the node in which they were first seen
+    visited: HashMap<Vec<Word>, usize>,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Bfs<'a> {
+    pub fn new(ctx: &'a Context, sys: &TransitionSystem) -> Result<Self, String> {
+        check_supported(ctx, sys)?;
+        let sim = Interpreter::new(ctx, sys);
+        let with_width = |e: ExprRef| (e, e.get_bv_type(ctx).unwrap());
+        let states = sys
+            .states()
+            .map(|(_, s)| with_width(s.symbol))
+            .collect::<Vec<_>>();
+        let uninit = sys
+            .states()
+            .filter(|(_, s)| s.init.is_none())
+            .map(|(_, s)| with_width(s.symbol))
+            .collect::<Vec<_>>();
+        let initialized = sys
+            .states()
+            .filter(|(_, s)| s.init.is_some())
+            .map(|(_, s)| s.symbol)
+            .collect::<HashSet<_>>();
+        for (_, state) in sys.states() {
+            if state.next.is_none() {
+                let name = state.symbol.get_symbol_name(ctx).unwrap();
+                return Err(format!(
+                    "state `{name}` without a next value is not supported"
+                ));
+            }
+            // we assign arbitrary values only after the init program has run
+            if let Some(init) = state.init {
+                if depends_on_free_symbols(ctx, init, &initialized) {
+                    let name = state.symbol.get_symbol_name(ctx).unwrap();
+                    return Err(format!(
+                        "the initial value of `{name}` depends on an input or uninitialized state"
+                    ));
+                }
+            }
+        }
+        let inputs = sys
+            .get_signals(|s| s.is_input())
+            .into_iter()
+            .map(|(e, _)| with_width(e))
+            .collect::<Vec<_>>();
+        let enumerated = inputs
+            .iter()
+            .filter(|(e, _)| sim.get(*e).is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        if total_bits(&enumerated) > MAX_INPUT_BITS {
+            return Err(format!(
+                "{} input bits are too many to enumerate",
+                total_bits(&enumerated)
+            ));
+        }
+        if total_bits(&uninit) > MAX_UNINIT_BITS {
+            return Err(format!(
+                "{} bits without an initial value are too many to enumerate",
+                total_bits(&uninit)
+            ));
+        }
+        let signals = |filter: fn(&SignalInfo) -> bool| {
+            sys.get_signals(filter)
+                .into_iter()
+                .map(|(e, _)| e)
+                .collect::<Vec<_>>()
+        };
+        Ok(Self {
+            sim,
+            states,
+            uninit,
+            inputs,
+            enumerated,
+            bad_states: signals(|s| s.labels.is_bad()),
+            constraints: signals(|s| s.labels.is_constraint()),
+            visited: HashMap::new(),
+            nodes: Vec::new(),
+        })
+    }
+
+    /// Explores all reachable states. Returns the shortest counterexample, `UnSat` with the
+    /// reachable states as invariant or an error once too many states have been visited.
+    /// No certificate is emitted if there are too many reachable states to list them.
+    pub fn run(
+        &mut self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        verbose: bool,
+    ) -> Result<ModelCheckResult, String> {
+        let mut todo = VecDeque::new();
+        self.sim.init(InitKind::Zero);
+        for values in 0..1u64 << total_bits(&self.uninit) {
+            assign(&mut self.sim, &self.uninit, values);
+            self.discover(None, &mut todo);
+        }
+        // every state is explored before any state that is further away from the initial states
+        while let Some(node) = todo.pop_front() {
+            for values in 0..1u64 << total_bits(&self.enumerated) {
+                self.sim.restore_snapshot(self.nodes[node].snapshot);
+                assign(&mut self.sim, &self.enumerated, values);
+                self.sim.update();
+                let constraints_hold = self
+                    .constraints
+                    .iter()
+                    .all(|c| self.sim.get(*c).unwrap().to_u64().unwrap() == 1);
+                if !constraints_hold {
+                    continue;
+                }
+                let bads = check_for_bad_states(&self.bad_states, &self.sim);
+                if !bads.is_empty() {
+                    return Ok(ModelCheckResult::Sat(self.witness(node, values, bads)));
+                }
+                self.sim.step();
+                self.discover(Some((node, values)), &mut todo);
+                if self.nodes.len() > MAX_STATES {
+                    return Err(format!("gave up after visiting {MAX_STATES} states"));
+                }
+            }
+        }
+        if self.nodes.len() > MAX_CERTIFICATE_STATES {
+            if verbose {
+                println!(
+                    "explicit-state: {} reachable states are too many for a certificate",
+                    self.nodes.len()
+                );
+            }
+            return Ok(ModelCheckResult::UnSat(None));
+        }
+        let mut ctx = ctx.clone();
+        let invariant = self.invariant(&mut ctx);
+        Ok(ModelCheckResult::UnSat(Some(Certificate::invariant(
+            &ctx, sys, invariant,
+        ))))
+    }
+
+    /// Remembers the current state of the simulator if we have never seen it before.
+    fn discover(&mut self, parent: Option<(usize, u64)>, todo: &mut VecDeque<usize>) {
+        let values = self.values();
+        if self.visited.contains_key(&values) {
+            return;
+        }
+        let node = self.nodes.len();
+        self.visited.insert(values, node);
+        self.nodes.push(Node {
+            snapshot: self.sim.take_snapshot(),
+            parent,
+        });
+        todo.push_back(node);
+    }
+
+    fn values(&self) -> Vec<Word> {
+        self.states
+            .iter()
+            .flat_map(|(s, _)| self.sim.get(*s).unwrap().words().to_vec())
+            .collect()
+    }
+
+    /// Follows the parents back to an initial state.
+    fn witness(&mut self, node: usize, values: u64, failed_safety: Vec<usize>) -> Witness {
+        let mut path = vec![values];
+        let mut current = node;
+        while let Some((parent, values)) = self.nodes[current].parent {
+            path.push(values);
+            current = parent;
+        }
+        self.sim.restore_snapshot(self.nodes[current].snapshot);
+        let state_init = self.values();
+        let mut input_data = Vec::new();
+        for values in path.iter().rev() {
+            let mut offset = 0;
+            for (input, width) in self.inputs.iter() {
+                if self.enumerated.iter().any(|(e, _)| e == input) {
+                    input_data.push((values >> offset) & mask(*width));
+                    offset += width;
+                } else {
+                    input_data.extend(std::iter::repeat_n(0, width.div_ceil(Word::BITS) as usize));
+                }
+            }
+        }
+        Witness {
+            input_data,
+            state_init,
+            k: (path.len() - 1) as StepInt,
+            failed_safety,
+        }
+    }
+
+    /// Disjunction of all visited states.
+    fn invariant(&self, ctx: &mut Context) -> ExprRef {
+        let mut visited = self.visited.iter().collect::<Vec<_>>();
+        visited.sort_unstable_by_key(|(_, node)| **node);
+        let mut out = None;
+        for (values, _) in visited {
+            let mut offset = 0;
+            let mut is_state = ctx.one(1);
+            for (symbol, width) in self.states.iter() {
+                let words = width.div_ceil(Word::BITS) as usize;
+                let equal = equals(ctx, *symbol, *width, &values[offset..offset + words]);
+                is_state = ctx.and(is_state, equal);
+                offset += words;
+            }
+            out = Some(match out {
+                None => is_state,
+                Some(prev) => ctx.or(prev, is_state),
+            });
+        }
+        out.unwrap_or_else(|| ctx.zero(1))
+    }
+}
+
+/// Exhaustively searches for a counterexample. Only feasible for designs with few input bits.
+pub fn explicit_state(ctx: &Context, sys: &TransitionSystem, verbose: bool) -> ModelCheckResult {
+    if sys.bad_states().is_empty() {
+        return ModelCheckResult::Unknown;
+    }
+    let result = Bfs::new(ctx, sys).and_then(|mut bfs| bfs.run(ctx, sys, verbose));
+    result.unwrap_or_else(|e| {
+        println!("explicit-state: {e}");
+        ModelCheckResult::Unknown
+    })
+}
+
+fn total_bits(signals: &[(ExprRef, WidthInt)]) -> WidthInt {
+    signals.iter().map(|(_, w)| *w).sum()
+}
+
+/// Assigns consecutive bits of `values` to the signals.
+fn assign(sim: &mut Interpreter, signals: &[(ExprRef, WidthInt)], values: u64) {
+    let mut offset = 0;
+    for (signal, width) in signals.iter() {
+        let value = [(values >> offset) & mask(*width)];
+        sim.set(*signal, ValueRef::new(&value, *width));
+        offset += width;
+    }
+}
+
+/// Checks whether an expression refers to a symbol that is not an initialized state.
+fn depends_on_free_symbols(ctx: &Context, expr: ExprRef, initialized: &HashSet<ExprRef>) -> bool {
+    let mut todo = vec![expr];
+    let mut visited = HashSet::new();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        let expr = ctx.get(e);
+        if expr.is_symbol() && !initialized.contains(&e) {
+            return true;
+        }
+        expr.for_each_child(|c| todo.push(*c));
+    }
+    false
+}
+
+/// Compares a state to a value word by word.
+fn equals(ctx: &mut Context, symbol: ExprRef, width: WidthInt, words: &[Word]) -> ExprRef {
+    if width <= Word::BITS {
+        let value = ctx.bv_lit(words[0], width);
+        return ctx.bv_equal(symbol, value);
+    }
+    let mut out = ctx.one(1);
+    for (ii, word) in words.iter().enumerate() {
+        let lo = ii as WidthInt * Word::BITS;
+        let hi = (lo + Word::BITS).min(width) - 1;
+        let part = ctx.slice(symbol, hi, lo);
+        let value = ctx.bv_lit(*word, hi - lo + 1);
+        let equal = ctx.bv_equal(part, value);
+        out = ctx.and(out, equal);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmc::bmc;
+    use crate::certificate::check_certificate;
+
+    /// Counter that increments when enabled and wraps around after `max`.
+    fn counter(ctx: &mut Context, width: WidthInt, max: u64, bad: u64) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", width);
+        let state = sys.add_state(ctx, count);
+        let one = ctx.one(width);
+        let inc = ctx.add(count, one);
+        let max = ctx.bv_lit(max, width);
+        let wrap = ctx.bv_equal(count, max);
+        let zero = ctx.zero(width);
+        let inc = ctx.bv_ite(wrap, zero, inc);
+        let next = ctx.bv_ite(en, inc, count);
+        sys.modify_state(state, |s| {
+            s.next = Some(next);
+            s.init = Some(zero);
+        });
+        let bad = ctx.bv_lit(bad, width);
+        let bad = ctx.bv_equal(count, bad);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+        sys
+    }
+
+    #[test]
+    fn test_explicit_state() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx, 4, 9, 6);
+        let ModelCheckResult::Sat(wit) = explicit_state(&ctx, &sys, false) else {
+            panic!("expected a counter example");
+        };
+        // shortest counterexample, same as for BMC
+        let ModelCheckResult::Sat(expected) = bmc(&ctx, &sys, 10, false) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.k, expected.k);
+        assert_eq!(wit.state_init, [0]);
+        assert_eq!(wit.input_data, [1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(wit.failed_safety, [0]);
+
+        // the reachable states are an inductive invariant
+        let sys = counter(&mut ctx, 4, 9, 12);
+        let ModelCheckResult::UnSat(Some(cert)) = explicit_state(&ctx, &sys, false) else {
+            panic!("expected a proof");
+        };
+        check_certificate(&ctx, &sys, &cert).unwrap();
+
+        // listing every reachable state would make the certificate too large
+        let max = MAX_CERTIFICATE_STATES as u64;
+        let sys = counter(&mut ctx, 13, max, max + 1);
+        assert!(matches!(
+            explicit_state(&ctx, &sys, false),
+            ModelCheckResult::UnSat(None)
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Go-explore style search: we remember rarely seen register valuations together with
+// the inputs that lead to them and start new random segments from there.
+
+use crate::progress::Progress;
+use crate::random::{
+    check_for_bad_states, count_cycle, record_inputs, sample_k_max, GeneratorState, InputDriver,
+    InputHistory, RandomOptions,
+};
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::{InitKind, Interpreter};
+use rand::Rng;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Upper bound on the number of states that we keep snapshots for.
+const MAX_CELLS: usize = 4096;
+/// Number of randomly picked cells out of which we start from the least visited.
+const TOURNAMENT_SIZE: usize = 4;
+
+/// A saved state together with the inputs needed to get there from its parent.
+struct Cell {
+    snapshot: u32,
+    /// `None` for the initial state
+    parent: Option<usize>,
+    /// inputs of all cycles since leaving the parent cell
+    inputs: Vec<Word>,
+    /// number of cycles from the initial state
+    depth: StepInt,
+    /// hash of the register valuation
+    hash: u64,
+    /// previous input values, pending transfers and hold counters, which constrain the
+    /// inputs of the next cycle
+    history: InputHistory,
+}
+
+pub fn explore_testing(
+    mut ctx: Context,
+    mut sys: TransitionSystem,
+    opts: RandomOptions,
+    seed: u64,
+    progress: &mut Progress,
+) -> ModelCheckResult {
+    let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+    inputs.instrument(&ctx, &mut sys);
+    progress.instrument(&ctx, &mut sys);
+
+    // collect bad states
+    let bad_states = sys
+        .bad_states()
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    let states = sys
+        .states()
+        .map(|(_, s)| s.symbol)
+        .filter(|s| s.get_type(&ctx).is_bit_vector())
+        .collect::<Vec<_>>();
+
+    // create simulator
+    let sim_ctx = ctx.clone();
+    let mut sim = Interpreter::new(&sim_ctx, &sys);
+    sim.init(InitKind::Zero);
+    let mut state_init = Vec::new();
+    for (_, state) in sys.states() {
+        state_init.extend_from_slice(sim.get(state.symbol).unwrap().words());
+    }
+
+    // the initial state is always part of the archive
+    let start_hash = hash_state(&states, &sim);
+    let mut visits = HashMap::from([(start_hash, 1u64)]);
+    let mut cells = vec![Cell {
+        snapshot: sim.take_snapshot(),
+        parent: None,
+        inputs: Vec::new(),
+        depth: 0,
+        hash: start_hash,
+        history: InputHistory::default(),
+    }];
+
+    let mut gen = GeneratorState::new(seed);
+    let mut cycle_count = 0;
+    loop {
+        let start = pick_cell(&cells, &visits, &mut gen.rng);
+        let depth = cells[start].depth;
+        let k_max = sample_k_max(&mut gen.rng, &opts);
+        sim.restore_snapshot(cells[start].snapshot);
+        inputs.start_trace(&mut gen);
+        gen.restore_input_history(&cells[start].history);
+
+        // inputs applied since leaving the start cell
+        let mut segment = Vec::new();
+        for k in 0..=k_max {
+            // cycles are counted from the initial state, thus resets are only applied once
+            match inputs.drive_cycle(
+                &ctx,
+                &mut gen,
+                depth + k,
+                &mut sim,
+                false,
+                &mut cycle_count,
+                opts.max_cycles,
+            ) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(result) => return result,
+            }
+            inputs.observe(&sim, &mut gen);
+            progress.record(&ctx, &sim);
+            record_inputs(&ctx, &sys, &sim, &mut segment);
+
+            // check if we are in a bad state
+            let bads = check_for_bad_states(&bad_states, &sim);
+            if !bads.is_empty() {
+                let wit = Witness {
+                    input_data: stitch_inputs(&cells, start, segment),
+                    state_init,
+                    k: depth + k,
+                    failed_safety: bads,
+                };
+                return ModelCheckResult::Sat(wit);
+            }
+
+            // advance the system
+            sim.step();
+            if count_cycle(&mut cycle_count, opts.max_cycles) {
+                return ModelCheckResult::Unknown;
+            }
+
+            // remember states that we have never seen before
+            let hash = hash_state(&states, &sim);
+            let count = visits.entry(hash).or_insert(0);
+            *count += 1;
+            if *count == 1 && cells.len() < MAX_CELLS {
+                cells.push(Cell {
+                    snapshot: sim.take_snapshot(),
+                    parent: Some(start),
+                    inputs: segment.clone(),
+                    depth: depth + k + 1,
+                    hash,
+                    history: gen.input_history(),
+                });
+            }
+        }
+    }
+}
+
+fn hash_state(states: &[ExprRef], sim: &Interpreter) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for state in states.iter() {
+        sim.get(*state).unwrap().words().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Picks a cell whose state was rarely visited.
+fn pick_cell(cells: &[Cell], visits: &HashMap<u64, u64>, rng: &mut impl Rng) -> usize {
+    (0..TOURNAMENT_SIZE)
+        .map(|_| rng.gen_range(0..cells.len()))
+        .min_by_key(|c| visits[&cells[*c].hash])
+        .unwrap()
+}
+
+/// Concatenates the inputs of all cells on the path from the initial state to `cell`
+/// and the inputs of the current segment.
+fn stitch_inputs(cells: &[Cell], cell: usize, segment: Vec<Word>) -> Vec<Word> {
+    let mut parts = vec![segment];
+    let mut current = Some(cell);
+    while let Some(c) = current {
+        parts.push(cells[c].inputs.clone());
+        current = cells[c].parent;
+    }
+    parts.into_iter().rev().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_witness_starts_at_cycle_zero() {
+        // counter that only increments when enabled and is bad once it reaches 7
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let counter = ctx.bv_symbol("counter", 4);
+        let state = sys.add_state(&ctx, counter);
+        let one = ctx.one(4);
+        let inc = ctx.add(counter, one);
+        let next = ctx.bv_ite(en, inc, counter);
+        sys.modify_state(state, |s| s.next = Some(next));
+        let seven = ctx.bv_lit(7, 4);
+        let bad = ctx.bv_equal(counter, seven);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.small_k = 2;
+        opts.large_k = 2;
+        opts.input_heuristics = false;
+        let mut progress = Progress::new(&mut ctx, &sys);
+        let ModelCheckResult::Sat(wit) =
+            explore_testing(ctx.clone(), sys.clone(), opts, 0, &mut progress)
+        else {
+            panic!("expected a counter example");
+        };
+        // segments are at most three cycles long, thus the witness has to be stitched together
+        assert!(wit.k >= 7);
+
+        // replay the witness from the initial state
+        let inputs = sys.get_signals(|s| s.is_input());
+        let words_per_cycle = inputs
+            .iter()
+            .map(|(i, _)| i.get_bv_type(&ctx).unwrap().div_ceil(Word::BITS) as usize)
+            .sum::<usize>();
+        assert_eq!(wit.input_data.len(), (wit.k as usize + 1) * words_per_cycle);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let bad_states = sys
+            .bad_states()
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        for (k, cycle) in wit.input_data.chunks(words_per_cycle).enumerate() {
+            let mut offset = 0;
+            for (input, _) in inputs.iter() {
+                let width = input.get_bv_type(&ctx).unwrap();
+                let len = width.div_ceil(Word::BITS) as usize;
+                sim.set(*input, ValueRef::new(&cycle[offset..offset + len], width));
+                offset += len;
+            }
+            sim.update();
+            let bads = check_for_bad_states(&bad_states, &sim);
+            if k as StepInt == wit.k {
+                assert_eq!(bads, wit.failed_safety);
+            } else {
+                assert!(bads.is_empty());
+            }
+            sim.step();
+        }
+    }
+
+    #[test]
+    fn test_restart_holds_pending_transfer() {
+        // the design is only ready every other cycle and is bad after six transfers
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let valid = ctx.bv_symbol("in_valid", 1);
+        let data = ctx.bv_symbol("in_data", 8);
+        sys.add_input(&ctx, valid);
+        sys.add_input(&ctx, data);
+        let busy = ctx.bv_symbol("busy", 1);
+        let state = sys.add_state(&ctx, busy);
+        let ready = ctx.not(busy);
+        sys.modify_state(state, |s| s.next = Some(ready));
+        let name = ctx.add_node("in_ready");
+        sys.add_signal(ready, SignalKind::Node, SignalLabels::output(), Some(name));
+        let count = ctx.bv_symbol("count", 4);
+        let state = sys.add_state(&ctx, count);
+        let one = ctx.one(4);
+        let inc = ctx.add(count, one);
+        let fire = ctx.and(valid, ready);
+        let next = ctx.bv_ite(fire, inc, count);
+        sys.modify_state(state, |s| s.next = Some(next));
+        let six = ctx.bv_lit(6, 4);
+        let bad = ctx.bv_equal(count, six);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.small_k = 2;
+        opts.large_k = 2;
+        opts.input_heuristics = false;
+        opts.infer_handshakes = true;
+        for seed in 0..10 {
+            let mut progress = Progress::new(&mut ctx, &sys);
+            let ModelCheckResult::Sat(wit) =
+                explore_testing(ctx.clone(), sys.clone(), opts.clone(), seed, &mut progress)
+            else {
+                panic!("expected a counter example");
+            };
+            // every input fits into a single word, `busy` toggles starting from zero
+            let cycles = wit.input_data.chunks(2).collect::<Vec<_>>();
+            for (k, pair) in cycles.windows(2).enumerate() {
+                let stalled = pair[0][0] == 1 && k % 2 == 1;
+                if stalled {
+                    assert_eq!(pair[0], pair[1], "seed {seed}, cycle {k}");
+                }
+            }
+        }
+    }
+}
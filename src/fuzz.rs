@@ -0,0 +1,518 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Coverage guided fuzzing strategy to finding counter examples.
+
+use crate::progress::Progress;
+use crate::random::{
+    check_for_bad_states, corner_values, count_cycle, make_observable, sample_k_max,
+    GeneratorState, InputDriver, RandomOptions,
+};
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::{InitKind, Interpreter};
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Probability of starting from a fresh random trace instead of mutating one from the corpus.
+const FRESH_TRACE_PROB: f64 = 0.1;
+/// Probability of extending a mutated trace with freshly generated cycles.
+const EXTEND_PROB: f64 = 0.2;
+/// Maximum number of mutations that are stacked on top of each other.
+const MAX_MUTATIONS: u32 = 4;
+/// Number of executions after which a worker pulls the traces discovered by other workers.
+const SYNC_INTERVAL: u64 = 64;
+
+pub fn fuzz_testing(
+    mut ctx: Context,
+    mut sys: TransitionSystem,
+    opts: RandomOptions,
+    seed: u64,
+    shared: Arc<SharedCorpus>,
+    progress: &mut Progress,
+) -> ModelCheckResult {
+    // coverage points need to be derived from the same system as the shared map
+    let coverage = Coverage::new(&ctx, &sys);
+    assert_eq!(coverage.len(), shared.map.len());
+    let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+    inputs.instrument(&ctx, &mut sys);
+    coverage.instrument(&ctx, &mut sys);
+    progress.instrument(&ctx, &mut sys);
+    let layout = InputLayout::new(&ctx, &sys, &inputs);
+
+    // collect bad states
+    let bad_states = sys
+        .bad_states()
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+
+    // create simulator
+    let sim_ctx = ctx.clone();
+    let mut sim = Interpreter::new(&sim_ctx, &sys);
+    sim.init(InitKind::Zero);
+    let start_state = sim.take_snapshot();
+    let mut state_init = Vec::new();
+    for (_, state) in sys.states() {
+        state_init.extend_from_slice(sim.get(state.symbol).unwrap().words());
+    }
+    let coverage_start = coverage.state_values(&sim);
+
+    let mut gen = GeneratorState::new(seed);
+    let mut corpus: Vec<Trace> = Vec::new();
+    // number of shared traces that we have already seen
+    let mut synced = 0;
+
+    let mut cycle_count = 0;
+    for execution in 0.. {
+        if execution % SYNC_INTERVAL == 0 {
+            synced = shared.pull(seed, synced, &mut corpus);
+        }
+
+        // pick the next trace to execute
+        let (trace, len) = if corpus.is_empty() || gen.rng.gen_bool(FRESH_TRACE_PROB) {
+            (Trace::default(), sample_k_max(&mut gen.rng, &opts) + 1)
+        } else {
+            let parent = &corpus[gen.rng.gen_range(0..corpus.len())];
+            let mut trace = parent.clone();
+            mutate(&mut trace, &corpus, &layout, &mut gen.rng);
+            let mut len = layout.cycles(&trace) as StepInt;
+            if len == 0 || gen.rng.gen_bool(EXTEND_PROB) {
+                len += gen.rng.gen_range(1..=opts.small_k);
+            }
+            (trace, std::cmp::min(len, opts.large_k + 1))
+        };
+
+        // execute trace, cycles that are missing from the trace are generated randomly
+        sim.restore_snapshot(start_state);
+        inputs.start_trace(&mut gen);
+        let mut prev_states = coverage_start.clone();
+        let mut executed = Trace::default();
+        let mut new_coverage = false;
+        for k in 0..len {
+            // missing cycles are generated randomly
+            let replayed = (k as usize) < layout.cycles(&trace);
+            if replayed {
+                layout.apply(&trace, k as usize, &mut sim);
+            }
+            match inputs.drive_cycle(
+                &ctx,
+                &mut gen,
+                k,
+                &mut sim,
+                replayed,
+                &mut cycle_count,
+                opts.max_cycles,
+            ) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(result) => return result,
+            }
+            inputs.observe(&sim, &mut gen);
+            progress.record(&ctx, &sim);
+            layout.record(&sim, &mut executed);
+            coverage.record(&sim, &mut prev_states, |point| {
+                new_coverage |= shared.map.insert(point)
+            });
+
+            // check if we are in a bad state
+            let bads = check_for_bad_states(&bad_states, &sim);
+            if !bads.is_empty() {
+                let wit = Witness {
+                    input_data: executed.words,
+                    state_init,
+                    k,
+                    failed_safety: bads,
+                };
+                return ModelCheckResult::Sat(wit);
+            }
+
+            // advance the system
+            sim.step();
+            if count_cycle(&mut cycle_count, opts.max_cycles) {
+                return ModelCheckResult::Unknown;
+            }
+        }
+
+        if new_coverage {
+            shared.push(seed, executed.clone());
+            corpus.push(executed);
+        }
+    }
+    unreachable!()
+}
+
+/// Input values over time. Every cycle contains the words of all inputs in the order
+/// in which they are declared in the system.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub words: Vec<Word>,
+}
+
+/// Describes where each input is stored inside a trace.
+pub struct InputLayout {
+    /// input, width and word offset inside a cycle
+    inputs: Vec<(ExprRef, WidthInt, usize)>,
+    words_per_cycle: usize,
+    /// indices of inputs that may be mutated
+    mutable: Vec<usize>,
+}
+
+impl InputLayout {
+    pub fn new(ctx: &Context, sys: &TransitionSystem, driver: &InputDriver) -> Self {
+        let mut inputs = Vec::new();
+        let mut mutable = Vec::new();
+        let mut offset = 0;
+        for (input, _) in sys.get_signals(|s| s.is_input()) {
+            let Some(width) = input.get_bv_type(ctx) else {
+                todo!("support array type inputs");
+            };
+            if !driver.is_fixed(input) {
+                mutable.push(inputs.len());
+            }
+            inputs.push((input, width, offset));
+            offset += width.div_ceil(Word::BITS) as usize;
+        }
+        Self {
+            inputs,
+            words_per_cycle: offset,
+            mutable,
+        }
+    }
+
+    pub fn cycles(&self, trace: &Trace) -> usize {
+        trace
+            .words
+            .len()
+            .checked_div(self.words_per_cycle)
+            .unwrap_or(0)
+    }
+
+    pub fn cycle_range(&self, cycle: usize) -> std::ops::Range<usize> {
+        cycle * self.words_per_cycle..(cycle + 1) * self.words_per_cycle
+    }
+
+    pub fn apply(&self, trace: &Trace, cycle: usize, sim: &mut Interpreter) {
+        let words = &trace.words[self.cycle_range(cycle)];
+        for (input, width, offset) in self.inputs.iter() {
+            let len = width.div_ceil(Word::BITS) as usize;
+            sim.set(
+                *input,
+                ValueRef::new(&words[*offset..*offset + len], *width),
+            );
+        }
+    }
+
+    /// Appends the current input values to the trace.
+    pub fn record(&self, sim: &Interpreter, trace: &mut Trace) {
+        for (input, width, _) in self.inputs.iter() {
+            match sim.get(*input) {
+                Some(value) => trace.words.extend_from_slice(value.words()),
+                // inputs that are not used by the system are always zero
+                None => trace
+                    .words
+                    .extend(std::iter::repeat_n(0, width.div_ceil(Word::BITS) as usize)),
+            }
+        }
+    }
+
+    /// Returns the words that store the value of `input` at `cycle`.
+    fn value_mut<'t>(&self, trace: &'t mut Trace, cycle: usize, input: usize) -> &'t mut [Word] {
+        let (_, width, offset) = self.inputs[input];
+        let start = cycle * self.words_per_cycle + offset;
+        &mut trace.words[start..start + width.div_ceil(Word::BITS) as usize]
+    }
+}
+
+pub fn mutate(trace: &mut Trace, corpus: &[Trace], layout: &InputLayout, rng: &mut impl Rng) {
+    for _ in 0..rng.gen_range(1..=MAX_MUTATIONS) {
+        let cycles = layout.cycles(trace);
+        if cycles == 0 {
+            return;
+        }
+        let cycle = rng.gen_range(0..cycles);
+        let value_mutation = rng.gen_range(0..6) < 3;
+        if value_mutation && !layout.mutable.is_empty() {
+            mutate_value(trace, cycle, layout, rng);
+        } else {
+            let range = layout.cycle_range(cycle);
+            match rng.gen_range(0..3) {
+                // duplicate a cycle
+                0 => {
+                    let copy = trace.words[range.clone()].to_vec();
+                    trace.words.splice(range.start..range.start, copy);
+                }
+                // delete a cycle
+                1 => {
+                    trace.words.drain(range);
+                }
+                // splice with another trace from the corpus
+                _ => {
+                    let other = &corpus[rng.gen_range(0..corpus.len())];
+                    let other_start = rng.gen_range(0..=layout.cycles(other));
+                    trace.words.truncate(range.start);
+                    trace
+                        .words
+                        .extend_from_slice(&other.words[other_start * layout.words_per_cycle..]);
+                }
+            }
+        }
+    }
+}
+
+/// Changes the value of a random input in the given cycle.
+pub fn mutate_value(trace: &mut Trace, cycle: usize, layout: &InputLayout, rng: &mut impl Rng) {
+    if layout.mutable.is_empty() {
+        return;
+    }
+    let input = layout.mutable[rng.gen_range(0..layout.mutable.len())];
+    let width = layout.inputs[input].1;
+    let words = layout.value_mut(trace, cycle, input);
+    match rng.gen_range(0..3) {
+        // bit flip
+        0 => {
+            let bit = rng.gen_range(0..width);
+            words[(bit / Word::BITS) as usize] ^= 1 << (bit % Word::BITS);
+        }
+        // random value
+        1 => {
+            for word in words.iter_mut() {
+                *word = rng.next_u64();
+            }
+        }
+        // corner value
+        _ => {
+            words.fill(0);
+            if width <= Word::BITS {
+                let corners = corner_values(width);
+                words[0] = corners[rng.gen_range(0..corners.len())];
+            }
+        }
+    }
+    // make sure that the value fits the input width
+    let last = words.len() - 1;
+    words[last] &= mask(width - last as WidthInt * Word::BITS);
+}
+
+/// Coverage points: both outcomes of every `ite` condition as well as rising and
+/// falling edges of every state bit.
+pub struct Coverage {
+    conditions: Vec<ExprRef>,
+    states: Vec<(ExprRef, WidthInt)>,
+    /// number of state bits, without the unused bits of the last word of every state
+    state_bits: usize,
+    state_words: usize,
+}
+
+impl Coverage {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let mut todo = sys
+            .get_signals(|s| !s.labels.is_none())
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        for (_, state) in sys.states() {
+            todo.extend(state.next);
+        }
+        let mut visited = ExprMetaData::default();
+        let mut conditions = Vec::new();
+        while let Some(e) = todo.pop() {
+            if *visited.get(e) {
+                continue;
+            }
+            *visited.get_mut(e) = true;
+            let expr = ctx.get(e);
+            expr.for_each_child(|c| todo.push(*c));
+            if let Expr::BVIte { cond, .. } | Expr::ArrayIte { cond, .. } = expr {
+                if !cond.is_bv_lit(ctx) {
+                    conditions.push(*cond);
+                }
+            }
+        }
+        conditions.sort_unstable();
+        conditions.dedup();
+
+        let states = sys
+            .states()
+            .filter_map(|(_, s)| Some((s.symbol, s.symbol.get_bv_type(ctx)?)))
+            .collect::<Vec<_>>();
+        let state_bits = states.iter().map(|(_, w)| *w as usize).sum();
+        let state_words = states
+            .iter()
+            .map(|(_, w)| w.div_ceil(Word::BITS) as usize)
+            .sum();
+        Self {
+            conditions,
+            states,
+            state_bits,
+            state_words,
+        }
+    }
+
+    /// Makes sure that all conditions can be read from the simulator.
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        for cond in self.conditions.iter() {
+            make_observable(ctx, sys, *cond);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        2 * self.conditions.len() + 2 * self.state_bits
+    }
+
+    /// Current values of all states that we track.
+    pub fn state_values(&self, sim: &Interpreter) -> Vec<Word> {
+        let mut out = Vec::with_capacity(self.state_words);
+        for (state, _) in self.states.iter() {
+            out.extend_from_slice(sim.get(*state).unwrap().words());
+        }
+        out
+    }
+
+    /// Reports all coverage points that are reached in the current cycle.
+    /// `prev_states` contains the state values of the previous cycle and is updated.
+    pub fn record(&self, sim: &Interpreter, prev_states: &mut [Word], mut hit: impl FnMut(usize)) {
+        for (ii, cond) in self.conditions.iter().enumerate() {
+            let value = sim.get(*cond).unwrap().to_u64().unwrap() as usize;
+            hit(2 * ii + value);
+        }
+        let mut bit_base = 2 * self.conditions.len();
+        let mut offset = 0;
+        for (state, width) in self.states.iter() {
+            for (ii, word) in sim.get(*state).unwrap().words().iter().enumerate() {
+                let prev = prev_states[offset];
+                let (mut rose, mut fell) = (!prev & word, prev & !word);
+                while rose != 0 {
+                    hit(bit_base + 2 * rose.trailing_zeros() as usize);
+                    rose &= rose - 1;
+                }
+                while fell != 0 {
+                    hit(bit_base + 2 * fell.trailing_zeros() as usize + 1);
+                    fell &= fell - 1;
+                }
+                prev_states[offset] = *word;
+                offset += 1;
+                // bits beyond the width of the state are always zero
+                let bits = std::cmp::min(*width - ii as WidthInt * Word::BITS, Word::BITS);
+                bit_base += 2 * bits as usize;
+            }
+        }
+    }
+}
+
+/// Remembers which coverage points have been reached. The bitmap can be updated
+/// concurrently by all workers without taking a lock.
+pub struct CoverageMap {
+    covered: Vec<AtomicU64>,
+    len: usize,
+}
+
+impl CoverageMap {
+    pub fn new(len: usize) -> Self {
+        Self {
+            covered: (0..len.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true iff the point was not covered before by any worker.
+    pub fn insert(&self, point: usize) -> bool {
+        debug_assert!(point < self.len);
+        let word = &self.covered[point / 64];
+        let bit = 1u64 << (point % 64);
+        // most points are already covered, reading avoids contention on the cache line
+        if word.load(Ordering::Relaxed) & bit != 0 {
+            return false;
+        }
+        word.fetch_or(bit, Ordering::Relaxed) & bit == 0
+    }
+}
+
+/// Coverage map and corpus that are shared between all fuzzing workers.
+pub struct SharedCorpus {
+    pub map: CoverageMap,
+    /// traces that reached new coverage together with the worker that found them
+    traces: Mutex<Vec<(u64, Trace)>>,
+}
+
+impl SharedCorpus {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        Self {
+            map: CoverageMap::new(Coverage::new(ctx, sys).len()),
+            traces: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn push(&self, worker: u64, trace: Trace) {
+        self.traces.lock().unwrap().push((worker, trace));
+    }
+
+    /// Copies all traces that were found by other workers since `synced` into `corpus`.
+    /// Returns the new number of synchronized traces.
+    fn pull(&self, worker: u64, synced: usize, corpus: &mut Vec<Trace>) -> usize {
+        let traces = self.traces.lock().unwrap();
+        for (_, trace) in traces[synced..].iter().filter(|(w, _)| *w != worker) {
+            corpus.push(trace.clone());
+        }
+        traces.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::btor2;
+
+    #[test]
+    fn test_coverage_points() {
+        let (ctx, mut sys) = btor2::parse_file("inputs/reg_en.bad.btor").unwrap();
+        let coverage = Coverage::new(&ctx, &sys);
+        // two ite conditions per register and 32 bits per register, each with two outcomes
+        assert_eq!(coverage.len(), 2 * 2 * 2 + 2 * 2 * 32);
+        coverage.instrument(&ctx, &mut sys);
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut prev = coverage.state_values(&sim);
+        let a_en = sys.generate_name_to_ref(&ctx)["A.en"];
+        let a_d = sys.generate_name_to_ref(&ctx)["A.d"];
+        sim.set(a_en, ValueRef::new(&[1], 1));
+        sim.set(a_d, ValueRef::new(&[0b101], 32));
+        sim.update();
+        sim.step();
+        sim.update();
+        let map = CoverageMap::new(coverage.len());
+        let mut count = 0;
+        coverage.record(&sim, &mut prev, |p| count += map.insert(p) as usize);
+        // four conditions with one outcome each plus two bits that rose
+        assert_eq!(count, 4 + 2);
+        // nothing new when we record the same cycle again
+        coverage.record(&sim, &mut prev, |p| assert!(!map.insert(p)));
+    }
+
+    #[test]
+    fn test_shared_corpus_sync() {
+        let (ctx, sys) = btor2::parse_file("inputs/reg_en.bad.btor").unwrap();
+        let shared = SharedCorpus::new(&ctx, &sys);
+        let trace = |w| Trace { words: vec![w] };
+        shared.push(0, trace(1));
+        shared.push(1, trace(2));
+        let mut corpus = Vec::new();
+        let synced = shared.pull(0, 0, &mut corpus);
+        assert_eq!(synced, 2);
+        // traces found by the worker itself are not duplicated
+        assert_eq!(corpus.len(), 1);
+        assert_eq!(corpus[0].words, [2]);
+        shared.push(1, trace(3));
+        assert_eq!(shared.pull(0, synced, &mut corpus), 3);
+        assert_eq!(corpus.len(), 2);
+    }
+}
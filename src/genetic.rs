@@ -0,0 +1,250 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Genetic search that evolves input traces towards a bad state.
+
+use crate::distance::BadDistance;
+use crate::fuzz::{mutate, mutate_value, InputLayout, Trace};
+use crate::progress::Progress;
+use crate::random::{
+    check_for_bad_states, count_cycle, sample_k_max, GeneratorState, InputDriver, RandomOptions,
+};
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::{InitKind, Interpreter};
+use rand::Rng;
+
+const POPULATION_SIZE: usize = 32;
+/// Number of the fittest individuals that survive unchanged.
+const ELITE_SIZE: usize = 4;
+/// Number of individuals that compete for becoming a parent.
+const TOURNAMENT_SIZE: usize = 3;
+/// Probability of mutating a child after crossover.
+const MUTATION_PROB: f64 = 0.5;
+/// Probability of appending random cycles to a child, otherwise traces could never grow
+/// beyond the fittest cycle of their parents.
+const EXTEND_PROB: f64 = 0.25;
+
+pub fn genetic_testing(
+    mut ctx: Context,
+    mut sys: TransitionSystem,
+    opts: RandomOptions,
+    seed: u64,
+    progress: &mut Progress,
+) -> ModelCheckResult {
+    let distance = BadDistance::new(&mut ctx, &sys);
+    let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+    inputs.instrument(&ctx, &mut sys);
+    distance.instrument(&ctx, &mut sys);
+    progress.instrument(&ctx, &mut sys);
+    let layout = InputLayout::new(&ctx, &sys, &inputs);
+
+    // collect bad states
+    let bad_states = sys
+        .bad_states()
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+
+    // create simulator
+    let sim_ctx = ctx.clone();
+    let mut sim = Interpreter::new(&sim_ctx, &sys);
+    sim.init(InitKind::Zero);
+    let start_state = sim.take_snapshot();
+    let mut state_init = Vec::new();
+    for (_, state) in sys.states() {
+        state_init.extend_from_slice(sim.get(state.symbol).unwrap().words());
+    }
+
+    let mut runner = Runner {
+        ctx: &ctx,
+        inputs,
+        layout,
+        distance,
+        bad_states,
+        sim,
+        start_state,
+        state_init,
+        cycle_count: 0,
+        max_cycles: opts.max_cycles,
+        progress,
+    };
+    let mut gen = GeneratorState::new(seed);
+
+    // the first generation is generated randomly
+    let mut population: Vec<(Trace, f64)> = Vec::with_capacity(POPULATION_SIZE);
+    loop {
+        population.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let mut next = population
+            .iter()
+            .take(ELITE_SIZE)
+            .cloned()
+            .collect::<Vec<_>>();
+        while next.len() < POPULATION_SIZE {
+            let (trace, len) = if population.is_empty() {
+                (Trace::default(), sample_k_max(&mut gen.rng, &opts) + 1)
+            } else {
+                let a = select(&population, &mut gen.rng);
+                let b = select(&population, &mut gen.rng);
+                let mut child = crossover(&runner.layout, a, b, &mut gen.rng);
+                if gen.rng.gen_bool(MUTATION_PROB) {
+                    let parents = [a.clone(), b.clone()];
+                    mutate(&mut child, &parents, &runner.layout, &mut gen.rng);
+                }
+                // traces end in their fittest cycle, which is thus the most promising to change
+                let cycles = runner.layout.cycles(&child);
+                if cycles > 0 && gen.rng.gen_bool(MUTATION_PROB) {
+                    mutate_value(&mut child, cycles - 1, &runner.layout, &mut gen.rng);
+                }
+                let len = match runner.layout.cycles(&child) as StepInt {
+                    0 => sample_k_max(&mut gen.rng, &opts) + 1,
+                    len if gen.rng.gen_bool(EXTEND_PROB) => {
+                        let len = len + gen.rng.gen_range(1..=opts.small_k);
+                        std::cmp::min(len, opts.large_k + 1)
+                    }
+                    len => std::cmp::min(len, opts.large_k + 1),
+                };
+                (child, len)
+            };
+            match runner.run(&mut gen, &trace, len) {
+                Ok(individual) => next.push(individual),
+                Err(result) => return result,
+            }
+        }
+        population = next;
+    }
+}
+
+/// Picks the fittest out of a few random individuals.
+fn select<'p>(population: &'p [(Trace, f64)], rng: &mut impl Rng) -> &'p Trace {
+    let (trace, _) = (0..TOURNAMENT_SIZE)
+        .map(|_| &population[rng.gen_range(0..population.len())])
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap();
+    trace
+}
+
+/// Combines the first cycles of `a` with the remaining cycles of `b`.
+fn crossover(layout: &InputLayout, a: &Trace, b: &Trace, rng: &mut impl Rng) -> Trace {
+    let (a_cycles, b_cycles) = (layout.cycles(a), layout.cycles(b));
+    let cut = rng.gen_range(0..=std::cmp::min(a_cycles, b_cycles));
+    let mut words = a.words[..layout.cycle_range(cut).start].to_vec();
+    words.extend_from_slice(&b.words[layout.cycle_range(cut).start..]);
+    Trace { words }
+}
+
+struct Runner<'a, 'p> {
+    ctx: &'a Context,
+    inputs: InputDriver,
+    layout: InputLayout,
+    distance: BadDistance,
+    bad_states: Vec<ExprRef>,
+    sim: Interpreter<'a>,
+    start_state: u32,
+    state_init: Vec<Word>,
+    cycle_count: u64,
+    max_cycles: Option<u64>,
+    progress: &'p mut Progress,
+}
+
+impl Runner<'_, '_> {
+    /// Executes `len` cycles of the trace, generating missing cycles randomly.
+    /// Returns the executed trace up to its fittest cycle together with its fitness,
+    /// or the final result if we are done.
+    fn run(
+        &mut self,
+        gen: &mut GeneratorState,
+        trace: &Trace,
+        len: StepInt,
+    ) -> Result<(Trace, f64), ModelCheckResult> {
+        let sim = &mut self.sim;
+        sim.restore_snapshot(self.start_state);
+        self.inputs.start_trace(gen);
+        let mut executed = Trace::default();
+        let (mut fitness, mut fittest_cycle) = (-1.0f64, 0);
+        for k in 0..len {
+            let replayed = (k as usize) < self.layout.cycles(trace);
+            if replayed {
+                self.layout.apply(trace, k as usize, sim);
+            }
+            let ok = self.inputs.drive_cycle(
+                self.ctx,
+                gen,
+                k,
+                sim,
+                replayed,
+                &mut self.cycle_count,
+                self.max_cycles,
+            )?;
+            if !ok {
+                break;
+            }
+            self.inputs.observe(sim, gen);
+            self.progress.record(self.ctx, sim);
+            self.layout.record(sim, &mut executed);
+            let cycle_fitness = self.distance.fitness(self.ctx, sim);
+            if cycle_fitness > fitness {
+                (fitness, fittest_cycle) = (cycle_fitness, k as usize);
+            }
+
+            // check if we are in a bad state
+            let bads = check_for_bad_states(&self.bad_states, sim);
+            if !bads.is_empty() {
+                let wit = Witness {
+                    input_data: executed.words,
+                    state_init: self.state_init.clone(),
+                    k,
+                    failed_safety: bads,
+                };
+                return Err(ModelCheckResult::Sat(wit));
+            }
+
+            // advance the system
+            sim.step();
+            if count_cycle(&mut self.cycle_count, self.max_cycles) {
+                return Err(ModelCheckResult::Unknown);
+            }
+        }
+        // cycles after the fittest one do not contribute, thus mutating them would be wasted
+        executed
+            .words
+            .truncate(self.layout.cycle_range(fittest_cycle).end);
+        Ok((executed, fitness))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::random_testing;
+
+    #[test]
+    fn test_fitness_guides_search() {
+        // a random 32-bit value is virtually never equal to the magic constant
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let x = ctx.bv_symbol("x", 32);
+        sys.add_input(&ctx, x);
+        let magic = ctx.bv_lit(0xdead_beef, 32);
+        let bad = ctx.bv_equal(x, magic);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.small_k = 1;
+        opts.large_k = 1;
+        opts.input_heuristics = false;
+        opts.max_cycles = Some(50_000);
+        let mut progress = Progress::new(&mut ctx, &sys);
+        let result = random_testing(ctx.clone(), sys.clone(), opts.clone(), 0, &mut progress);
+        assert!(matches!(result, ModelCheckResult::Unknown));
+
+        // the hamming distance to the constant guides the bit flips
+        let mut progress = Progress::new(&mut ctx, &sys);
+        let ModelCheckResult::Sat(wit) = genetic_testing(ctx, sys, opts, 0, &mut progress) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.input_data[wit.k as usize], 0xdead_beef);
+    }
+}
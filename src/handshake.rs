@@ -0,0 +1,175 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Valid/ready handshakes: once the valid input is asserted, it needs to stay asserted
+// with stable data until the design signals that it is ready to accept the transfer.
+
+use patronus::ir::*;
+
+/// Handshake specified by signal names, `VALID,READY,DATA...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeOptions {
+    pub valid: String,
+    pub ready: String,
+    pub data: Vec<String>,
+}
+
+/// Parses a `VALID,READY,DATA...` specification.
+pub fn parse_handshake(src: &str) -> Result<HandshakeOptions, String> {
+    let names = src.split(',').map(|n| n.trim()).collect::<Vec<_>>();
+    match names.as_slice() {
+        [valid, ready, data @ ..] if !valid.is_empty() && !ready.is_empty() => {
+            Ok(HandshakeOptions {
+                valid: valid.to_string(),
+                ready: ready.to_string(),
+                data: data.iter().map(|d| d.to_string()).collect(),
+            })
+        }
+        _ => Err(format!(
+            "expected `VALID,READY,DATA...` in handshake `{src}`"
+        )),
+    }
+}
+
+/// Handshake resolved to the signals of a concrete system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    /// 1-bit input
+    pub valid: ExprRef,
+    /// 1-bit signal computed by the design
+    pub ready: ExprRef,
+    /// inputs that need to be stable while the transfer is pending
+    pub data: Vec<ExprRef>,
+}
+
+impl Handshake {
+    pub fn new(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        opts: &HandshakeOptions,
+    ) -> Result<Self, String> {
+        let input = |name: &str| {
+            sys.get_signals(|s| s.is_input())
+                .into_iter()
+                .map(|(e, _)| e)
+                .find(|e| e.get_symbol_name(ctx) == Some(name))
+                .ok_or_else(|| format!("unknown input `{name}`"))
+        };
+        let valid = input(&opts.valid)?;
+        let ready = find_design_signal(ctx, sys, &opts.ready)
+            .ok_or_else(|| format!("unknown signal `{}`", opts.ready))?;
+        for (name, signal) in [(&opts.valid, valid), (&opts.ready, ready)] {
+            if signal.get_bv_type(ctx) != Some(1) {
+                return Err(format!("`{name}` is not a 1-bit signal"));
+            }
+        }
+        let data = opts
+            .data
+            .iter()
+            .map(|d| input(d))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { valid, ready, data })
+    }
+
+    /// Inputs that are held while a transfer is pending.
+    pub fn inputs(&self) -> impl Iterator<Item = ExprRef> + '_ {
+        std::iter::once(self.valid).chain(self.data.iter().copied())
+    }
+}
+
+/// Looks up a named signal that is not an input.
+fn find_design_signal(ctx: &Context, sys: &TransitionSystem, name: &str) -> Option<ExprRef> {
+    sys.get_signals(|s| !s.is_input())
+        .into_iter()
+        .find(|(_, info)| info.name.is_some_and(|n| ctx.get(n) == name))
+        .map(|(e, _)| e)
+}
+
+/// Finds handshakes by naming convention: a 1-bit input `P_valid` together with a 1-bit
+/// design signal `P_ready`. All other inputs starting with `P_` are the data.
+/// Inputs named `P.valid` and `Pvalid` are recognized as well.
+pub fn infer_handshakes(ctx: &Context, sys: &TransitionSystem) -> Vec<Handshake> {
+    let inputs = sys
+        .get_signals(|s| s.is_input())
+        .into_iter()
+        .filter_map(|(e, _)| Some((e, e.get_symbol_name(ctx)?)))
+        .collect::<Vec<_>>();
+    let mut out = Vec::new();
+    for (valid, name) in inputs.iter() {
+        let Some(prefix) = name.strip_suffix("valid") else {
+            continue;
+        };
+        if valid.get_bv_type(ctx) != Some(1) {
+            continue;
+        }
+        let ready = find_design_signal(ctx, sys, &format!("{prefix}ready"))
+            .filter(|r| r.get_bv_type(ctx) == Some(1));
+        let Some(ready) = ready else {
+            continue;
+        };
+        // without a separator, the prefix is too unspecific to find the data
+        let data = if prefix.ends_with(['_', '.']) {
+            inputs
+                .iter()
+                .filter(|(e, n)| e != valid && n.starts_with(prefix))
+                .map(|(e, _)| *e)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        out.push(Handshake {
+            valid: *valid,
+            ready,
+            data,
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_infer() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let names = ["in_valid", "in_data", "in_last", "out_ready", "other"];
+        let [in_valid, in_data, in_last, out_ready, _] = names.map(|n| {
+            let width = if n == "in_data" { 8 } else { 1 };
+            let input = ctx.bv_symbol(n, width);
+            sys.add_input(&ctx, input);
+            input
+        });
+        // the design is ready whenever the output is ready
+        let in_ready = ctx.not(out_ready);
+        let in_ready = ctx.not(in_ready);
+        let name = ctx.add_node("in_ready");
+        sys.add_signal(
+            in_ready,
+            SignalKind::Node,
+            SignalLabels::output(),
+            Some(name),
+        );
+
+        let inferred = infer_handshakes(&ctx, &sys);
+        assert_eq!(
+            inferred,
+            [Handshake {
+                valid: in_valid,
+                ready: in_ready,
+                data: vec![in_data, in_last],
+            }]
+        );
+
+        let opts = parse_handshake("in_valid, in_ready, in_data").unwrap();
+        let declared = Handshake::new(&ctx, &sys, &opts).unwrap();
+        assert_eq!(declared.data, [in_data]);
+        assert!(parse_handshake("in_valid").is_err());
+        let opts = parse_handshake("in_data,in_ready").unwrap();
+        assert!(Handshake::new(&ctx, &sys, &opts).is_err());
+        let opts = parse_handshake("in_valid,other").unwrap();
+        assert!(Handshake::new(&ctx, &sys, &opts).is_err());
+    }
+}
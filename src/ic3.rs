@@ -0,0 +1,522 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Bit-level IC3 / property directed reachability. Every frame over-approximates the states
+// reachable in up to that many steps by blocking cubes of state bits. Once two frames are
+// the same, their clauses form an inductive invariant that excludes all bad states.
+
+use crate::bitblast::{check_supported, words, BitBlaster, Bits};
+use crate::certificate::Certificate;
+use crate::sat::{Lit, Solver};
+use crate::{ModelCheckResult, Witness};
+use patronus::ir::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Conjunction of literals over the current state bits.
+pub type Cube = Vec<Lit>;
+
+#[derive(Debug, Clone)]
+pub enum Ic3Result {
+    /// the bad states are unreachable, the invariant is the conjunction of the negated cubes
+    Safe(Vec<Cube>),
+    Unsafe(Witness),
+}
+
+/// A state that reaches a bad state, together with the inputs that lead towards it.
+#[derive(Debug, Clone)]
+struct Obligation {
+    cube: Cube,
+    state: Vec<Word>,
+    inputs: Vec<Word>,
+    /// the state that we move to, `None` if this state is bad
+    next: Option<usize>,
+    /// bad states that are reached, only set at the end of the trace
+    failed: Vec<usize>,
+}
+
+pub struct Ic3 {
+    /// transition relation and bad states over the current state bits
+    template: Solver,
+    /// initial states are asserted in the first frame
+    frames: Vec<Solver>,
+    /// cubes that are blocked in all frames from one up to the index
+    levels: Vec<Vec<Cube>>,
+    symbols: Vec<ExprRef>,
+    states: Vec<Bits>,
+    inputs: Vec<Bits>,
+    /// next state literal of every current state variable
+    next: HashMap<u32, Lit>,
+    bad_states: Vec<Lit>,
+    any_bad: Lit,
+}
+
+impl Ic3 {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Result<Self, String> {
+        check_supported(ctx, sys)?;
+        let mut solver = Solver::new();
+        let mut blaster = BitBlaster::new(&mut solver);
+        let mut values = HashMap::new();
+        let fresh = |solver: &mut Solver, values: &mut HashMap<ExprRef, Bits>, e: ExprRef| {
+            let bits = blaster.fresh(solver, e.get_bv_type(ctx).unwrap());
+            values.insert(e, bits.clone());
+            bits
+        };
+        let states = sys
+            .states()
+            .map(|(_, s)| fresh(&mut solver, &mut values, s.symbol))
+            .collect::<Vec<_>>();
+        let inputs = sys
+            .get_signals(|s| s.is_input())
+            .into_iter()
+            .map(|(e, _)| fresh(&mut solver, &mut values, e))
+            .collect::<Vec<_>>();
+
+        let mut next = HashMap::new();
+        for ((_, state), bits) in sys.states().zip(states.iter()) {
+            let next_bits = match state.next {
+                Some(n) => blaster.blast(ctx, &mut solver, &mut values, n),
+                None => blaster.fresh(&mut solver, bits.len() as WidthInt),
+            };
+            for (current, next_bit) in bits.iter().zip(next_bits) {
+                next.insert(current.var(), next_bit);
+            }
+        }
+        for (constraint, _) in sys.constraints() {
+            let bits = blaster.blast(ctx, &mut solver, &mut values, constraint);
+            solver.add_clause(&bits);
+        }
+        let bad_states = sys
+            .bad_states()
+            .into_iter()
+            .map(|(e, _)| blaster.blast(ctx, &mut solver, &mut values, e)[0])
+            .collect::<Vec<_>>();
+        let any_bad = blaster.or_all(&mut solver, &bad_states);
+
+        // initial states: every state with an init expression equals its value
+        let mut init = Vec::new();
+        for ((_, state), bits) in sys.states().zip(states.iter()) {
+            if let Some(e) = state.init {
+                let value = blaster.blast(ctx, &mut solver, &mut values, e);
+                init.push(blaster.equal(&mut solver, bits, &value));
+            }
+        }
+        let mut first = solver.clone();
+        for lit in init {
+            first.add_clause(&[lit]);
+        }
+
+        Ok(Self {
+            template: solver,
+            symbols: sys.states().map(|(_, s)| s.symbol).collect(),
+            frames: vec![first],
+            levels: vec![Vec::new()],
+            states,
+            inputs,
+            next,
+            bad_states,
+            any_bad,
+        })
+    }
+
+    pub fn check(&mut self) -> Ic3Result {
+        if self.frames[0].solve(&[self.any_bad]) {
+            let bad = self.bad_obligation(0);
+            return Ic3Result::Unsafe(self.witness(&[bad], 0));
+        }
+        self.add_frame();
+        loop {
+            let k = self.frames.len() - 1;
+            while self.frames[k].solve(&[self.any_bad]) {
+                let bad = self.bad_obligation(k);
+                if let Some(wit) = self.block(bad, k) {
+                    return Ic3Result::Unsafe(wit);
+                }
+            }
+            self.add_frame();
+            if let Some(invariant) = self.propagate() {
+                return Ic3Result::Safe(invariant);
+            }
+        }
+    }
+
+    /// Expresses an invariant over the state symbols.
+    pub fn invariant_expr(&self, ctx: &mut Context, cubes: &[Cube]) -> ExprRef {
+        let mut bits = HashMap::new();
+        for (symbol, state) in self.symbols.iter().zip(self.states.iter()) {
+            for (ii, bit) in state.iter().enumerate() {
+                bits.insert(bit.var(), (*symbol, ii as WidthInt));
+            }
+        }
+        let clauses = cubes
+            .iter()
+            .map(|cube| {
+                let lits = cube
+                    .iter()
+                    .map(|lit| {
+                        let (symbol, bit) = bits[&lit.var()];
+                        let value = ctx.slice(symbol, bit, bit);
+                        // the clause is the negated cube
+                        if lit.is_negated() {
+                            value
+                        } else {
+                            ctx.not(value)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                lits.into_iter().reduce(|a, b| ctx.or(a, b)).unwrap()
+            })
+            .collect::<Vec<_>>();
+        clauses
+            .into_iter()
+            .reduce(|a, b| ctx.and(a, b))
+            .unwrap_or_else(|| ctx.one(1))
+    }
+
+    fn add_frame(&mut self) {
+        self.frames.push(self.template.clone());
+        self.levels.push(Vec::new());
+    }
+
+    fn primed(&self, lit: Lit) -> Lit {
+        let next = self.next[&lit.var()];
+        if lit.is_negated() {
+            !next
+        } else {
+            next
+        }
+    }
+
+    /// Reads the current state and inputs from the last satisfying assignment of a frame.
+    fn obligation(&self, frame: usize) -> Obligation {
+        let solver = &self.frames[frame];
+        let cube = self
+            .states
+            .iter()
+            .flatten()
+            .map(|b| Lit::new(b.var(), !solver.value(*b)))
+            .collect();
+        let read = |bits: &[Bits]| bits.iter().flat_map(|b| words(solver, b)).collect();
+        Obligation {
+            cube,
+            state: read(&self.states),
+            inputs: read(&self.inputs),
+            next: None,
+            failed: Vec::new(),
+        }
+    }
+
+    fn bad_obligation(&self, frame: usize) -> Obligation {
+        let mut out = self.obligation(frame);
+        let solver = &self.frames[frame];
+        out.failed = (0..self.bad_states.len())
+            .filter(|ii| solver.value(self.bad_states[*ii]))
+            .collect();
+        out
+    }
+
+    fn intersects_init(&mut self, cube: &[Lit]) -> bool {
+        self.frames[0].solve(cube)
+    }
+
+    /// Checks whether the cube can be reached in one step from a state in `frame` that is not
+    /// in the cube itself. Returns the predecessor, or the part of the cube that is unreachable.
+    fn predecessor(&mut self, frame: usize, cube: &[Lit]) -> Result<Cube, Obligation> {
+        let primed = cube.iter().map(|l| self.primed(*l)).collect::<Vec<_>>();
+        let solver = &mut self.frames[frame];
+        let activate = solver.new_var();
+        let mut outside = vec![!activate];
+        outside.extend(cube.iter().map(|l| !*l));
+        solver.add_clause(&outside);
+        let mut assumptions = vec![activate];
+        assumptions.extend(primed.iter().copied());
+        let reachable = solver.solve(&assumptions);
+        let result = if reachable {
+            Err(self.obligation(frame))
+        } else {
+            let failed = self.frames[frame]
+                .failed_assumptions()
+                .iter()
+                .copied()
+                .collect::<HashSet<_>>();
+            Ok(cube
+                .iter()
+                .zip(primed.iter())
+                .filter(|(_, p)| failed.contains(*p))
+                .map(|(l, _)| *l)
+                .collect())
+        };
+        self.frames[frame].add_clause(&[!activate]);
+        result
+    }
+
+    /// Adds literals of the cube back to the core until it excludes all initial states.
+    fn exclude_init(&mut self, cube: &[Lit], core: Cube) -> Cube {
+        let mut out = if core.is_empty() { cube.to_vec() } else { core };
+        for lit in cube.iter() {
+            if !self.intersects_init(&out) {
+                break;
+            }
+            if !out.contains(lit) {
+                out.push(*lit);
+            }
+        }
+        out
+    }
+
+    /// Drops literals from a cube that is unreachable from `frame - 1`, as long as it stays so.
+    fn generalize(&mut self, frame: usize, cube: &[Lit], core: Cube) -> Cube {
+        let mut out = self.exclude_init(cube, core);
+        let mut ii = 0;
+        while ii < out.len() {
+            let mut candidate = out.clone();
+            candidate.remove(ii);
+            if candidate.is_empty() || self.intersects_init(&candidate) {
+                ii += 1;
+                continue;
+            }
+            match self.predecessor(frame - 1, &candidate) {
+                Ok(core) => out = self.exclude_init(&candidate, core),
+                Err(_) => ii += 1,
+            }
+        }
+        out
+    }
+
+    fn add_blocked(&mut self, level: usize, cube: Cube) {
+        let clause = cube.iter().map(|l| !*l).collect::<Vec<_>>();
+        for frame in self.frames[1..=level].iter_mut() {
+            frame.add_clause(&clause);
+        }
+        self.levels[level].push(cube);
+    }
+
+    /// Blocks a bad state in frame `k`, returns a witness if it is reachable.
+    fn block(&mut self, bad: Obligation, k: usize) -> Option<Witness> {
+        let mut obligations = vec![bad];
+        let mut todo = BinaryHeap::from([Reverse((k, 0))]);
+        while let Some(Reverse((frame, index))) = todo.pop() {
+            let cube = obligations[index].cube.clone();
+            if self.intersects_init(&cube) {
+                return Some(self.witness(&obligations, index));
+            }
+            // the cube might have been blocked as part of a more general one
+            if !self.frames[frame].solve(&cube) {
+                continue;
+            }
+            match self.predecessor(frame - 1, &cube) {
+                Err(mut pred) => {
+                    pred.next = Some(index);
+                    obligations.push(pred);
+                    todo.push(Reverse((frame - 1, obligations.len() - 1)));
+                    todo.push(Reverse((frame, index)));
+                }
+                Ok(core) => {
+                    let cube = self.generalize(frame, &cube, core);
+                    // block the cube in as many frames as possible
+                    let mut level = frame;
+                    while level < k && self.predecessor(level, &cube).is_ok() {
+                        level += 1;
+                    }
+                    self.add_blocked(level, cube);
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves blocked cubes to later frames. Returns an invariant once two frames are equal.
+    fn propagate(&mut self) -> Option<Vec<Cube>> {
+        let k = self.frames.len() - 1;
+        for level in 1..k {
+            for cube in std::mem::take(&mut self.levels[level]) {
+                if self.predecessor(level, &cube).is_ok() {
+                    let clause = cube.iter().map(|l| !*l).collect::<Vec<_>>();
+                    self.frames[level + 1].add_clause(&clause);
+                    self.levels[level + 1].push(cube);
+                } else {
+                    self.levels[level].push(cube);
+                }
+            }
+            if self.levels[level].is_empty() {
+                return Some(self.levels[level + 1..].concat());
+            }
+        }
+        None
+    }
+
+    fn witness(&self, obligations: &[Obligation], start: usize) -> Witness {
+        let mut input_data = Vec::new();
+        let mut k = 0;
+        let mut current = &obligations[start];
+        input_data.extend_from_slice(&current.inputs);
+        while let Some(next) = current.next {
+            current = &obligations[next];
+            input_data.extend_from_slice(&current.inputs);
+            k += 1;
+        }
+        Witness {
+            input_data,
+            state_init: obligations[start].state.clone(),
+            k,
+            failed_safety: current.failed.clone(),
+        }
+    }
+}
+
+/// Proves that the bad states are unreachable or finds a counterexample.
+pub fn ic3(ctx: &Context, sys: &TransitionSystem) -> ModelCheckResult {
+    if sys.bad_states().is_empty() {
+        return ModelCheckResult::Unknown;
+    }
+    let mut engine = match Ic3::new(ctx, sys) {
+        Ok(engine) => engine,
+        Err(e) => {
+            println!("IC3: {e}");
+            return ModelCheckResult::Unknown;
+        }
+    };
+    match engine.check() {
+        Ic3Result::Unsafe(wit) => ModelCheckResult::Sat(wit),
+        Ic3Result::Safe(cubes) => {
+            let mut ctx = ctx.clone();
+            let invariant = engine.invariant_expr(&mut ctx, &cubes);
+            ModelCheckResult::UnSat(Some(Certificate::invariant(&ctx, sys, invariant)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmc::bmc;
+    use crate::StepInt;
+    use rand::{Rng, SeedableRng};
+
+    fn steps(result: &ModelCheckResult) -> Option<StepInt> {
+        match result {
+            ModelCheckResult::Sat(wit) => Some(wit.k),
+            _ => None,
+        }
+    }
+
+    /// Counter that increments when enabled and wraps around after `max`.
+    fn counter(ctx: &mut Context, max: u64, bad_at: u64) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let state = sys.add_state(ctx, count);
+        let zero = ctx.zero(4);
+        let one = ctx.one(4);
+        let max = ctx.bv_lit(max, 4);
+        let at_max = ctx.bv_equal(count, max);
+        let inc = ctx.add(count, one);
+        let wrapped = ctx.bv_ite(at_max, zero, inc);
+        let next = ctx.bv_ite(en, wrapped, count);
+        sys.modify_state(state, |s| {
+            s.next = Some(next);
+            s.init = Some(zero);
+        });
+        let bad_at = ctx.bv_lit(bad_at, 4);
+        let bad = ctx.bv_equal(count, bad_at);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+        sys
+    }
+
+    /// Random expression over the symbols, all of width 3.
+    fn random_expr(
+        ctx: &mut Context,
+        rng: &mut impl Rng,
+        symbols: &[ExprRef],
+        depth: u32,
+    ) -> ExprRef {
+        if depth == 0 || rng.gen_bool(0.3) {
+            return if rng.gen_bool(0.8) {
+                symbols[rng.gen_range(0..symbols.len())]
+            } else {
+                ctx.bv_lit(rng.gen_range(0..8), 3)
+            };
+        }
+        let a = random_expr(ctx, rng, symbols, depth - 1);
+        let b = random_expr(ctx, rng, symbols, depth - 1);
+        match rng.gen_range(0..7) {
+            0 => ctx.add(a, b),
+            1 => ctx.xor(a, b),
+            2 => ctx.and(a, b),
+            3 => ctx.sub(a, b),
+            4 => ctx.mul(a, b),
+            5 => ctx.shift_right(a, b),
+            _ => {
+                let cond = ctx.greater(a, b);
+                let c = random_expr(ctx, rng, symbols, depth - 1);
+                ctx.bv_ite(cond, c, a)
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_with_bmc() {
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        let (mut safe, mut unsafe_) = (0, 0);
+        for _ in 0..60 {
+            let mut ctx = Context::default();
+            let mut sys = TransitionSystem::new("test".to_string());
+            let input = ctx.bv_symbol("i", 3);
+            sys.add_input(&ctx, input);
+            let a = ctx.bv_symbol("a", 3);
+            let b = ctx.bv_symbol("b", 3);
+            let symbols = [input, a, b];
+            for symbol in [a, b] {
+                let state = sys.add_state(&ctx, symbol);
+                let next = random_expr(&mut ctx, &mut rng, &symbols, 3);
+                let init = ctx.bv_lit(rng.gen_range(0..8), 3);
+                sys.modify_state(state, |s| {
+                    s.next = Some(next);
+                    s.init = Some(init);
+                });
+            }
+            let target = ctx.bv_lit(rng.gen_range(0..8), 3);
+            let at_target = ctx.bv_equal(a, target);
+            let other = ctx.bv_lit(rng.gen_range(0..8), 3);
+            let b_other = ctx.bv_equal(b, other);
+            let bad = ctx.and(at_target, b_other);
+            sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+            // with 64 states, every reachable state is reached in fewer than 64 steps
+            let expected = steps(&bmc(&ctx, &sys, 64, false));
+            let actual = steps(&ic3(&ctx, &sys));
+            assert_eq!(actual, expected, "{}", sys.serialize_to_str(&ctx));
+            if expected.is_some() {
+                unsafe_ += 1;
+            } else {
+                safe += 1;
+            }
+        }
+        assert!(safe > 5 && unsafe_ > 5, "{safe} {unsafe_}");
+    }
+
+    #[test]
+    fn test_ic3() {
+        let mut ctx = Context::default();
+        // never gets past 9
+        let sys = counter(&mut ctx, 9, 12);
+        let Ic3Result::Safe(invariant) = Ic3::new(&ctx, &sys).unwrap().check() else {
+            panic!("expected a proof");
+        };
+        assert!(!invariant.is_empty());
+
+        // the shortest counterexample takes 6 steps, same as for BMC
+        let sys = counter(&mut ctx, 9, 6);
+        let result = ic3(&ctx, &sys);
+        assert_eq!(steps(&result), Some(6));
+        assert_eq!(steps(&bmc(&ctx, &sys, 10, false)), Some(6));
+        let ModelCheckResult::Sat(wit) = result else {
+            unreachable!()
+        };
+        assert_eq!(wit.state_init, [0]);
+        assert_eq!(wit.input_data[..6], [1; 6]);
+        assert_eq!(wit.failed_safety, [0]);
+    }
+}
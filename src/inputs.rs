@@ -0,0 +1,203 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// heuristics to classify inputs by the role they play in the design
+
+use patronus::ir::*;
+use std::collections::HashSet;
+
+/// How deep we look into nested `ite` expressions when searching for reset-like inputs.
+const MAX_ITE_DEPTH: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Input that cannot influence any bad state or constraint, e.g., the clock in
+    /// a BTOR2 file generated by yosys.
+    Clock,
+    /// Input that forces registers to a constant value while it is active. Either all
+    /// registers, or the input needs to be named like a reset.
+    Reset {
+        active_low: bool,
+        forced_states: usize,
+    },
+    Data,
+}
+
+impl std::fmt::Display for InputKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputKind::Clock => write!(f, "clock"),
+            InputKind::Reset {
+                active_low,
+                forced_states,
+            } => {
+                let polarity = if *active_low { "low" } else { "high" };
+                write!(
+                    f,
+                    "reset (active {polarity}, forces {forced_states} states)"
+                )
+            }
+            InputKind::Data => write!(f, "data"),
+        }
+    }
+}
+
+/// Classifies every input of the system.
+pub fn classify_inputs(ctx: &Context, sys: &TransitionSystem) -> Vec<(ExprRef, InputKind)> {
+    // inputs that can influence a property
+    let roots = sys
+        .get_signals(|s| s.labels.is_bad() || s.labels.is_constraint())
+        .into_iter()
+        .map(|(e, _)| e);
+    let mut relevant = HashSet::new();
+    for root in roots {
+        relevant.extend(cone_of_influence(ctx, sys, root));
+    }
+
+    // count how many states each input forces to a constant
+    let num_next = sys.states().filter(|(_, s)| s.next.is_some()).count();
+    let mut forced = Vec::new();
+    for (_, state) in sys.states() {
+        if let Some(next) = state.next {
+            let mut found = Vec::new();
+            find_resets(ctx, next, MAX_ITE_DEPTH, &mut found);
+            found.sort_unstable();
+            found.dedup();
+            forced.extend(found);
+        }
+    }
+
+    sys.get_signals(|s| s.is_input())
+        .into_iter()
+        .map(|(input, _)| {
+            let kind = if !relevant.contains(&input) {
+                InputKind::Clock
+            } else {
+                // an ordinary mux select only forces some of the states
+                let is_reset = |kind: &InputKind| match kind {
+                    InputKind::Reset { forced_states, .. } => {
+                        *forced_states == num_next
+                            || has_reset_name(input.get_symbol_name(ctx).unwrap())
+                    }
+                    _ => false,
+                };
+                reset_kind(&forced, input)
+                    .filter(is_reset)
+                    .unwrap_or(InputKind::Data)
+            };
+            (input, kind)
+        })
+        .collect()
+}
+
+fn reset_kind(forced: &[(ExprRef, bool)], input: ExprRef) -> Option<InputKind> {
+    let count = |active_low: bool| forced.iter().filter(|f| **f == (input, active_low)).count();
+    let (high, low) = (count(false), count(true));
+    if high == 0 && low == 0 {
+        None
+    } else {
+        Some(InputKind::Reset {
+            active_low: low > high,
+            forced_states: std::cmp::max(high, low),
+        })
+    }
+}
+
+/// Checks the last component of a hierarchical name for a `_` separated `rst` or `reset`
+/// token, names like `first` or `awburst` only happen to contain these letters.
+fn has_reset_name(name: &str) -> bool {
+    let name = name.rsplit('.').next().unwrap().to_ascii_lowercase();
+    name.starts_with("reset")
+        || name.split('_').any(|token| {
+            matches!(
+                token,
+                "rst" | "rstn" | "nrst" | "reset" | "resetn" | "areset" | "aresetn"
+            )
+        })
+}
+
+/// Searches for `ite(input, const, _)` and `ite(input, _, const)` patterns.
+fn find_resets(ctx: &Context, e: ExprRef, depth: u32, out: &mut Vec<(ExprRef, bool)>) {
+    if depth == 0 {
+        return;
+    }
+    if let Expr::BVIte { cond, tru, fals } = *ctx.get(e) {
+        let (input, inverted) = match *ctx.get(cond) {
+            Expr::BVNot(inner, 1) => (inner, true),
+            _ => (cond, false),
+        };
+        if input.is_symbol(ctx) {
+            if tru.is_bv_lit(ctx) {
+                out.push((input, inverted));
+            }
+            if fals.is_bv_lit(ctx) {
+                out.push((input, !inverted));
+            }
+        }
+        find_resets(ctx, tru, depth - 1, out);
+        find_resets(ctx, fals, depth - 1, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::btor2;
+
+    #[test]
+    fn test_classify_reg_en() {
+        let (mut ctx, mut sys) = btor2::parse_file("inputs/reg_en.bad.btor").unwrap();
+        simplify_expressions(&mut ctx, &mut sys);
+        let kinds = classify_inputs(&ctx, &sys)
+            .into_iter()
+            .map(|(e, k)| (e.get_symbol_name(&ctx).unwrap().to_string(), k))
+            .collect::<Vec<_>>();
+        let kind_of = |name: &str| kinds.iter().find(|(n, _)| n == name).unwrap().1;
+        assert_eq!(kind_of("clk"), InputKind::Clock);
+        assert_eq!(
+            kind_of("A.rst"),
+            InputKind::Reset {
+                active_low: false,
+                forced_states: 1
+            }
+        );
+        assert_eq!(kind_of("A.d"), InputKind::Data);
+        // the enable selects between two non-constant values
+        assert_eq!(kind_of("B.en"), InputKind::Data);
+    }
+
+    #[test]
+    fn test_mux_select_is_not_a_reset() {
+        // `sel` forces only one of two states to a constant, `clear` forces both
+        let src = "1 sort bitvec 1\n2 sort bitvec 8\n3 input 1 sel\n4 input 1 clear\n\
+                   5 input 2 d\n6 state 2 a\n7 state 2 b\n8 zero 2\n9 ite 2 3 8 5\n\
+                   10 ite 2 4 8 9\n11 next 2 6 10\n12 ite 2 4 8 6\n13 next 2 7 12\n\
+                   14 eq 1 6 7\n15 bad 14\n";
+        let mut ctx = Context::default();
+        let sys = btor2::parse_str(&mut ctx, src, None).unwrap();
+        let kinds = classify_inputs(&ctx, &sys);
+        let kind_of = |name: &str| {
+            let found = kinds
+                .iter()
+                .find(|(e, _)| e.get_symbol_name(&ctx) == Some(name));
+            found.unwrap().1
+        };
+        assert_eq!(kind_of("sel"), InputKind::Data);
+        assert_eq!(
+            kind_of("clear"),
+            InputKind::Reset {
+                active_low: false,
+                forced_states: 2
+            }
+        );
+        assert!(has_reset_name("top.core.rst_n"));
+        assert!(has_reset_name("core_rst"));
+        assert!(has_reset_name("resetn"));
+        assert!(has_reset_name("s_axi_aresetn"));
+        assert!(!has_reset_name("first.sel"));
+        assert!(!has_reset_name("first"));
+        assert!(!has_reset_name("worst_case"));
+        assert!(!has_reset_name("s_axi_awburst"));
+    }
+}
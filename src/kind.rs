@@ -0,0 +1,141 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// k-induction: the base case is bounded model checking, the inductive step shows that
+// no loop-free path of k good states can be followed by a bad state.
+
+use crate::bmc::Bmc;
+use crate::certificate::Certificate;
+use crate::sat::Solver;
+use crate::unroll::Unroller;
+use crate::{ModelCheckResult, StepInt};
+use patronus::ir::*;
+
+/// Base case and inductive step for increasing depths.
+pub struct KInduction {
+    base: Bmc,
+    solver: Solver,
+    /// the inductive step starts in an arbitrary state
+    step: Unroller,
+}
+
+impl KInduction {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Result<Self, String> {
+        let base = Bmc::new(ctx, sys)?;
+        let mut solver = Solver::new();
+        let step = Unroller::new(ctx, sys, &mut solver, false)?;
+        Ok(Self { base, solver, step })
+    }
+
+    /// Checks depth `k`, which needs to increase by one with every call. Returns `UnSat` once
+    /// the bad states are unreachable.
+    pub fn check(&mut self, ctx: &Context, k: usize) -> ModelCheckResult {
+        if let Some(wit) = self.base.check(ctx, k) {
+            return ModelCheckResult::Sat(wit);
+        }
+        let (solver, step) = (&mut self.solver, &mut self.step);
+        step.add_frame(ctx, solver);
+        if k > 0 {
+            let bad = step.any_bad(ctx, solver, k - 1);
+            solver.add_clause(&[!bad]);
+        }
+        // simple path: all states are different
+        for other in 0..k {
+            let same = step.same_states(solver, other, k);
+            solver.add_clause(&[!same]);
+        }
+        let bad = step.any_bad(ctx, solver, k);
+        if solver.solve(&[bad]) {
+            ModelCheckResult::Unknown
+        } else {
+            ModelCheckResult::UnSat(Some(Certificate::KInduction(k as StepInt)))
+        }
+    }
+}
+
+/// Tries to prove that no bad state is reachable by induction over up to `max_k` steps.
+pub fn k_induction(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    max_k: StepInt,
+    verbose: bool,
+) -> ModelCheckResult {
+    if sys.bad_states().is_empty() {
+        return ModelCheckResult::Unknown;
+    }
+    let mut engine = match KInduction::new(ctx, sys) {
+        Ok(engine) => engine,
+        Err(e) => {
+            println!("k-induction: {e}");
+            return ModelCheckResult::Unknown;
+        }
+    };
+    for k in 0..=max_k as usize {
+        match engine.check(ctx, k) {
+            ModelCheckResult::Unknown => {}
+            result => return result,
+        }
+    }
+    if verbose {
+        println!("k-induction: not inductive for k <= {max_k}");
+    }
+    ModelCheckResult::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_simple_path() {
+        // state 1 can stay forever or move to the bad state 3, but it is never reached
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let leave = ctx.bv_symbol("leave", 1);
+        sys.add_input(&ctx, leave);
+        let x = ctx.bv_symbol("x", 2);
+        let state = sys.add_state(&ctx, x);
+        let [zero, one, three] = [0, 1, 3].map(|v| ctx.bv_lit(v, 2));
+        let in_one = ctx.bv_equal(x, one);
+        let from_one = ctx.bv_ite(leave, three, one);
+        let next = ctx.bv_ite(in_one, from_one, x);
+        sys.modify_state(state, |s| {
+            s.next = Some(next);
+            s.init = Some(zero);
+        });
+        let bad = ctx.bv_equal(x, three);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        assert!(matches!(
+            k_induction(&ctx, &sys, 1, false),
+            ModelCheckResult::Unknown
+        ));
+        assert!(matches!(
+            k_induction(&ctx, &sys, 2, false),
+            ModelCheckResult::UnSat(Some(Certificate::KInduction(2)))
+        ));
+
+        // starting in state 1 makes the bad state reachable
+        sys.modify_state(state, |s| s.init = Some(one));
+        let ModelCheckResult::Sat(wit) = k_induction(&ctx, &sys, 2, false) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.k, 1);
+        assert_eq!(wit.input_data[0], 1);
+    }
+
+    #[test]
+    fn test_anonymous_input() {
+        // anonymous inputs are only replaced with zero for the testing workers
+        let mut ctx = Context::default();
+        let sys =
+            patronus::btor2::parse_str(&mut ctx, "1 sort bitvec 1\n2 input 1\n3 bad 2\n", None)
+                .unwrap();
+        let ModelCheckResult::Sat(wit) = k_induction(&ctx, &sys, 2, false) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.k, 0);
+        assert_eq!(wit.input_data, [1]);
+    }
+}
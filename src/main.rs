@@ -2,24 +2,52 @@
 // released under MIT License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
+mod bdd;
+mod bitblast;
+mod bmc;
+mod certificate;
+mod cmplog;
 mod constraints;
+mod dictionary;
+mod distance;
+mod explicit;
+mod explore;
+mod fuzz;
+mod genetic;
+mod handshake;
+mod ic3;
+mod inputs;
+mod kind;
+mod markov;
+mod prefix;
+mod progress;
 mod random;
+mod reach;
+mod sat;
+mod schedule;
+mod stimulus;
+mod temporal;
+mod unroll;
+mod vcd;
 
-use clap::{arg, Parser};
+use clap::Parser;
 use patronus::btor2::DEFAULT_INPUT_PREFIX;
 use patronus::ir::*;
 use patronus::*;
 use random::*;
 use std::borrow::Cow;
 use std::fmt::{Debug, Formatter};
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(name = "patron")]
 #[command(author = "Kevin Laeufer <laeufer@cornell.edu>")]
 #[command(version)]
 #[command(about = "Tries to find a witness that shows how to get to a bad state.", long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long)]
     verbose: bool,
     #[arg(long)]
@@ -28,8 +56,207 @@ struct Args {
     show_system: bool,
     #[arg(long)]
     max_cycles: Option<u64>,
-    #[arg(value_name = "BTOR2", index = 1)]
-    filename: String,
+    #[arg(
+        long,
+        help = "use coverage guided fuzzing instead of pure random testing"
+    )]
+    fuzz: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["fuzz", "explore", "genetic"],
+        help = "learn which trace lengths reach new coverage or get closer to a bad state"
+    )]
+    adaptive_k: bool,
+    #[arg(
+        long,
+        help = "hold some inputs constant or change them rarely, picked anew for every trace"
+    )]
+    swarm: bool,
+    #[arg(
+        long,
+        value_name = "PROB|NAME=PROB",
+        help = "probability of an input keeping its previous value, for all inputs or a single one"
+    )]
+    dwell: Vec<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["fuzz", "explore", "genetic"],
+        help = "learn for every input how likely it should keep its previous value, only supported by pure random testing"
+    )]
+    learn_dwell: bool,
+    #[arg(
+        long,
+        conflicts_with = "fuzz",
+        help = "start random traces from rarely visited states instead of always from the initial state"
+    )]
+    explore: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["fuzz", "explore"],
+        help = "evolve input traces that get closer and closer to a bad state"
+    )]
+    genetic: bool,
+    #[arg(
+        long,
+        value_name = "PROPERTY",
+        help = "temporal environment assumption, e.g., `valid && !ready |=> valid`"
+    )]
+    assume: Vec<String>,
+    #[arg(
+        long = "assert",
+        value_name = "PROPERTY",
+        help = "temporal safety assertion, e.g., `req |-> ##[1:4] ack`"
+    )]
+    assertions: Vec<String>,
+    #[arg(
+        long,
+        value_name = "INPUT",
+        help = "1-bit reset input driven at the start of every trace"
+    )]
+    reset: Option<String>,
+    #[arg(
+        long,
+        requires = "reset",
+        help = "the reset is asserted by driving a zero"
+    )]
+    reset_active_low: bool,
+    #[arg(
+        long,
+        requires = "reset",
+        default_value_t = 1,
+        help = "number of cycles the reset is asserted for"
+    )]
+    reset_cycles: u64,
+    #[arg(
+        long,
+        requires = "reset",
+        default_value_t = 0.0,
+        help = "probability of asserting the reset after the initial reset cycles"
+    )]
+    reset_prob: f64,
+    #[arg(
+        long,
+        help = "randomize clock-like and reset-like inputs like any other input"
+    )]
+    no_input_heuristics: bool,
+    #[arg(
+        long,
+        value_name = "NAME=DIST",
+        help = "input value distribution, e.g., `A.d=range(0, 15) hold 4`"
+    )]
+    stimulus: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "file with one input value distribution per line"
+    )]
+    stimulus_file: Option<String>,
+    #[arg(
+        long,
+        value_name = "VCD",
+        help = "example stimulus trace to learn a model of the input traffic from"
+    )]
+    stimulus_vcd: Vec<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "clock to sample VCD files at, every time step is a cycle otherwise"
+    )]
+    vcd_clock: Option<String>,
+    #[arg(
+        long,
+        value_name = "VALID,READY,DATA...",
+        help = "hold the valid and data inputs of a handshake until the design is ready"
+    )]
+    handshake: Vec<String>,
+    #[arg(
+        long,
+        help = "find handshakes by their names, e.g., `in_valid`, `in_ready` and `in_data`"
+    )]
+    infer_handshakes: bool,
+    #[arg(
+        long,
+        value_name = "WITNESS|VCD",
+        conflicts_with_all = ["fuzz", "explore", "genetic"],
+        help = "replay a BTOR2 witness or VCD once and start all random traces from where it ends"
+    )]
+    prefix: Option<String>,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "probability of driving an input with a corner value like zero or all-ones"
+    )]
+    corner_prob: f64,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "probability of driving an input with a constant mined from the design"
+    )]
+    dictionary_prob: f64,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "probability of driving an input with a comparison operand observed during simulation"
+    )]
+    cmplog_prob: f64,
+    #[arg(
+        long,
+        value_name = "K",
+        help = "also run bounded model checking for up to K steps"
+    )]
+    bmc: Option<StepInt>,
+    #[arg(
+        long,
+        value_name = "K",
+        help = "also try to prove that the bad states are unreachable by k-induction of up to K steps"
+    )]
+    k_induction: Option<StepInt>,
+    #[arg(
+        long,
+        help = "also try to prove that the bad states are unreachable with IC3"
+    )]
+    ic3: bool,
+    #[arg(
+        long,
+        help = "also visit every reachable state, only feasible for designs with few state and input bits"
+    )]
+    explicit_state: bool,
+    #[arg(
+        long,
+        help = "also compute the reachable states with binary decision diagrams"
+    )]
+    bdd: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "write a certificate to FILE when the bad states are proven unreachable"
+    )]
+    certificate: Option<String>,
+    #[arg(value_name = "BTOR2", index = 1, required = true)]
+    filename: Option<String>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    #[command(about = "Checks a certificate that was written with `--certificate`.")]
+    CheckProof {
+        #[arg(value_name = "BTOR2")]
+        filename: String,
+        #[arg(value_name = "CERTIFICATE")]
+        certificate: String,
+        #[arg(
+            long,
+            value_name = "PROPERTY",
+            help = "temporal environment assumption that the proof was made under"
+        )]
+        assume: Vec<String>,
+        #[arg(
+            long = "assert",
+            value_name = "PROPERTY",
+            help = "temporal safety assertion that was proven"
+        )]
+        assertions: Vec<String>,
+    },
 }
 
 static RANDOM_OPTS: RandomOptions = RandomOptions {
@@ -37,14 +264,43 @@ static RANDOM_OPTS: RandomOptions = RandomOptions {
     large_k: 10_000,
     large_k_prob: 0.5,
     max_cycles: None,
+    reset: None,
+    input_heuristics: true,
+    reset_like_prob: 0.05,
+    stimulus: Vec::new(),
+    corner_prob: 0.0,
+    dictionary_prob: 0.0,
+    cmplog_prob: 0.0,
+    adaptive_k: false,
+    swarm: false,
+    dwell_prob: 0.0,
+    dwell: Vec::new(),
+    learn_dwell: false,
+    input_model: None,
+    handshakes: Vec::new(),
+    infer_handshakes: false,
+    prefix: None,
 };
 
 fn main() {
     let args = Args::parse();
+    if let Some(Command::CheckProof {
+        filename,
+        certificate,
+        assume,
+        assertions,
+    }) = &args.command
+    {
+        check_proof(filename, certificate, assume, assertions);
+        return;
+    }
 
-    // load system
-    let (mut ctx, mut sys) = btor2::parse_file(&args.filename).expect("Failed to load btor2 file!");
-
+    let (mut ctx, mut sys) = load_system(
+        args.filename.as_ref().unwrap(),
+        &args.assume,
+        &args.assertions,
+        args.verbose,
+    );
     let orig_sys = sys.clone();
     let orig_ctx = ctx.clone();
 
@@ -56,58 +312,379 @@ fn main() {
         println!("{}", sys.serialize_to_str(&ctx));
     }
 
+    if args.verbose && !args.no_input_heuristics {
+        println!("Input classification:");
+        for (input, kind) in inputs::classify_inputs(&ctx, &sys) {
+            println!("  {}: {kind}", input.get_symbol_name(&ctx).unwrap());
+        }
+    }
+
+    let reset = args.reset.as_ref().map(|name| {
+        let is_bool_input = find_input(&ctx, &sys, name)
+            .map(|i| i.get_bv_type(&ctx) == Some(1))
+            .unwrap_or(false);
+        if !is_bool_input {
+            exit_with_error(format!("`{name}` is not a 1-bit input"));
+        }
+        if !(0.0..=1.0).contains(&args.reset_prob) {
+            exit_with_error("the reset probability needs to be between 0 and 1");
+        }
+        ResetOptions {
+            name: name.clone(),
+            active_low: args.reset_active_low,
+            cycles: args.reset_cycles,
+            assert_prob: args.reset_prob,
+        }
+    });
+
+    let stimulus = load_stimulus(&args, &ctx, &sys);
+    if !(0.0..=1.0).contains(&args.corner_prob) {
+        exit_with_error("the corner value probability needs to be between 0 and 1");
+    }
+    if !(0.0..=1.0).contains(&args.dictionary_prob) {
+        exit_with_error("the dictionary probability needs to be between 0 and 1");
+    }
+    if !(0.0..=1.0).contains(&args.cmplog_prob) {
+        exit_with_error("the comparison log probability needs to be between 0 and 1");
+    }
+    let (dwell_prob, dwell) = parse_dwell(&args, &ctx, &sys);
+    let input_model = load_input_model(&args, &ctx, &sys);
+    let prefix = load_prefix(&args, &ctx, &sys, &orig_ctx, &orig_sys);
+    let handshakes = args
+        .handshake
+        .iter()
+        .map(|src| {
+            let opts = handshake::parse_handshake(src).unwrap_or_else(|e| exit_with_error(e));
+            if let Err(e) = handshake::Handshake::new(&ctx, &sys, &opts) {
+                exit_with_error(e);
+            }
+            opts
+        })
+        .collect::<Vec<_>>();
+    if args.verbose && args.infer_handshakes {
+        println!("Inferred handshakes:");
+        for h in handshake::infer_handshakes(&ctx, &sys) {
+            let name = |e: ExprRef| e.get_symbol_name(&ctx).unwrap_or("?").to_string();
+            let data = h.data.iter().map(|d| name(*d)).collect::<Vec<_>>();
+            let ready = sys.get_signal(h.ready).and_then(|i| i.name).unwrap();
+            println!(
+                "  {} / {}: {}",
+                name(h.valid),
+                ctx.get(ready),
+                data.join(", ")
+            );
+        }
+    }
+    if args.verbose {
+        if let Some(model) = &input_model {
+            println!("Learned models for {} inputs.", model.inputs.len());
+        }
+    }
+    if args.verbose && args.dictionary_prob > 0.0 {
+        println!("Constant dictionary:");
+        let dictionary = dictionary::mine_constants(&ctx, &sys);
+        for (input, _) in sys.get_signals(|s| s.is_input()) {
+            if let Some(values) = dictionary.get(&input) {
+                let name = input.get_symbol_name(&ctx).unwrap();
+                println!("  {name}: {} values", values.len());
+            }
+        }
+    }
+
     // run testing on multiple cores
     let num_threads = if args.single_thread {
         1
     } else {
         std::thread::available_parallelism().unwrap().get() as u64
     };
-    let result = Arc::new(RwLock::new(None));
+    let (result_tx, result_rx) = std::sync::mpsc::channel();
+    // every worker tracks how close it gets to the bad states
+    let progress = progress::Progress::new(&mut ctx, &sys);
+    // all fuzzing workers share their coverage and corpus
+    let fuzz = args
+        .fuzz
+        .then(|| Arc::new(fuzz::SharedCorpus::new(&ctx, &sys)));
+    let explore = args.explore;
+    let genetic = args.genetic;
     for seed in 0..num_threads {
-        let result = result.clone();
+        let result_tx = result_tx.clone();
+        let mut progress = progress.clone();
+        let fuzz = fuzz.clone();
         let sys = sys.clone();
         let ctx = ctx.clone();
         let mut options = RANDOM_OPTS.clone();
         options.max_cycles = args.max_cycles.map(|c| c.div_ceil(num_threads));
+        options.reset = reset.clone();
+        options.input_heuristics = !args.no_input_heuristics;
+        options.stimulus = stimulus.clone();
+        options.corner_prob = args.corner_prob;
+        options.dictionary_prob = args.dictionary_prob;
+        options.cmplog_prob = args.cmplog_prob;
+        options.adaptive_k = args.adaptive_k;
+        options.swarm = args.swarm;
+        options.dwell_prob = dwell_prob;
+        options.dwell = dwell.clone();
+        options.learn_dwell = args.learn_dwell;
+        options.input_model = input_model.clone();
+        options.handshakes = handshakes.clone();
+        options.infer_handshakes = args.infer_handshakes;
+        options.prefix = prefix.clone();
+        std::thread::spawn(move || {
+            let p = &mut progress;
+            let res = if let Some(shared) = fuzz {
+                fuzz::fuzz_testing(ctx.clone(), sys.clone(), options, seed, shared, p)
+            } else if explore {
+                explore::explore_testing(ctx.clone(), sys.clone(), options, seed, p)
+            } else if genetic {
+                genetic::genetic_testing(ctx.clone(), sys.clone(), options, seed, p)
+            } else {
+                random_testing(ctx.clone(), sys.clone(), options, seed, p)
+            };
+            // the receiver is gone once another worker has found a result
+            let _ = result_tx.send((res, progress, false));
+        });
+    }
+    // formal engines run next to the testing workers, on a system in which anonymous inputs
+    // can still take any value
+    let mut formal_ctx = orig_ctx.clone();
+    let mut formal_sys = orig_sys.clone();
+    simplify_expressions(&mut formal_ctx, &mut formal_sys);
+    let mut engines: Vec<Engine> = Vec::new();
+    let verbose = args.verbose;
+    if let Some(max_k) = args.bmc {
+        engines.push(Box::new(move |ctx, sys| bmc::bmc(ctx, sys, max_k, verbose)));
+    }
+    if let Some(max_k) = args.k_induction {
+        engines.push(Box::new(move |ctx, sys| {
+            kind::k_induction(ctx, sys, max_k, verbose)
+        }));
+    }
+    if args.ic3 {
+        engines.push(Box::new(ic3::ic3));
+    }
+    if args.explicit_state {
+        engines.push(Box::new(move |ctx, sys| {
+            explicit::explicit_state(ctx, sys, verbose)
+        }));
+    }
+    if args.bdd {
+        engines.push(Box::new(reach::bdd_reachability));
+    }
+    for engine in engines {
+        let result_tx = result_tx.clone();
+        let progress = progress.clone();
+        let sys = formal_sys.clone();
+        let ctx = formal_ctx.clone();
         std::thread::spawn(move || {
-            let res = random_testing(ctx.clone(), sys.clone(), options, seed);
-            let mut shared_result = result.write().unwrap();
-            *shared_result = Some(res);
+            let res = engine(&ctx, &sys);
+            let _ = result_tx.send((res, progress, true));
         });
     }
+    drop(result_tx);
 
-    loop {
-        let shared_result = (*result.read().unwrap()).clone();
-        if let Some(res) = shared_result {
-            match res {
-                ModelCheckResult::Unknown => {
-                    // print nothing
-                }
-                ModelCheckResult::UnSat => {
-                    println!("unsat");
-                }
-                ModelCheckResult::Sat(wit) => {
-                    println!("sat");
-                    wit.print(&orig_ctx, &orig_sys, &mut std::io::stdout())
-                        .unwrap()
+    // wait for a definite result or for all workers to give up
+    let mut unknown_progress = progress;
+    for (res, progress, from_engine) in result_rx.iter() {
+        match res {
+            ModelCheckResult::Unknown => {
+                unknown_progress.merge(&progress);
+            }
+            ModelCheckResult::UnSat(cert) => {
+                println!("unsat");
+                if let Some(filename) = &args.certificate {
+                    let Some(cert) = cert else {
+                        exit_with_error(format!("no certificate to write to {filename}"));
+                    };
+                    std::fs::write(filename, cert.serialize()).unwrap_or_else(|e| {
+                        exit_with_error(format!("failed to write {filename}: {e}"))
+                    });
                 }
+                std::process::exit(0);
+            }
+            ModelCheckResult::Sat(wit) => {
+                println!("sat");
+                // only the testing workers replace anonymous inputs with zero
+                let zeroed = !from_engine;
+                wit.print(&orig_ctx, &orig_sys, zeroed, &mut std::io::stdout())
+                    .unwrap();
+                std::process::exit(0);
             }
-            std::process::exit(0);
         }
     }
+    unknown_progress
+        .print(&ctx, &sys, &mut std::io::stdout())
+        .unwrap();
+}
+
+/// Loads a system and adds monitors for temporal assumptions and assertions.
+fn load_system(
+    filename: &str,
+    assume: &[String],
+    assertions: &[String],
+    verbose: bool,
+) -> (Context, TransitionSystem) {
+    let (mut ctx, mut sys) = btor2::parse_file(filename).expect("Failed to load btor2 file!");
+    if !assume.is_empty() {
+        let assumptions = parse_properties(assume);
+        let monitors = temporal::add_assumptions(&mut ctx, &mut sys, &assumptions)
+            .unwrap_or_else(|e| exit_with_error(e));
+        if verbose {
+            println!(
+                "Added {monitors} monitor states for {} assumptions.",
+                assumptions.len()
+            );
+        }
+    }
+    if !assertions.is_empty() {
+        let assertions = parse_properties(assertions);
+        let monitors = temporal::add_assertions(&mut ctx, &mut sys, &assertions)
+            .unwrap_or_else(|e| exit_with_error(e));
+        if verbose {
+            println!(
+                "Added {monitors} monitor states for {} assertions.",
+                assertions.len()
+            );
+        }
+    }
+    (ctx, sys)
+}
+
+/// Validates a certificate against the design exactly as parsed, with the temporal
+/// assumptions and assertions added but without any of the simplifications of the engines.
+fn check_proof(filename: &str, certificate: &str, assume: &[String], assertions: &[String]) {
+    let (ctx, sys) = load_system(filename, assume, assertions, false);
+    let content = std::fs::read_to_string(certificate)
+        .unwrap_or_else(|e| exit_with_error(format!("failed to read {certificate}: {e}")));
+    let cert = certificate::Certificate::parse(&content)
+        .unwrap_or_else(|e| exit_with_error(format!("{certificate}: {e}")));
+    match certificate::check_certificate(&ctx, &sys, &cert) {
+        Ok(()) => println!("proof is valid"),
+        Err(e) => exit_with_error(format!("invalid proof: {e}")),
+    }
+}
+
+fn load_stimulus(
+    args: &Args,
+    ctx: &Context,
+    sys: &TransitionSystem,
+) -> Vec<(String, stimulus::InputSpec)> {
+    let mut out = Vec::new();
+    if let Some(filename) = &args.stimulus_file {
+        let content = std::fs::read_to_string(filename)
+            .unwrap_or_else(|e| exit_with_error(format!("failed to read {filename}: {e}")));
+        out = stimulus::parse_stimulus_file(&content).unwrap_or_else(|e| exit_with_error(e));
+    }
+    for src in args.stimulus.iter() {
+        out.push(stimulus::parse_input_spec(src).unwrap_or_else(|e| exit_with_error(e)));
+    }
+    for (name, spec) in out.iter() {
+        let input = find_input(ctx, sys, name)
+            .unwrap_or_else(|| exit_with_error(format!("unknown input `{name}`")));
+        let width = input.get_bv_type(ctx).unwrap();
+        if let Err(e) = spec.check_width(width) {
+            exit_with_error(format!("{name}: {e}"));
+        }
+    }
+    out
+}
+
+fn load_prefix(
+    args: &Args,
+    ctx: &Context,
+    sys: &TransitionSystem,
+    orig_ctx: &Context,
+    orig_sys: &TransitionSystem,
+) -> Option<prefix::Prefix> {
+    let filename = args.prefix.as_ref()?;
+    let content = std::fs::read_to_string(filename)
+        .unwrap_or_else(|e| exit_with_error(format!("failed to read {filename}: {e}")));
+    let prefix = if filename.ends_with(".vcd") {
+        vcd::parse_vcd(&content, args.vcd_clock.as_deref())
+            .and_then(|trace| prefix::Prefix::from_vcd(ctx, sys, &trace))
+    } else {
+        let wit = btor2::parse_witness(&mut content.as_bytes())
+            .unwrap_or_else(|e| exit_with_error(format!("{filename}: {e}")));
+        prefix::Prefix::from_witness(ctx, sys, orig_ctx, orig_sys, &wit)
+    };
+    Some(prefix.unwrap_or_else(|e| exit_with_error(format!("{filename}: {e}"))))
+}
+
+fn load_input_model(
+    args: &Args,
+    ctx: &Context,
+    sys: &TransitionSystem,
+) -> Option<markov::InputModel> {
+    if args.stimulus_vcd.is_empty() {
+        return None;
+    }
+    let traces = args
+        .stimulus_vcd
+        .iter()
+        .map(|filename| {
+            let content = std::fs::read_to_string(filename)
+                .unwrap_or_else(|e| exit_with_error(format!("failed to read {filename}: {e}")));
+            vcd::parse_vcd(&content, args.vcd_clock.as_deref())
+                .unwrap_or_else(|e| exit_with_error(format!("{filename}: {e}")))
+        })
+        .collect::<Vec<_>>();
+    let inputs = sys
+        .get_signals(|s| s.is_input())
+        .into_iter()
+        .filter_map(|(input, _)| {
+            let width = input.get_bv_type(ctx).filter(|w| *w <= 64)?;
+            Some((input.get_symbol_name(ctx)?.to_string(), width))
+        })
+        .collect::<Vec<_>>();
+    Some(markov::InputModel::fit(&traces, &inputs).unwrap_or_else(|e| exit_with_error(e)))
+}
+
+fn parse_dwell(args: &Args, ctx: &Context, sys: &TransitionSystem) -> (f64, Vec<(String, f64)>) {
+    let (mut default, mut per_input) = (0.0, Vec::new());
+    let parse_prob = |src: &str| match src.trim().parse::<f64>() {
+        Ok(prob) if (0.0..=1.0).contains(&prob) => prob,
+        _ => exit_with_error(format!("invalid dwell probability `{src}`")),
+    };
+    for src in args.dwell.iter() {
+        if let Some((name, prob)) = src.split_once('=') {
+            let name = name.trim();
+            if find_input(ctx, sys, name).is_none() {
+                exit_with_error(format!("unknown input `{name}`"));
+            }
+            per_input.push((name.to_string(), parse_prob(prob)));
+        } else {
+            default = parse_prob(src);
+        }
+    }
+    (default, per_input)
+}
+
+fn parse_properties(sources: &[String]) -> Vec<temporal::Property> {
+    sources
+        .iter()
+        .map(|p| temporal::parse_property(p))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| exit_with_error(e))
+}
+
+fn exit_with_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("error: {e}");
+    std::process::exit(1);
 }
 
 #[derive(Debug, Clone)]
 pub enum ModelCheckResult {
     Unknown,
-    UnSat,
+    /// `None` if the engine could not produce a certificate of a reasonable size.
+    UnSat(Option<certificate::Certificate>),
     Sat(Witness),
 }
 
 pub type StepInt = u64;
 
+/// Formal engine that runs next to the testing workers.
+type Engine = Box<dyn FnOnce(&Context, &TransitionSystem) -> ModelCheckResult + Send>;
+
 /// In-memory representation of a witness.
-/// We currently assume that all states start at zero.
 #[derive(Clone)]
 pub struct Witness {
     pub input_data: Vec<Word>,
@@ -126,10 +703,13 @@ impl Debug for Witness {
 /// data format.
 /// https://github.com/ekiwi/patronus/blob/a0ced099581d7a02079059eb96ac459e3133e70b/src/btor2/witness.rs#L224C22-L224C42
 impl Witness {
+    /// `zeroed`: the witness was found on a system in which anonymous inputs were replaced
+    /// with zero, thus `input_data` contains no values for them.
     pub fn print(
         &self,
         ctx: &Context,
         sys: &TransitionSystem,
+        zeroed: bool,
         out: &mut impl std::io::Write,
     ) -> std::io::Result<()> {
         // declare failed properties
@@ -143,13 +723,16 @@ impl Witness {
             }
         }
 
-        // print starting state (always zero!)
+        // print starting state
         let mut offset = 0;
         if sys.states().count() > 0 {
             writeln!(out, "#0")?;
             for (ii, (_, state)) in sys.states().enumerate() {
                 if state.init.is_some() {
                     // the state has a computed init value
+                    if let Some(width) = state.symbol.get_bv_type(ctx) {
+                        offset += width.div_ceil(Word::BITS) as usize;
+                    }
                     continue;
                 }
                 let name = state
@@ -186,7 +769,7 @@ impl Witness {
             writeln!(out, "@{k}")?;
             for (ii, input) in inputs.iter() {
                 let name = input.get_symbol_name(ctx).unwrap();
-                let is_removed = name.starts_with(DEFAULT_INPUT_PREFIX);
+                let is_removed = zeroed && name.starts_with(DEFAULT_INPUT_PREFIX);
                 let width = input.get_bv_type(ctx).unwrap();
                 let words = width.div_ceil(Word::BITS) as usize;
                 let value = if is_removed {
@@ -0,0 +1,254 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Per-input Markov models fitted to example stimulus traces. Every input holds its value
+// for a sampled number of cycles and then moves to a value that followed the current one
+// in the examples. Inputs that tend to change together with an earlier input follow it.
+
+use crate::stimulus::sample_weighted;
+use crate::vcd::VcdTrace;
+use patronus::ir::value::mask;
+use patronus::ir::WidthInt;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Inputs with more distinct values only learn their hold times.
+const MAX_VALUES: usize = 256;
+/// Probability of picking a uniformly random value instead of following the model,
+/// so that we still explore values that never appeared in the examples.
+const EXPLORE_PROB: f64 = 0.1;
+/// An input follows another input only if it changed at least this often when the other did.
+const MIN_CORRELATION: f64 = 0.5;
+/// Minimum number of changes of another input before we trust the correlation.
+const MIN_LEADER_CHANGES: u64 = 4;
+
+#[derive(Debug, Clone)]
+pub struct MarkovInput {
+    pub name: String,
+    width: WidthInt,
+    /// values that were driven and how often, empty if there were too many different ones
+    values: Vec<(u64, u64)>,
+    /// for every value, the values that followed it and how often
+    transitions: HashMap<u64, Vec<(u64, u64)>>,
+    /// number of cycles a value was held and how often
+    holds: Vec<(u64, u64)>,
+    /// earlier input that this input changes together with, and the probability of doing so
+    leader: Option<(usize, f64)>,
+}
+
+impl MarkovInput {
+    fn next_value(&self, rng: &mut impl Rng, current: Option<u64>) -> u64 {
+        if self.values.is_empty() || rng.gen_bool(EXPLORE_PROB) {
+            return rng.next_u64() & mask(self.width);
+        }
+        match current.and_then(|c| self.transitions.get(&c)) {
+            Some(successors) => sample_weighted(rng, successors),
+            None => sample_weighted(rng, &self.values),
+        }
+    }
+
+    fn hold(&self, rng: &mut impl Rng) -> u64 {
+        sample_weighted(rng, &self.holds)
+    }
+}
+
+/// Current value of every modelled input in a trace, and for how many more cycles it is held.
+#[derive(Debug, Clone, Default)]
+pub struct ModelState {
+    values: Vec<u64>,
+    remaining: Vec<u64>,
+}
+
+impl ModelState {
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.remaining.clear();
+    }
+
+    pub fn value(&self, input: usize) -> u64 {
+        self.values[input]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputModel {
+    pub inputs: Vec<MarkovInput>,
+}
+
+impl InputModel {
+    /// Fits a model for every input that appears in at least one of the traces.
+    pub fn fit(traces: &[VcdTrace], inputs: &[(String, WidthInt)]) -> Result<Self, String> {
+        // values of every input, one vector per trace that contains the input
+        let mut modelled = Vec::new();
+        for (name, width) in inputs.iter() {
+            let columns: Columns = traces
+                .iter()
+                .map(|t| {
+                    let var = t.find(name).filter(|_| !t.cycles.is_empty())?;
+                    Some(t.values(var).map(|v| v & mask(*width)).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+            if columns.iter().any(|c| c.is_some()) {
+                modelled.push((name.as_str(), *width, columns));
+            }
+        }
+        if modelled.is_empty() {
+            return Err("none of the inputs appear in the stimulus traces".to_string());
+        }
+
+        let changes = modelled
+            .iter()
+            .map(|(_, _, columns)| {
+                // inputs missing from a trace never change, keeping all inputs aligned
+                let changes = columns
+                    .iter()
+                    .zip(traces.iter())
+                    .flat_map(|(c, t)| match c {
+                        Some(c) => std::iter::once(false)
+                            .chain(c.windows(2).map(|w| w[0] != w[1]))
+                            .collect(),
+                        None => vec![false; t.cycles.len()],
+                    });
+                changes.collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let inputs = modelled
+            .iter()
+            .enumerate()
+            .map(|(ii, (name, width, columns))| {
+                let mut values: HashMap<u64, u64> = HashMap::new();
+                let mut transitions: HashMap<u64, HashMap<u64, u64>> = HashMap::new();
+                let mut holds: HashMap<u64, u64> = HashMap::new();
+                for column in columns.iter().flatten() {
+                    let mut hold = 1;
+                    *values.entry(column[0]).or_default() += 1;
+                    for w in column.windows(2) {
+                        if w[0] == w[1] {
+                            hold += 1;
+                        } else {
+                            *values.entry(w[1]).or_default() += 1;
+                            *transitions
+                                .entry(w[0])
+                                .or_default()
+                                .entry(w[1])
+                                .or_default() += 1;
+                            *holds.entry(hold).or_default() += 1;
+                            hold = 1;
+                        }
+                    }
+                    *holds.entry(hold).or_default() += 1;
+                }
+                if values.len() > MAX_VALUES {
+                    values.clear();
+                    transitions.clear();
+                }
+                MarkovInput {
+                    name: name.to_string(),
+                    width: *width,
+                    values: sorted(values),
+                    transitions: transitions
+                        .into_iter()
+                        .map(|(v, next)| (v, sorted(next)))
+                        .collect(),
+                    holds: sorted(holds),
+                    leader: find_leader(&changes, ii),
+                }
+            })
+            .collect();
+        Ok(Self { inputs })
+    }
+
+    /// Advances the model by one cycle, starting a new trace if the state is empty.
+    pub fn step(&self, rng: &mut impl Rng, state: &mut ModelState) {
+        let mut changed = vec![false; self.inputs.len()];
+        if state.values.is_empty() {
+            for input in self.inputs.iter() {
+                state.values.push(input.next_value(rng, None));
+                state.remaining.push(input.hold(rng) - 1);
+            }
+            return;
+        }
+        for (ii, input) in self.inputs.iter().enumerate() {
+            let follows = input
+                .leader
+                .is_some_and(|(leader, prob)| changed[leader] && rng.gen_bool(prob));
+            if follows || state.remaining[ii] == 0 {
+                let value = input.next_value(rng, Some(state.values[ii]));
+                changed[ii] = value != state.values[ii];
+                state.values[ii] = value;
+                state.remaining[ii] = input.hold(rng) - 1;
+            } else {
+                state.remaining[ii] -= 1;
+            }
+        }
+    }
+}
+
+/// Values of an input in every trace, `None` if the trace does not contain the input.
+type Columns = Vec<Option<Vec<u64>>>;
+
+/// Deterministic order, independent of the hash map.
+fn sorted(counts: HashMap<u64, u64>) -> Vec<(u64, u64)> {
+    let mut out = counts.into_iter().collect::<Vec<_>>();
+    out.sort_unstable();
+    out
+}
+
+/// Finds the earlier input that input `ii` most often changes together with.
+fn find_leader(changes: &[Vec<bool>], ii: usize) -> Option<(usize, f64)> {
+    (0..ii)
+        .filter_map(|leader| {
+            let leader_changes = changes[leader].iter().filter(|c| **c).count() as u64;
+            let together = changes[leader]
+                .iter()
+                .zip(changes[ii].iter())
+                .filter(|(a, b)| **a && **b)
+                .count() as u64;
+            let prob = together as f64 / leader_changes as f64;
+            (leader_changes >= MIN_LEADER_CHANGES && prob >= MIN_CORRELATION)
+                .then_some((leader, prob))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_fit_and_generate() {
+        // `valid` toggles every three cycles, `data` counts up whenever `valid` changes
+        let mut trace = VcdTrace {
+            names: vec!["tb.valid".to_string(), "tb.data".to_string()],
+            widths: vec![1, 8],
+            cycles: Vec::new(),
+        };
+        for k in 0..60u64 {
+            trace.cycles.push(vec![(k / 3) % 2, k / 3]);
+        }
+        let inputs = [("valid".to_string(), 1), ("data".to_string(), 8)];
+        let model = InputModel::fit(&[trace], &inputs).unwrap();
+        assert_eq!(model.inputs[1].leader, Some((0, 1.0)));
+        assert!(InputModel::fit(&[], &inputs).is_err());
+
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        let mut state = ModelState::default();
+        let mut valid = Vec::new();
+        for _ in 0..30 {
+            model.step(&mut rng, &mut state);
+            valid.push(state.value(0));
+        }
+        // values are held for three cycles, except for the last one in the example
+        let runs = valid
+            .chunk_by(|a, b| a == b)
+            .map(|r| r.len())
+            .collect::<Vec<_>>();
+        assert!(
+            runs[1..runs.len() - 1].iter().all(|r| *r % 3 == 0),
+            "{runs:?}"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Replays a known trace before the random search starts, in order to explore the
+// scenario at its end.
+
+use crate::random::{check_for_bad_states, find_input, InputDriver};
+use crate::vcd::VcdTrace;
+use crate::{StepInt, Witness};
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use patronus::mc::{Simulator, WitnessValue};
+use patronus::sim::interpreter::Interpreter;
+
+#[derive(Debug, Clone)]
+pub struct Prefix {
+    /// values of all inputs in every cycle, in the format of [`Witness::input_data`]
+    input_data: Vec<Word>,
+    cycles: StepInt,
+    /// initial value of every state, `None` for states that start at zero
+    init: Vec<Option<Word>>,
+}
+
+impl Prefix {
+    /// Converts a BTOR2 witness for the original system. Inputs and states are identified
+    /// by their position in the original system and by name in the system that we test.
+    pub fn from_witness(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        orig_ctx: &Context,
+        orig_sys: &TransitionSystem,
+        wit: &patronus::mc::Witness,
+    ) -> Result<Self, String> {
+        check_inputs(ctx, sys)?;
+        let orig_inputs = orig_sys.get_signals(|s| s.is_input());
+        let mut positions = Vec::new();
+        for (ii, (input, _)) in orig_inputs.iter().enumerate() {
+            let name = input.get_symbol_name(orig_ctx).unwrap();
+            if let Some(input) = find_input(ctx, sys, name) {
+                positions.push((input, ii));
+                continue;
+            }
+            // inputs that are not part of the system, like anonymous ones, are replayed as
+            // zero, which diverges from the witness for any other value
+            let is_nonzero = |cycle: &Vec<Option<WitnessValue>>| match cycle.get(ii) {
+                Some(Some(WitnessValue::Scalar(value, _))) => !value.to_u64_digits().is_empty(),
+                Some(Some(WitnessValue::Array(_))) => true,
+                _ => false,
+            };
+            if let Some(k) = wit.inputs.iter().position(is_nonzero) {
+                return Err(format!(
+                    "witness input `{name}` is not part of the system, but is not zero in cycle {k}"
+                ));
+            }
+        }
+        let inputs = sys.get_signals(|s| s.is_input());
+        let mut input_data = Vec::new();
+        for cycle in wit.inputs.iter() {
+            for (input, _) in inputs.iter() {
+                let value = positions
+                    .iter()
+                    .find(|(i, _)| i == input)
+                    .and_then(|(_, ii)| cycle.get(*ii).cloned().flatten());
+                input_data.push(to_word(ctx, *input, value)?);
+            }
+        }
+
+        let orig_states = orig_sys.states().map(|(_, s)| s.symbol).collect::<Vec<_>>();
+        let init = sys
+            .states()
+            .map(|(_, state)| {
+                let name = state.symbol.get_symbol_name(ctx);
+                let value = orig_states
+                    .iter()
+                    .position(|s| name.is_some() && s.get_symbol_name(orig_ctx) == name)
+                    .and_then(|ii| wit.init.get(ii).cloned().flatten());
+                value
+                    .map(|v| to_word(ctx, state.symbol, Some(v)))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            input_data,
+            cycles: wit.inputs.len() as StepInt,
+            init,
+        })
+    }
+
+    /// Converts the input values of every cycle in a VCD, inputs that do not appear are zero.
+    pub fn from_vcd(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        trace: &VcdTrace,
+    ) -> Result<Self, String> {
+        check_inputs(ctx, sys)?;
+        let vars = sys
+            .get_signals(|s| s.is_input())
+            .into_iter()
+            .map(|(input, _)| {
+                let width = input.get_bv_type(ctx).unwrap();
+                let var = input.get_symbol_name(ctx).and_then(|n| trace.find(n));
+                (var, mask(width))
+            })
+            .collect::<Vec<_>>();
+        let input_data = trace
+            .cycles
+            .iter()
+            .flat_map(|values| {
+                vars.iter()
+                    .map(|(var, mask)| var.map(|v| values[v] & mask).unwrap_or(0))
+            })
+            .collect();
+        Ok(Self {
+            input_data,
+            cycles: trace.cycles.len() as StepInt,
+            init: vec![None; sys.states().count()],
+        })
+    }
+
+    pub fn cycles(&self) -> StepInt {
+        self.cycles
+    }
+
+    /// Overrides the initial value of all states that the prefix specifies.
+    pub fn init(&self, ctx: &Context, sys: &TransitionSystem, sim: &mut Interpreter) {
+        for ((_, state), value) in sys.states().zip(self.init.iter()) {
+            if let Some(value) = value {
+                let width = state.symbol.get_bv_type(ctx).unwrap();
+                sim.set(state.symbol, ValueRef::new(&[*value], width));
+            }
+        }
+    }
+
+    /// Executes all cycles of the prefix. Returns the cycle and the bad states if one is hit.
+    /// Fails if the prefix violates a constraint, since nothing that happens afterwards
+    /// would be a valid execution.
+    pub fn replay(
+        &self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        inputs: &InputDriver,
+        bad_states: &[ExprRef],
+        sim: &mut Interpreter,
+    ) -> Result<Option<(StepInt, Vec<usize>)>, String> {
+        let driven = sys.get_signals(|s| s.is_input());
+        // every input fits into a single word
+        for k in 0..self.cycles {
+            let cycle = &self.input_data[k as usize * driven.len()..][..driven.len()];
+            for ((input, _), value) in driven.iter().zip(cycle.iter()) {
+                let width = input.get_bv_type(ctx).unwrap();
+                sim.set(*input, ValueRef::new(&[*value], width));
+            }
+            sim.update();
+            if !inputs.is_valid(ctx, sim) {
+                return Err(format!("prefix violates a constraint in cycle {k}"));
+            }
+            let bads = check_for_bad_states(bad_states, sim);
+            if !bads.is_empty() {
+                return Ok(Some((k, bads)));
+            }
+            sim.step();
+        }
+        Ok(None)
+    }
+
+    /// Witness for a bad state that was reached while replaying the prefix.
+    pub fn witness(
+        &self,
+        sys: &TransitionSystem,
+        state_init: Vec<Word>,
+        k: StepInt,
+        bads: Vec<usize>,
+    ) -> Witness {
+        let words_per_cycle = sys.get_signals(|s| s.is_input()).len();
+        Witness {
+            input_data: self.input_data[..(k as usize + 1) * words_per_cycle].to_vec(),
+            state_init,
+            k,
+            failed_safety: bads,
+        }
+    }
+
+    /// Turns a witness that starts at the end of the prefix into one that starts
+    /// in the initial state.
+    pub fn extend(&self, wit: Witness, state_init: Vec<Word>) -> Witness {
+        let mut input_data = self.input_data.clone();
+        input_data.extend(wit.input_data);
+        Witness {
+            input_data,
+            state_init,
+            k: self.cycles + wit.k,
+            failed_safety: wit.failed_safety,
+        }
+    }
+}
+
+/// Every input needs to fit into a single word.
+fn check_inputs(ctx: &Context, sys: &TransitionSystem) -> Result<(), String> {
+    for (input, _) in sys.get_signals(|s| s.is_input()) {
+        if input.get_bv_type(ctx).is_none_or(|w| w > Word::BITS) {
+            let name = input.get_symbol_name(ctx).unwrap_or("?");
+            return Err(format!(
+                "`{name}`: only bit-vector inputs of up to 64 bits are supported"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn to_word(ctx: &Context, symbol: ExprRef, value: Option<WitnessValue>) -> Result<Word, String> {
+    let name = symbol.get_symbol_name(ctx).unwrap_or("?");
+    let width = symbol
+        .get_bv_type(ctx)
+        .filter(|w| *w <= Word::BITS)
+        .ok_or_else(|| format!("`{name}`: only bit-vectors of up to 64 bits are supported"))?;
+    match value {
+        None => Ok(0),
+        Some(WitnessValue::Scalar(value, _)) => {
+            let digits = value.to_u64_digits();
+            Ok(digits.first().copied().unwrap_or(0) & mask(width))
+        }
+        Some(WitnessValue::Array(_)) => Err(format!("`{name}`: arrays are not supported")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Progress;
+    use crate::random::random_testing;
+    use crate::ModelCheckResult;
+
+    #[test]
+    fn test_witness_includes_prefix() {
+        // counter that only increments when enabled and is bad once it reaches 7
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let counter = ctx.bv_symbol("counter", 4);
+        let state = sys.add_state(&ctx, counter);
+        let one = ctx.one(4);
+        let inc = ctx.add(counter, one);
+        let next = ctx.bv_ite(en, inc, counter);
+        sys.modify_state(state, |s| s.next = Some(next));
+        let seven = ctx.bv_lit(7, 4);
+        let bad = ctx.bv_equal(counter, seven);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        // the prefix enables the counter for five cycles
+        let trace = VcdTrace {
+            names: vec!["tb.en".to_string()],
+            widths: vec![1],
+            cycles: vec![vec![1]; 5],
+        };
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.small_k = 2;
+        opts.large_k = 2;
+        opts.input_heuristics = false;
+        opts.prefix = Some(Prefix::from_vcd(&ctx, &sys, &trace).unwrap());
+        let mut progress = Progress::new(&mut ctx, &sys);
+        // random traces are too short to reach the bad state on their own
+        let ModelCheckResult::Sat(wit) = random_testing(ctx, sys, opts, 0, &mut progress) else {
+            panic!("expected a counter example");
+        };
+        assert_eq!(wit.k, 7);
+        assert_eq!(wit.input_data[..7], [1; 7]);
+        assert_eq!(wit.state_init, [0]);
+    }
+
+    #[test]
+    fn test_replay_rejects_constraint_violation() {
+        // the counter may only be enabled while it is below three
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let counter = ctx.bv_symbol("counter", 4);
+        let state = sys.add_state(&ctx, counter);
+        let one = ctx.one(4);
+        let inc = ctx.add(counter, one);
+        let next = ctx.bv_ite(en, inc, counter);
+        sys.modify_state(state, |s| s.next = Some(next));
+        let three = ctx.bv_lit(3, 4);
+        let below_three = ctx.greater(three, counter);
+        let not_en = ctx.not(en);
+        let constraint = ctx.or(not_en, below_three);
+        sys.add_signal(
+            constraint,
+            SignalKind::Node,
+            SignalLabels::constraint(),
+            None,
+        );
+
+        let inputs = InputDriver::new(&mut ctx, &sys, &crate::RANDOM_OPTS);
+        inputs.instrument(&ctx, &mut sys);
+        let replay = |cycles: usize| {
+            let trace = VcdTrace {
+                names: vec!["tb.en".to_string()],
+                widths: vec![1],
+                cycles: vec![vec![1]; cycles],
+            };
+            let prefix = Prefix::from_vcd(&ctx, &sys, &trace).unwrap();
+            let mut sim = Interpreter::new(&ctx, &sys);
+            sim.init(patronus::sim::interpreter::InitKind::Zero);
+            prefix.replay(&ctx, &sys, &inputs, &[], &mut sim)
+        };
+        assert_eq!(replay(3), Ok(None));
+        assert_eq!(
+            replay(5),
+            Err("prefix violates a constraint in cycle 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_witness_inputs_need_to_be_mapped() {
+        let mut orig_ctx = Context::default();
+        let mut orig_sys = TransitionSystem::new("test".to_string());
+        for name in ["a", "b"] {
+            let input = orig_ctx.bv_symbol(name, 1);
+            orig_sys.add_input(&orig_ctx, input);
+        }
+        // only `a` is part of the system that we test
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 1);
+        sys.add_input(&ctx, a);
+
+        let value = |v: u32| Some(WitnessValue::Scalar(v.into(), 1));
+        let mut wit = patronus::mc::Witness {
+            init: Vec::new(),
+            init_names: Vec::new(),
+            inputs: vec![vec![value(1), value(0)], vec![value(0), None]],
+            input_names: Vec::new(),
+            failed_safety: Vec::new(),
+        };
+        let prefix = Prefix::from_witness(&ctx, &sys, &orig_ctx, &orig_sys, &wit).unwrap();
+        assert_eq!(prefix.input_data, [1, 0]);
+        wit.inputs[1][1] = value(1);
+        assert_eq!(
+            Prefix::from_witness(&ctx, &sys, &orig_ctx, &orig_sys, &wit).unwrap_err(),
+            "witness input `b` is not part of the system, but is not zero in cycle 1"
+        );
+    }
+}
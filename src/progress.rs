@@ -0,0 +1,223 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Tracks how close we got to every bad state, in order to explain unknown results.
+
+use crate::constraints::split_conjunction;
+use crate::random::make_observable;
+use patronus::ir::*;
+use patronus::mc::Simulator;
+use patronus::sim::interpreter::Interpreter;
+
+/// Sub-expressions deeper than this are abbreviated in the report.
+pub const MAX_DEPTH: u32 = 4;
+
+#[derive(Debug, Clone)]
+struct BadProgress {
+    /// sub-conditions that all need to hold for the bad state to be reached
+    conjuncts: Vec<ExprRef>,
+    /// largest number of sub-conditions that held in the same cycle
+    best: usize,
+    /// whether a sub-condition was ever true
+    ever_true: Vec<bool>,
+}
+
+/// Per bad state: the most sub-conditions that were satisfied at the same time and
+/// the sub-conditions that were never satisfied.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    bads: Vec<BadProgress>,
+}
+
+impl Progress {
+    pub fn new(ctx: &mut Context, sys: &TransitionSystem) -> Self {
+        let bads = sys
+            .bad_states()
+            .into_iter()
+            .map(|(e, _)| {
+                let conjuncts = split_conjunction(ctx, e).to_vec();
+                BadProgress {
+                    ever_true: vec![false; conjuncts.len()],
+                    conjuncts,
+                    best: 0,
+                }
+            })
+            .collect();
+        Self { bads }
+    }
+
+    /// Makes sure that all sub-conditions can be read from the simulator.
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        for bad in self.bads.iter() {
+            for c in bad.conjuncts.iter() {
+                make_observable(ctx, sys, *c);
+            }
+        }
+    }
+
+    /// Records which sub-conditions hold in the current cycle.
+    pub fn record(&mut self, ctx: &Context, sim: &Interpreter) {
+        for bad in self.bads.iter_mut() {
+            let mut satisfied = 0;
+            for (c, ever_true) in bad.conjuncts.iter().zip(bad.ever_true.iter_mut()) {
+                let is_true = match ctx.get(*c) {
+                    Expr::BVLiteral { value, .. } => *value == 1,
+                    _ => sim.get(*c).unwrap().to_u64().unwrap() == 1,
+                };
+                if is_true {
+                    satisfied += 1;
+                    *ever_true = true;
+                }
+            }
+            bad.best = std::cmp::max(bad.best, satisfied);
+        }
+    }
+
+    /// Combines the progress made by another worker.
+    pub fn merge(&mut self, other: &Self) {
+        for (bad, other) in self.bads.iter_mut().zip(other.bads.iter()) {
+            bad.best = std::cmp::max(bad.best, other.best);
+            for (a, b) in bad.ever_true.iter_mut().zip(other.ever_true.iter()) {
+                *a |= *b;
+            }
+        }
+    }
+
+    pub fn print(
+        &self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        out: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        writeln!(out, "Closest approach to bad states:")?;
+        for (ii, (bad, (_, info))) in self.bads.iter().zip(sys.bad_states()).enumerate() {
+            let name = info
+                .name
+                .map(|n| format!(" ({})", ctx.get(n)))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "  b{ii}{name}: {}/{} sub-conditions satisfied at once",
+                bad.best,
+                bad.conjuncts.len()
+            )?;
+            for (c, _) in bad
+                .conjuncts
+                .iter()
+                .zip(bad.ever_true.iter())
+                .filter(|(_, t)| !**t)
+            {
+                writeln!(out, "    never true: {}", describe(ctx, *c, MAX_DEPTH))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes an expression including its children.
+pub fn describe(ctx: &Context, e: ExprRef, depth: u32) -> String {
+    let mut out = String::new();
+    write_expr(ctx, e, depth, &mut out).unwrap();
+    out
+}
+
+fn write_expr(
+    ctx: &Context,
+    e: ExprRef,
+    depth: u32,
+    out: &mut impl std::fmt::Write,
+) -> std::fmt::Result {
+    let expr = ctx.get(e);
+    let mut children = Vec::new();
+    expr.for_each_child(|c| children.push(*c));
+    if children.is_empty() {
+        return write!(out, "{}", expr.serialize_to_str(ctx));
+    }
+    if depth == 0 {
+        return write!(out, "...");
+    }
+    if let Expr::BVSlice { e, hi, lo } = *expr {
+        write_expr(ctx, e, depth - 1, out)?;
+        return if hi == lo {
+            write!(out, "[{hi}]")
+        } else {
+            write!(out, "[{hi}:{lo}]")
+        };
+    }
+    // the serialization of an expression leaves blanks where its children go, thus we only
+    // take the name of the operation from it
+    let shallow = expr.serialize_to_str(ctx);
+    let Some((op, _)) = shallow.split_once('(') else {
+        return write!(out, "{shallow}");
+    };
+    write!(out, "{op}(")?;
+    for (ii, child) in children.iter().enumerate() {
+        if ii > 0 {
+            write!(out, ", ")?;
+        }
+        write_expr(ctx, *child, depth - 1, out)?;
+    }
+    match *expr {
+        Expr::BVZeroExt { by, .. } | Expr::BVSignExt { by, .. } => write!(out, ", {by})"),
+        _ => write!(out, ")"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::sim::interpreter::InitKind;
+
+    #[test]
+    fn test_record_and_merge() {
+        // bad: a & b & c
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let [a, b, c] = ["a", "b", "c"].map(|n| ctx.bv_symbol(n, 1));
+        for i in [a, b, c] {
+            sys.add_input(&ctx, i);
+        }
+        let a_and_b = ctx.and(a, b);
+        let bad = ctx.and(a_and_b, c);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+        let mut progress = Progress::new(&mut ctx, &sys);
+        progress.instrument(&ctx, &mut sys);
+        let mut other = progress.clone();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut run = |progress: &mut Progress, values: [u64; 3]| {
+            for (i, v) in [a, b, c].into_iter().zip(values) {
+                sim.set(i, ValueRef::new(&[v], 1));
+            }
+            sim.update();
+            progress.record(&ctx, &sim);
+        };
+        run(&mut progress, [1, 0, 0]);
+        run(&mut progress, [0, 1, 0]);
+        assert_eq!(progress.bads[0].best, 1);
+        run(&mut other, [1, 1, 0]);
+        progress.merge(&other);
+        assert_eq!(progress.bads[0].best, 2);
+        assert_eq!(progress.bads[0].ever_true, [true, true, false]);
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let lsb = ctx.slice(a, 3, 0);
+        let lit = ctx.bv_lit(3, 4);
+        let eq = ctx.bv_equal(lsb, lit);
+        assert_eq!(describe(&ctx, eq, MAX_DEPTH), "eq(a[3:0], 4'b11)");
+        let ext = ctx.zero_extend(lsb, 4);
+        assert_eq!(describe(&ctx, ext, MAX_DEPTH), "zext(a[3:0], 4)");
+        let ite = ctx.bv_ite(eq, ext, a);
+        assert_eq!(
+            describe(&ctx, ite, MAX_DEPTH),
+            "ite(eq(a[3:0], 4'b11), zext(a[3:0], 4), a)"
+        );
+        assert_eq!(describe(&ctx, ite, 1), "ite(..., ..., a)");
+    }
+}
@@ -4,16 +4,26 @@
 //
 // Random testing strategy to finding counter examples.
 
+use crate::cmplog::{CmpLog, Observations};
 use crate::constraints::{analyze_constraints, ConstraintCluster};
+use crate::dictionary::mine_constants;
+use crate::handshake::{infer_handshakes, Handshake, HandshakeOptions};
+use crate::inputs::{classify_inputs, InputKind};
+use crate::markov::{InputModel, ModelState};
+use crate::prefix::Prefix;
+use crate::progress::{describe, Progress, MAX_DEPTH};
+use crate::schedule::{DwellLearner, LengthScheduler, TraceFeedback};
+use crate::stimulus::InputSpec;
 use crate::{ModelCheckResult, StepInt, Witness};
 use patronus::ir::value::mask;
 use patronus::ir::*;
 use patronus::mc::Simulator;
 use patronus::sim::interpreter::{InitKind, Interpreter};
-use rand::{Rng, SeedableRng};
-use std::collections::HashSet;
+use rand::{Rng, RngCore, SeedableRng};
+use smallvec::{smallvec, SmallVec};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct RandomOptions {
     /// bound for searching for a small counter examples
     pub small_k: u64,
@@ -23,31 +33,671 @@ pub struct RandomOptions {
     pub large_k_prob: f64,
     /// maximum number of cycles to execute
     pub max_cycles: Option<u64>,
+    /// reset protocol applied at the start of every trace
+    pub reset: Option<ResetOptions>,
+    /// skip clock-like inputs and bias reset-like inputs, see `classify_inputs`
+    pub input_heuristics: bool,
+    /// probability of asserting an input that was classified as reset-like
+    pub reset_like_prob: f64,
+    /// user provided value distributions for inputs, by name
+    pub stimulus: Vec<(String, InputSpec)>,
+    /// probability of picking a corner value like zero or all-ones instead of a uniform value
+    pub corner_prob: f64,
+    /// probability of picking a value from the constants mined from the design
+    pub dictionary_prob: f64,
+    /// probability of picking a comparison operand that was observed during simulation
+    pub cmplog_prob: f64,
+    /// learn which trace lengths make progress instead of using `large_k_prob`
+    pub adaptive_k: bool,
+    /// pick a new swarm configuration for every trace, see `SwarmMode`
+    pub swarm: bool,
+    /// probability of an input keeping its value from the previous cycle
+    pub dwell_prob: f64,
+    /// input specific probabilities of keeping the previous value, by name
+    pub dwell: Vec<(String, f64)>,
+    /// learn the probability of keeping the previous value for every input
+    pub learn_dwell: bool,
+    /// model of realistic input traffic, learned from example stimulus traces
+    pub input_model: Option<InputModel>,
+    /// valid/ready handshakes whose valid and data inputs are held until the design is ready
+    pub handshakes: Vec<HandshakeOptions>,
+    /// find additional handshakes by their names, see `infer_handshakes`
+    pub infer_handshakes: bool,
+    /// trace that is replayed once, random traces start from the state it ends in
+    pub prefix: Option<Prefix>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResetOptions {
+    /// name of the 1-bit reset input
+    pub name: String,
+    /// the reset is asserted by driving a zero
+    pub active_low: bool,
+    /// number of cycles for which the reset is asserted at the start of every trace
+    pub cycles: u64,
+    /// probability of asserting the reset after the initial reset sequence
+    pub assert_prob: f64,
+}
+
+/// Reset options resolved to the reset input of a concrete system.
+struct Reset {
+    input: ExprRef,
+    active: Word,
+    cycles: u64,
+    assert_prob: f64,
+}
+
+impl Reset {
+    fn new(ctx: &Context, sys: &TransitionSystem, opts: &ResetOptions) -> Self {
+        let input = find_input(ctx, sys, &opts.name).expect("unknown reset input");
+        Self {
+            input,
+            active: if opts.active_low { 0 } else { 1 },
+            cycles: opts.cycles,
+            assert_prob: opts.assert_prob,
+        }
+    }
+
+    /// Drives the reset value for cycle `k` of the current trace.
+    fn apply(&self, rng: &mut impl Rng, k: StepInt, sim: &mut Interpreter) {
+        let is_active =
+            k < self.cycles || (self.assert_prob > 0.0 && rng.gen_bool(self.assert_prob));
+        let value = if is_active {
+            self.active
+        } else {
+            self.active ^ 1
+        };
+        sim.set(self.input, ValueRef::new(&[value], 1));
+    }
+}
+
+/// Number of attempts to fulfill the constraints of a cluster before we stop holding values.
+const MAX_HOLD_ATTEMPTS: u32 = 32;
+/// Number of attempts to fulfill the constraints of a cluster before we end the trace.
+const MAX_CONSTRAINT_ATTEMPTS: u32 = 1024;
+/// Probability that an input in `SwarmMode::Rare` changes its value in a cycle.
+const SWARM_RARE_CHANGE_PROB: f64 = 0.05;
+
+/// How an input is driven over the course of a single trace in swarm testing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SwarmMode {
+    /// keeps the value sampled in the first cycle
+    Constant,
+    /// rarely changes its value
+    Rare,
+    /// gets a new value every cycle
+    Random,
+}
+
+/// Decides how each input is driven in every cycle.
+pub struct InputDriver {
+    constraints: Vec<ConstraintCluster>,
+    unconstrained_inputs: Vec<ExprRef>,
+    resets: Vec<Reset>,
+    stimulus: HashMap<ExprRef, InputSpec>,
+    corner_prob: f64,
+    dictionary: HashMap<ExprRef, Vec<u64>>,
+    dictionary_prob: f64,
+    cmplog: Option<CmpLog>,
+    cmplog_prob: f64,
+    swarm: bool,
+    /// probability of keeping the previous value
+    dwell: HashMap<ExprRef, f64>,
+    model: Option<InputModel>,
+    /// index of every input driven by the model
+    modelled: HashMap<ExprRef, usize>,
+    handshakes: Vec<Handshake>,
+}
+
+/// Mutable state of the input generation which needs to be saved in order to replay a trace.
+#[derive(Clone)]
+pub struct GeneratorState {
+    pub rng: rand_xoshiro::Xoshiro256PlusPlus,
+    observations: Observations,
+    /// swarm configuration of the current trace, empty if swarm testing is disabled
+    swarm: HashMap<ExprRef, SwarmMode>,
+    /// learned probabilities of keeping the previous value, for the current trace
+    dwell: HashMap<ExprRef, f64>,
+    /// values assigned in the previous cycle of the current trace
+    previous: HashMap<ExprRef, Word>,
+    model: ModelState,
+    /// inputs of handshakes that were valid but not ready in the previous cycle
+    stalled: HashSet<ExprRef>,
+    /// we only report once that the constraints could not be fulfilled
+    reported_unsat: bool,
+}
+
+/// Part of the generator state that carries over from one cycle of a trace to the next.
+/// Needs to be restored in order to continue a trace from a saved state.
+#[derive(Clone, Default)]
+pub struct InputHistory {
+    previous: HashMap<ExprRef, Word>,
+    model: ModelState,
+    stalled: HashSet<ExprRef>,
+}
+
+impl GeneratorState {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(seed),
+            observations: Observations::default(),
+            swarm: HashMap::new(),
+            dwell: HashMap::new(),
+            previous: HashMap::new(),
+            model: ModelState::default(),
+            stalled: HashSet::new(),
+            reported_unsat: false,
+        }
+    }
+
+    pub fn input_history(&self) -> InputHistory {
+        InputHistory {
+            previous: self.previous.clone(),
+            model: self.model.clone(),
+            stalled: self.stalled.clone(),
+        }
+    }
+
+    /// Continues a trace, needs to be called after [`InputDriver::start_trace`].
+    pub fn restore_input_history(&mut self, history: &InputHistory) {
+        self.previous.clone_from(&history.previous);
+        self.model.clone_from(&history.model);
+        self.stalled.clone_from(&history.stalled);
+    }
+}
+
+impl InputDriver {
+    pub fn new(ctx: &mut Context, sys: &TransitionSystem, opts: &RandomOptions) -> Self {
+        // collect constraints for input randomization
+        let constraints = analyze_constraints(ctx, sys, false);
+
+        // user provided distributions
+        let stimulus: HashMap<ExprRef, InputSpec> = opts
+            .stimulus
+            .iter()
+            .map(|(name, spec)| {
+                let input = find_input(ctx, sys, name).expect("unknown stimulus input");
+                (input, spec.clone())
+            })
+            .collect();
+
+        let constrained_inputs = constraints
+            .iter()
+            .flat_map(|c| c.inputs().to_vec())
+            .collect::<HashSet<_>>();
+
+        // Combine the user provided reset protocol with the input classification heuristics.
+        // The heuristics are overruled by any user provided stimulus and by constraints,
+        // which could never be fulfilled by an input that is not randomized.
+        let mut resets: Vec<Reset> = opts.reset.iter().map(|r| Reset::new(ctx, sys, r)).collect();
+        let mut clocks = HashSet::new();
+        if opts.input_heuristics {
+            for (input, kind) in classify_inputs(ctx, sys) {
+                if stimulus.contains_key(&input) || constrained_inputs.contains(&input) {
+                    continue;
+                }
+                match kind {
+                    InputKind::Clock => {
+                        clocks.insert(input);
+                    }
+                    InputKind::Reset { active_low, .. } => {
+                        // the user provided reset protocol takes precedence
+                        if !resets.iter().any(|r| r.input == input) {
+                            resets.push(Reset {
+                                input,
+                                active: if active_low { 0 } else { 1 },
+                                cycles: 0,
+                                assert_prob: opts.reset_like_prob,
+                            });
+                        }
+                    }
+                    InputKind::Data => {}
+                }
+            }
+        }
+
+        // find out which inputs are unconstrained
+        let unconstrained_inputs = sys
+            .get_signals(|s| s.is_input())
+            .iter()
+            .map(|(s, _)| *s)
+            .filter(|s| !constrained_inputs.contains(s))
+            .filter(|s| !resets.iter().any(|r| r.input == *s) && !clocks.contains(s))
+            .collect::<Vec<_>>();
+
+        // the default probability applies to all inputs without an explicit one
+        let mut dwell = HashMap::new();
+        if opts.dwell_prob > 0.0 {
+            for (input, _) in sys.get_signals(|s| s.is_input()) {
+                dwell.insert(input, opts.dwell_prob);
+            }
+        }
+        for (name, prob) in opts.dwell.iter() {
+            let input = find_input(ctx, sys, name).expect("unknown dwell input");
+            dwell.insert(input, *prob);
+        }
+
+        // user provided distributions take precedence over the learned model
+        let modelled = opts
+            .input_model
+            .iter()
+            .flat_map(|m| m.inputs.iter().enumerate())
+            .map(|(ii, m)| {
+                let input = find_input(ctx, sys, &m.name).expect("unknown model input");
+                (input, ii)
+            })
+            .filter(|(input, _)| !stimulus.contains_key(input))
+            .collect();
+
+        // declared handshakes take precedence over inferred ones
+        let mut handshakes = opts
+            .handshakes
+            .iter()
+            .map(|h| Handshake::new(ctx, sys, h).expect("invalid handshake"))
+            .collect::<Vec<_>>();
+        if opts.infer_handshakes {
+            for inferred in infer_handshakes(ctx, sys) {
+                if !handshakes.iter().any(|h| h.valid == inferred.valid) {
+                    handshakes.push(inferred);
+                }
+            }
+        }
+
+        Self {
+            constraints,
+            unconstrained_inputs,
+            resets,
+            stimulus,
+            corner_prob: opts.corner_prob,
+            dictionary: if opts.dictionary_prob > 0.0 {
+                mine_constants(ctx, sys)
+            } else {
+                HashMap::new()
+            },
+            dictionary_prob: opts.dictionary_prob,
+            cmplog: Some(CmpLog::new(ctx, sys)).filter(|c| opts.cmplog_prob > 0.0 && !c.is_empty()),
+            cmplog_prob: opts.cmplog_prob,
+            swarm: opts.swarm,
+            dwell,
+            model: opts.input_model.clone(),
+            modelled,
+            handshakes,
+        }
+    }
+
+    /// Needs to be called before the generator state is saved for a new trace,
+    /// since the swarm configuration is part of that state.
+    pub fn start_trace(&self, gen: &mut GeneratorState) {
+        gen.previous.clear();
+        gen.model.clear();
+        gen.stalled.clear();
+        if !self.swarm {
+            return;
+        }
+        let inputs = self
+            .constraints
+            .iter()
+            .flat_map(|c| c.inputs().iter())
+            .chain(self.unconstrained_inputs.iter());
+        gen.swarm.clear();
+        for input in inputs {
+            let mode = match gen.rng.gen_range(0..4) {
+                0 => SwarmMode::Constant,
+                1 => SwarmMode::Rare,
+                _ => SwarmMode::Random,
+            };
+            gen.swarm.insert(*input, mode);
+        }
+    }
+
+    fn is_reset(&self, input: ExprRef) -> bool {
+        self.resets.iter().any(|r| r.input == input)
+    }
+
+    /// Makes all signals that the input generation needs to observe visible to the simulator.
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        if let Some(cmplog) = &self.cmplog {
+            cmplog.instrument(ctx, sys);
+        }
+        for handshake in self.handshakes.iter() {
+            make_observable(ctx, sys, handshake.ready);
+        }
+        // constraints are split into conjuncts which the simulator would not keep
+        for cluster in self.constraints.iter() {
+            for expr in cluster.exprs() {
+                make_observable(ctx, sys, *expr);
+            }
+        }
+    }
+
+    /// Checks whether the current inputs fulfill all constraints.
+    pub fn is_valid(&self, ctx: &Context, sim: &Interpreter) -> bool {
+        self.constraints
+            .iter()
+            .all(|cluster| cluster.exprs().iter().all(|expr| is_true(ctx, sim, *expr)))
+    }
+
+    /// Returns true iff the input is driven by a reset protocol or never randomized at all.
+    pub fn is_fixed(&self, input: ExprRef) -> bool {
+        self.is_reset(input)
+            || !(self.unconstrained_inputs.contains(&input)
+                || self.constraints.iter().any(|c| c.inputs().contains(&input)))
+    }
+
+    /// Records comparison operands and pending handshakes in the current cycle.
+    pub fn observe(&self, sim: &Interpreter, gen: &mut GeneratorState) {
+        if let Some(cmplog) = &self.cmplog {
+            cmplog.observe(sim, &mut gen.observations);
+        }
+        gen.stalled.clear();
+        for handshake in self.handshakes.iter() {
+            // inputs that do not influence anything are not part of the simulation
+            let is_high = |e: ExprRef| sim.get(e).and_then(|v| v.to_u64()) == Some(1);
+            if is_high(handshake.valid) && !is_high(handshake.ready) {
+                gen.stalled.extend(handshake.inputs());
+            }
+        }
+    }
+
+    /// Drives all reset inputs for cycle `k` of the current trace.
+    fn apply_resets(&self, gen: &mut GeneratorState, k: StepInt, sim: &mut Interpreter) {
+        for reset in self.resets.iter() {
+            reset.apply(&mut gen.rng, k, sim);
+        }
+    }
+
+    /// Drives all inputs of cycle `k` and evaluates the system. Inputs that were `replayed`
+    /// from a recorded trace are kept unless they violate a constraint. Returns `Ok(false)`
+    /// if the constraints cannot be fulfilled, which ends the trace. The failed attempt
+    /// counts as a cycle, otherwise the cycle budget might never run out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn drive_cycle(
+        &self,
+        ctx: &Context,
+        gen: &mut GeneratorState,
+        k: StepInt,
+        sim: &mut Interpreter,
+        replayed: bool,
+        cycle_count: &mut u64,
+        max_cycles: Option<u64>,
+    ) -> Result<bool, ModelCheckResult> {
+        if replayed {
+            self.apply_resets(gen, k, sim);
+            sim.update();
+            if self.is_valid(ctx, sim) {
+                return Ok(true);
+            }
+        }
+        if !self.randomize(ctx, gen, k, sim) {
+            return if count_cycle(cycle_count, max_cycles) {
+                Err(ModelCheckResult::Unknown)
+            } else {
+                Ok(false)
+            };
+        }
+        sim.update(); // FIXME: support partial re-evaluation!
+        Ok(true)
+    }
+
+    /// Assigns inputs for cycle `k` of the current trace. Returns `false` if the constraints
+    /// cannot be fulfilled in the current state, which ends the trace.
+    pub fn randomize(
+        &self,
+        ctx: &Context,
+        gen: &mut GeneratorState,
+        k: StepInt,
+        sim: &mut Interpreter,
+    ) -> bool {
+        // resets are driven by the reset protocol and thus never randomized
+        self.apply_resets(gen, k, sim);
+
+        if let Some(model) = &self.model {
+            if k == 0 {
+                gen.model.clear();
+            }
+            model.step(&mut gen.rng, &mut gen.model);
+        }
+
+        // randomize constrained inputs
+        for cluster in self.constraints.iter() {
+            let mut attempts = 0;
+            loop {
+                // randomize all inputs in cluster, unless we cannot find a solution
+                // with the held values
+                let may_hold = attempts < MAX_HOLD_ATTEMPTS;
+                for input in cluster.inputs().iter().filter(|i| !self.is_reset(**i)) {
+                    self.randomize_symbol(ctx, gen, *input, k, may_hold, sim);
+                }
+
+                // recalculate values
+                sim.update(); // FIXME: support partial re-evaluation!
+
+                // check to see if constraints are fulfilled
+                let ok = cluster.exprs().iter().all(|expr| is_true(ctx, sim, *expr));
+                // if they are, we are done here
+                if ok {
+                    break;
+                }
+                attempts += 1;
+                let randomized = cluster.inputs().iter().any(|i| !self.is_reset(*i));
+                if attempts >= MAX_CONSTRAINT_ATTEMPTS || !randomized {
+                    if !gen.reported_unsat {
+                        gen.reported_unsat = true;
+                        let failed = cluster
+                            .exprs()
+                            .iter()
+                            .find(|e| !is_true(ctx, sim, **e))
+                            .unwrap();
+                        println!(
+                            "Ending trace in cycle {k}, failed to fulfill constraint: {}",
+                            describe(ctx, *failed, MAX_DEPTH)
+                        );
+                    }
+                    return false;
+                }
+            }
+        }
+
+        // randomize other inputs
+        for input in self.unconstrained_inputs.iter() {
+            self.randomize_symbol(ctx, gen, *input, k, true, sim);
+        }
+        true
+    }
+
+    /// Decides whether `input` keeps its value from the previous cycle of the current trace.
+    /// A pending transfer cannot be changed or withdrawn.
+    fn is_stalled(gen: &GeneratorState, input: ExprRef, k: StepInt) -> bool {
+        k > 0 && gen.previous.contains_key(&input) && gen.stalled.contains(&input)
+    }
+
+    fn holds_value(&self, gen: &mut GeneratorState, input: ExprRef, k: StepInt) -> bool {
+        // every trace starts with a fresh value
+        if k == 0 || !gen.previous.contains_key(&input) {
+            return false;
+        }
+        if Self::is_stalled(gen, input, k) {
+            return true;
+        }
+        if let Some(spec) = self.stimulus.get(&input) {
+            if !k.is_multiple_of(spec.hold) {
+                return true;
+            }
+        }
+        let swarm_hold = match gen.swarm.get(&input) {
+            Some(SwarmMode::Constant) => true,
+            Some(SwarmMode::Rare) => !gen.rng.gen_bool(SWARM_RARE_CHANGE_PROB),
+            Some(SwarmMode::Random) | None => false,
+        };
+        // learned probabilities for the current trace take precedence over the configured ones
+        let dwell = gen.dwell.get(&input).or(self.dwell.get(&input)).copied();
+        swarm_hold || dwell.is_some_and(|p| p > 0.0 && gen.rng.gen_bool(p))
+    }
+
+    fn randomize_symbol(
+        &self,
+        ctx: &Context,
+        gen: &mut GeneratorState,
+        symbol: ExprRef,
+        k: StepInt,
+        may_hold: bool,
+        sim: &mut Interpreter,
+    ) {
+        // even a modelled input has to keep its value while its transfer is stalled
+        if may_hold && Self::is_stalled(gen, symbol, k) {
+            let width = symbol.get_bv_type(ctx).unwrap();
+            sim.set(symbol, ValueRef::new(&[gen.previous[&symbol]], width));
+            return;
+        }
+        // the model is abandoned when its values cannot fulfill the constraints
+        if let Some(index) = self.modelled.get(&symbol).filter(|_| may_hold) {
+            let width = symbol.get_bv_type(ctx).unwrap();
+            let value = gen.model.value(*index);
+            sim.set(symbol, ValueRef::new(&[value], width));
+            gen.previous.insert(symbol, value);
+            return;
+        }
+        if may_hold && self.holds_value(gen, symbol, k) {
+            let width = symbol.get_bv_type(ctx).unwrap();
+            sim.set(symbol, ValueRef::new(&[gen.previous[&symbol]], width));
+            return;
+        }
+        let spec = self.stimulus.get(&symbol);
+        let GeneratorState {
+            rng, observations, ..
+        } = gen;
+        match ctx.get(symbol).get_bv_type(ctx) {
+            Some(width) => {
+                if width <= 64 {
+                    debug_assert_eq!(Word::BITS, 64);
+                    let dictionary = self.dictionary.get(&symbol);
+                    let value = match spec {
+                        Some(spec) => spec.sample(rng, width),
+                        None if dictionary.is_some() && rng.gen_bool(self.dictionary_prob) => {
+                            let values = dictionary.unwrap();
+                            values[rng.gen_range(0..values.len())]
+                        }
+                        None if self.cmplog.is_some()
+                            && observations.has_values(symbol)
+                            && rng.gen_bool(self.cmplog_prob) =>
+                        {
+                            observations.sample(rng, symbol, width).unwrap()
+                        }
+                        None if self.corner_prob > 0.0 && rng.gen_bool(self.corner_prob) => {
+                            let corners = corner_values(width);
+                            corners[rng.gen_range(0..corners.len())]
+                        }
+                        None => (rng.next_u64() as Word) & mask(width),
+                    };
+                    let words = [value];
+                    sim.set(symbol, ValueRef::new(&words, width));
+                    gen.previous.insert(symbol, value);
+                } else {
+                    todo!("generate value wider than 64-bit");
+                }
+            }
+            None => {
+                todo!("support array type inputs");
+            }
+        }
+    }
+}
+
+/// Values at the boundaries of the unsigned and signed ranges of a bit-vector,
+/// as well as their direct neighbours.
+pub fn corner_values(width: WidthInt) -> SmallVec<[Word; 8]> {
+    let max = mask(width);
+    let msb = 1 << (width - 1);
+    let mut out: SmallVec<[Word; 8]> =
+        smallvec![0, 1, 2, max, max.wrapping_sub(1), msb, msb - 1, msb + 1];
+    for value in out.iter_mut() {
+        *value &= max;
+    }
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+/// Looks up a bit-vector input by its name.
+pub fn find_input(ctx: &Context, sys: &TransitionSystem, name: &str) -> Option<ExprRef> {
+    sys.get_signals(|s| s.is_input())
+        .into_iter()
+        .map(|(e, _)| e)
+        .find(|e| e.get_symbol_name(ctx) == Some(name))
+}
+
+/// Value of a 1-bit condition, literals are never evaluated by the simulator.
+fn is_true(ctx: &Context, sim: &Interpreter, e: ExprRef) -> bool {
+    match ctx.get(e) {
+        Expr::BVLiteral { value, .. } => *value == 1,
+        _ => sim.get(e).unwrap().to_u64().unwrap() == 1,
+    }
+}
+
+/// Counts an executed cycle. Returns `true` once the cycle budget is used up.
+pub fn count_cycle(cycle_count: &mut u64, max_cycles: Option<u64>) -> bool {
+    *cycle_count += 1;
+    let done = max_cycles.is_some_and(|max_cycles| max_cycles <= *cycle_count);
+    if done {
+        println!("Exciting after executing {} cycles.", cycle_count);
+    }
+    done
+}
+
+/// Makes sure that the value of `expr` can be read from the simulator, which only
+/// keeps symbols and expressions with an output, bad, constraint or fairness label.
+pub fn make_observable(ctx: &Context, sys: &mut TransitionSystem, expr: ExprRef) {
+    // literals are never evaluated by the simulator
+    if expr.is_symbol(ctx) || expr.is_bv_lit(ctx) {
+        return;
+    }
+    match sys.get_signal(expr) {
+        Some(info) if !info.labels.is_none() => {}
+        info => {
+            // keep the name of nodes that are merely named
+            let name = info.and_then(|i| i.name);
+            sys.add_signal(expr, SignalKind::Node, SignalLabels::output(), name);
+        }
+    }
 }
 
 pub fn random_testing(
     mut ctx: Context,
-    sys: TransitionSystem,
+    mut sys: TransitionSystem,
     opts: RandomOptions,
     seed: u64,
+    progress: &mut Progress,
 ) -> ModelCheckResult {
     // println!("{}", sys.serialize_to_str(&ctx));
 
-    // collect constraints for input randomization
-    let constraints = analyze_constraints(&mut ctx, &sys, false);
-
-    // find out which inputs are unconstrained
-    let constrained_inputs = constraints
-        .iter()
-        .map(|c| c.inputs().to_vec())
-        .flatten()
-        .collect::<HashSet<_>>();
-    let unconstrained_inputs = sys
-        .get_signals(|s| s.is_input())
-        .iter()
-        .map(|(s, _)| *s)
-        .filter(|s| !constrained_inputs.contains(s))
-        .collect::<Vec<_>>();
+    let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+    inputs.instrument(&ctx, &mut sys);
+    progress.instrument(&ctx, &mut sys);
+    let mut scheduler = if opts.adaptive_k {
+        match LengthScheduler::new(&opts) {
+            Ok(scheduler) => Some(scheduler),
+            Err(e) => {
+                println!("adaptive-k: {e}");
+                return ModelCheckResult::Unknown;
+            }
+        }
+    } else {
+        None
+    };
+    let mut dwell_learner = opts.learn_dwell.then(|| {
+        let randomized = sys
+            .get_signals(|s| s.is_input())
+            .into_iter()
+            .map(|(i, _)| i)
+            .filter(|i| !inputs.is_fixed(*i));
+        DwellLearner::new(randomized)
+    });
+    // all learners are rewarded by the fraction of cycles in which a trace makes progress
+    let mut feedback = (opts.adaptive_k || opts.learn_dwell).then(|| {
+        let feedback = TraceFeedback::new(&mut ctx, &sys);
+        feedback.instrument(&ctx, &mut sys);
+        feedback
+    });
 
     // collect bad states
     let bad_states = sys
@@ -63,76 +713,127 @@ pub fn random_testing(
     // we initialize all states to zero, since most bugs are not reset initialization bugs
     sim.init(InitKind::Zero);
 
+    // replay the prefix, witnesses still need to start in the initial state
+    let mut state_init = Vec::new();
+    let k_start = match &opts.prefix {
+        Some(prefix) => {
+            prefix.init(&ctx, &sys, &mut sim);
+            for (_, state) in sys.states() {
+                state_init.extend_from_slice(sim.get(state.symbol).unwrap().words());
+            }
+            match prefix.replay(&ctx, &sys, &inputs, &bad_states, &mut sim) {
+                Ok(Some((k, bads))) => {
+                    return ModelCheckResult::Sat(prefix.witness(&sys, state_init, k, bads));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!("{e}");
+                    return ModelCheckResult::Unknown;
+                }
+            }
+            prefix.cycles()
+        }
+        None => 0,
+    };
+
     // take a snapshot so that we can go back to the initial state
     let start_state = sim.take_snapshot();
 
     // create random number generator
-    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(seed);
+    let mut gen = GeneratorState::new(seed);
 
     // main loop
     let mut cycle_count = 0;
     loop {
-        let k_max = sample_k_max(&mut rng, &opts);
+        let (bucket, k_max) = match &scheduler {
+            Some(scheduler) => scheduler.sample(&mut gen.rng),
+            None => (0, sample_k_max(&mut gen.rng, &opts)),
+        };
 
         // restore starting state
         sim.restore_snapshot(start_state);
+        if let Some(feedback) = &mut feedback {
+            feedback.start(&sim);
+        }
 
         // save state of random number generator
-        let rng_start = rng.clone();
+        if let Some(learner) = &mut dwell_learner {
+            gen.dwell = learner.sample(&mut gen.rng);
+        }
+        inputs.start_trace(&mut gen);
+        let gen_start = gen.clone();
 
         for k in 0..=k_max {
-            // randomize inputs to the system
-            randomize_inputs(
-                &mut ctx,
-                &mut rng,
-                &constraints,
-                &unconstrained_inputs,
+            // randomize inputs to the system, cycles are counted from the initial state
+            match inputs.drive_cycle(
+                &ctx,
+                &mut gen,
+                k_start + k,
                 &mut sim,
-            );
-            sim.update(); // FIXME: support partial re-evaluation!
+                false,
+                &mut cycle_count,
+                opts.max_cycles,
+            ) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(result) => return result,
+            }
+            inputs.observe(&sim, &mut gen);
+            progress.record(&ctx, &sim);
+            if let Some(feedback) = &mut feedback {
+                feedback.record(&ctx, &sim);
+            }
 
             // check if we are in a bad state
-            let bads = check_for_bad_states(&mut ctx, &bad_states, &mut sim);
+            let bads = check_for_bad_states(&bad_states, &sim);
             if !bads.is_empty() {
                 sim.restore_snapshot(start_state);
                 let wit = record_witness(
-                    &mut ctx,
+                    &ctx,
                     &sys,
-                    &constraints,
-                    &unconstrained_inputs,
+                    &inputs,
                     &bad_states,
                     &mut sim,
-                    rng_start,
+                    gen_start,
+                    k_start,
                     k,
                     bads,
                 );
+                let wit = match &opts.prefix {
+                    Some(prefix) => prefix.extend(wit, state_init),
+                    None => wit,
+                };
                 return ModelCheckResult::Sat(wit);
             }
 
             // advance the system
             sim.step();
-            cycle_count += 1;
-            if let Some(max_cycles) = opts.max_cycles {
-                if max_cycles <= cycle_count {
-                    println!("Exciting after executing {} cycles.", cycle_count);
-                    return ModelCheckResult::Unknown;
-                }
+            if count_cycle(&mut cycle_count, opts.max_cycles) {
+                return ModelCheckResult::Unknown;
+            }
+        }
+        if let Some(feedback) = &feedback {
+            let reward = feedback.reward();
+            if let Some(scheduler) = &mut scheduler {
+                scheduler.reward(bucket, reward);
+            }
+            if let Some(learner) = &mut dwell_learner {
+                learner.reward(reward);
             }
         }
     }
 }
 
-fn find_inputs() {}
-
 /// replays random execution in order to record the witness
+#[allow(clippy::too_many_arguments)]
 fn record_witness(
     ctx: &Context,
     sys: &TransitionSystem,
-    constraints: &[ConstraintCluster],
-    unconstrained_inputs: &[ExprRef],
+    inputs: &InputDriver,
     bad_states: &[ExprRef],
     sim: &mut Interpreter,
-    mut rng: rand_xoshiro::Xoshiro256PlusPlus,
+    mut gen: GeneratorState,
+    k_start: StepInt,
     k_bad: StepInt,
     bads: Vec<usize>,
 ) -> Witness {
@@ -145,33 +846,19 @@ fn record_witness(
     let mut input_data = Vec::new();
     for k in 0..=k_bad {
         // randomize inputs to the system
-        randomize_inputs(ctx, &mut rng, constraints, unconstrained_inputs, sim);
+        let ok = inputs.randomize(ctx, &mut gen, k_start + k, sim);
+        debug_assert!(ok, "the replay needs to fulfill the constraints");
 
-        // TODO: implement this without tunneling through the sim!
-        for (expr, info) in sys.get_signals(|s| s.is_input()) {
-            if let Some(value) = sim.get(expr) {
-                input_data.extend_from_slice(value.words());
-            } else {
-                let width = ctx.get(expr).get_bv_type(ctx).unwrap();
-                if width > Word::BITS {
-                    println!(
-                        "TODO: deal with missing input {} of width: {}",
-                        ctx.get(info.name.unwrap()),
-                        width
-                    );
-                } else {
-                    input_data.push(0);
-                }
-            }
-        }
+        record_inputs(ctx, sys, sim, &mut input_data);
 
         // TODO: remove
         sim.update();
+        inputs.observe(sim, &mut gen);
 
         // sanity check constraints
-        for cluster in constraints.iter() {
+        for cluster in inputs.constraints.iter() {
             for expr in cluster.exprs() {
-                let is_ok = sim.get(*expr).unwrap().to_u64().unwrap() == 1;
+                let is_ok = is_true(ctx, sim, *expr);
                 debug_assert!(
                     is_ok,
                     "{k}: failed {} in {:?}",
@@ -183,7 +870,7 @@ fn record_witness(
 
         if k == k_bad {
             // sanity check bad
-            let bads = check_for_bad_states(ctx, bad_states, sim);
+            let bads = check_for_bad_states(bad_states, sim);
             debug_assert!(!bads.is_empty());
         }
         sim.step();
@@ -197,7 +884,33 @@ fn record_witness(
     }
 }
 
-fn sample_k_max(rng: &mut impl Rng, opts: &RandomOptions) -> StepInt {
+/// Appends the current value of all inputs to `input_data`, in the format expected by [`Witness`].
+pub fn record_inputs(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    sim: &Interpreter,
+    input_data: &mut Vec<Word>,
+) {
+    // TODO: implement this without tunneling through the sim!
+    for (expr, info) in sys.get_signals(|s| s.is_input()) {
+        if let Some(value) = sim.get(expr) {
+            input_data.extend_from_slice(value.words());
+        } else {
+            let width = ctx.get(expr).get_bv_type(ctx).unwrap();
+            if width > Word::BITS {
+                println!(
+                    "TODO: deal with missing input {} of width: {}",
+                    ctx.get(info.name.unwrap()),
+                    width
+                );
+            } else {
+                input_data.push(0);
+            }
+        }
+    }
+}
+
+pub fn sample_k_max(rng: &mut impl Rng, opts: &RandomOptions) -> StepInt {
     let pick_large_k = rng.gen_bool(opts.large_k_prob);
     if pick_large_k {
         rng.gen_range(opts.small_k..(opts.large_k + 1))
@@ -206,11 +919,7 @@ fn sample_k_max(rng: &mut impl Rng, opts: &RandomOptions) -> StepInt {
     }
 }
 
-fn check_for_bad_states(
-    ctx: &Context,
-    bad_states: &[ExprRef],
-    sim: &mut Interpreter,
-) -> Vec<usize> {
+pub fn check_for_bad_states(bad_states: &[ExprRef], sim: &Interpreter) -> Vec<usize> {
     let mut out = Vec::with_capacity(0);
 
     for (index, expr) in bad_states.iter().enumerate() {
@@ -223,65 +932,193 @@ fn check_for_bad_states(
     out
 }
 
-fn randomize_inputs(
-    ctx: &Context,
-    rng: &mut impl Rng,
-    constraints: &[ConstraintCluster],
-    unconstrained_inputs: &[ExprRef],
-    sim: &mut Interpreter,
-) {
-    // randomize constrained inputs
-    for cluster in constraints.iter() {
-        loop {
-            // randomize all inputs in cluster
-            for input in cluster.inputs().iter() {
-                randomize_symbol(ctx, rng, *input, sim);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // recalculate values
-            sim.update(); // FIXME: support partial re-evaluation!
-
-            // check to see if constraints are fulfilled
-            let ok = cluster
-                .exprs()
-                .iter()
-                .all(|expr| sim.get(*expr).unwrap().to_u64().unwrap() == 1);
-            // if they are, we are done here
-            if ok {
-                break;
-            }
+    #[test]
+    fn test_corner_values() {
+        assert_eq!(corner_values(1).as_slice(), [0, 1]);
+        assert_eq!(corner_values(2).as_slice(), [0, 1, 2, 3]);
+        assert_eq!(
+            corner_values(8).as_slice(),
+            [0, 1, 2, 0x7f, 0x80, 0x81, 0xfe, 0xff]
+        );
+        assert_eq!(
+            corner_values(64).as_slice(),
+            [
+                0,
+                1,
+                2,
+                i64::MAX as u64,
+                1 << 63,
+                (1 << 63) + 1,
+                u64::MAX - 1,
+                u64::MAX
+            ]
+        );
+    }
+
+    #[test]
+    fn test_swarm_constant_input() {
+        let (mut ctx, sys) = patronus::btor2::parse_file("inputs/easy.btor").unwrap();
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.swarm = true;
+        let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+        let input = sys.get_signals(|s| s.is_input())[0].0;
+        let mut gen = GeneratorState::new(0);
+        inputs.start_trace(&mut gen);
+        assert!(gen.swarm.contains_key(&input));
+        gen.swarm.insert(input, SwarmMode::Constant);
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        inputs.randomize(&ctx, &mut gen, 0, &mut sim);
+        let first = sim.get(input).unwrap().to_u64();
+        for k in 1..10 {
+            sim.step();
+            inputs.randomize(&ctx, &mut gen, k, &mut sim);
+            assert_eq!(sim.get(input).unwrap().to_u64(), first);
         }
     }
 
-    // randomize other inputs
-    for input in unconstrained_inputs.iter() {
-        randomize_symbol(ctx, rng, *input, sim);
+    #[test]
+    fn test_unsatisfiable_constraints_end_trace() {
+        // `a` cannot be one and zero at once, `x` is a state that cannot be changed
+        let src = "1 sort bitvec 1\n2 input 1 a\n3 state 1 x\n4 zero 1\n5 init 1 3 4\n\
+                   6 next 1 3 2\n7 not 1 2\n8 and 1 2 7\n9 constraint 8\n10 constraint 3\n\
+                   11 bad 2\n";
+        let mut ctx = Context::default();
+        let mut sys = patronus::btor2::parse_str(&mut ctx, src, None).unwrap();
+        let inputs = InputDriver::new(&mut ctx, &sys, &crate::RANDOM_OPTS);
+        assert_eq!(inputs.constraints.len(), 2);
+        inputs.instrument(&ctx, &mut sys);
+        let mut gen = GeneratorState::new(0);
+        inputs.start_trace(&mut gen);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        assert!(!inputs.randomize(&ctx, &mut gen, 0, &mut sim));
+        assert!(gen.reported_unsat);
     }
-}
 
-fn randomize_symbol(ctx: &Context, rng: &mut impl Rng, symbol: ExprRef, sim: &mut Interpreter) {
-    match ctx.get(symbol).get_bv_type(ctx) {
-        Some(width) => {
-            if width <= 64 {
-                let mask = mask(width);
-                debug_assert_eq!(Word::BITS, 64);
-                let value = (rng.next_u64() as Word) & mask;
-                let words = [value];
-                sim.set(symbol, ValueRef::new(&words, width));
-            } else {
-                todo!("generate value wider than 64-bit");
-            }
+    #[test]
+    fn test_constrained_reset_is_randomized() {
+        // `rst` looks like a reset, but the constraint keeps it asserted
+        let src = "1 sort bitvec 1\n2 sort bitvec 4\n3 input 1 rst\n4 state 2 count\n\
+                   5 zero 2\n6 init 2 4 5\n7 one 2\n8 add 2 4 7\n9 ite 2 3 5 8\n\
+                   10 next 2 4 9\n11 constraint 3\n12 ones 2\n13 eq 1 4 12\n14 bad 13\n";
+        let mut ctx = Context::default();
+        let mut sys = patronus::btor2::parse_str(&mut ctx, src, None).unwrap();
+        let rst = find_input(&ctx, &sys, "rst").unwrap();
+        let inputs = InputDriver::new(&mut ctx, &sys, &crate::RANDOM_OPTS);
+        assert!(!inputs.is_reset(rst));
+        inputs.instrument(&ctx, &mut sys);
+        let mut gen = GeneratorState::new(0);
+        inputs.start_trace(&mut gen);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        for k in 0..20 {
+            assert!(inputs.randomize(&ctx, &mut gen, k, &mut sim));
+            assert_eq!(sim.get(rst).unwrap().to_u64(), Some(1));
+            sim.step();
         }
-        None => {
-            todo!("support array type inputs");
+    }
+
+    #[test]
+    fn test_dwell_keeps_value() {
+        let (mut ctx, sys) = patronus::btor2::parse_file("inputs/easy.btor").unwrap();
+        let input = sys.get_signals(|s| s.is_input())[0].0;
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.dwell = vec![(input.get_symbol_name(&ctx).unwrap().to_string(), 1.0)];
+        let inputs = InputDriver::new(&mut ctx, &sys, &opts);
+        let mut gen = GeneratorState::new(0);
+        inputs.start_trace(&mut gen);
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        inputs.randomize(&ctx, &mut gen, 0, &mut sim);
+        let first = sim.get(input).unwrap().to_u64();
+        for k in 1..10 {
+            sim.step();
+            inputs.randomize(&ctx, &mut gen, k, &mut sim);
+            assert_eq!(sim.get(input).unwrap().to_u64(), first);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Design with a valid/ready input port that is only ready every other cycle.
+    fn stalling_design() -> (Context, TransitionSystem) {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let valid = ctx.bv_symbol("in_valid", 1);
+        let data = ctx.bv_symbol("in_data", 8);
+        sys.add_input(&ctx, valid);
+        sys.add_input(&ctx, data);
+        let busy = ctx.bv_symbol("busy", 1);
+        let state = sys.add_state(&ctx, busy);
+        let not_busy = ctx.not(busy);
+        sys.modify_state(state, |s| s.next = Some(not_busy));
+        let name = ctx.add_node("in_ready");
+        sys.add_signal(
+            not_busy,
+            SignalKind::Node,
+            SignalLabels::output(),
+            Some(name),
+        );
+        let all_ones = ctx.bv_lit(0xff, 8);
+        let is_all_ones = ctx.bv_equal(data, all_ones);
+        let bad = ctx.and(valid, is_all_ones);
+        sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+        (ctx, sys)
+    }
+
+    /// Checks that data is held whenever the design was not ready for a valid transfer.
+    fn assert_holds_pending_transfer(opts: &RandomOptions) {
+        let (mut ctx, mut sys) = stalling_design();
+        let [valid, data] = ["in_valid", "in_data"].map(|n| find_input(&ctx, &sys, n).unwrap());
+        let not_busy = sys.get_signals(|s| s.labels.is_output())[0].0;
+        let inputs = InputDriver::new(&mut ctx, &sys, opts);
+        inputs.instrument(&ctx, &mut sys);
+        let mut gen = GeneratorState::new(0);
+        inputs.start_trace(&mut gen);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut stalls = 0;
+        let mut previous: Option<(u64, u64, bool)> = None;
+        for k in 0..100 {
+            inputs.randomize(&ctx, &mut gen, k, &mut sim);
+            sim.update();
+            inputs.observe(&sim, &mut gen);
+            let get = |e: ExprRef| sim.get(e).unwrap().to_u64().unwrap();
+            let current = (get(valid), get(data), get(not_busy) == 1);
+            if let Some((1, prev_data, false)) = previous {
+                assert_eq!((current.0, current.1), (1, prev_data));
+                stalls += 1;
+            }
+            previous = Some(current);
+            sim.step();
+        }
+        assert!(stalls > 0);
+    }
+
+    #[test]
+    fn test_handshake_holds_pending_transfer() {
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.infer_handshakes = true;
+        assert_holds_pending_transfer(&opts);
+    }
 
     #[test]
-    fn type_size() {}
+    fn test_handshake_holds_modelled_input() {
+        // in the example traffic, a new data value is sent in every cycle
+        let trace = crate::vcd::VcdTrace {
+            names: vec!["tb.in_valid".to_string(), "tb.in_data".to_string()],
+            widths: vec![1, 8],
+            cycles: (0..60u64).map(|k| vec![1, k]).collect(),
+        };
+        let names = [("in_valid".to_string(), 1), ("in_data".to_string(), 8)];
+        let mut opts = crate::RANDOM_OPTS.clone();
+        opts.infer_handshakes = true;
+        opts.input_model = Some(InputModel::fit(&[trace], &names).unwrap());
+        assert_holds_pending_transfer(&opts);
+    }
 }
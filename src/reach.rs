@@ -0,0 +1,483 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Symbolic reachability with BDDs: the bit-blasted system is converted into BDDs and the
+// reachable states are computed by image computation until a bad state shows up or
+// nothing new is reached.
+
+use crate::bdd::{self, Bdd, Manager, FALSE, TRUE};
+use crate::bitblast::{BitBlaster, Gate};
+use crate::certificate::Certificate;
+use crate::sat::{Lit, Solver};
+use crate::unroll::Unroller;
+use crate::{ModelCheckResult, StepInt, Witness};
+use patronus::ir::*;
+use std::collections::HashMap;
+
+pub struct Reachability {
+    bdds: Manager,
+    state_symbols: Vec<ExprRef>,
+    /// BDD variable of every state bit, the next state variable is always the one right after
+    states: Vec<Vec<u32>>,
+    inputs: Vec<Vec<u32>>,
+    init: Bdd,
+    /// next state function of every state bit, `None` if the state can take any value
+    next: Vec<Vec<Option<Bdd>>>,
+    constraints: Bdd,
+    bad_states: Vec<Bdd>,
+    any_bad: Bdd,
+    /// variables that do not appear in the transition relation
+    unused: Bdd,
+    /// one relation per state bit with the variables that can be quantified right after it
+    partitions: Vec<(Bdd, Bdd)>,
+    next_to_current: HashMap<u32, u32>,
+}
+
+impl Reachability {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Result<Self, String> {
+        let mut solver = Solver::new();
+        let mut unroller = Unroller::new(ctx, sys, &mut solver, false)?;
+        unroller.add_frame(ctx, &mut solver);
+        let mut bdds = Manager::new();
+
+        // inputs come first, current and next state bits are interleaved
+        let mut bits = HashMap::new();
+        let mut count = 0;
+        let mut new_vars = |lits: &[Lit], step: u32| -> bdd::Result<Vec<u32>> {
+            let mut vars = Vec::with_capacity(lits.len());
+            for lit in lits.iter() {
+                bits.insert(lit.var(), bdds.var(count)?);
+                vars.push(count);
+                count += step;
+            }
+            Ok(vars)
+        };
+        let num_inputs = sys.get_signals(|s| s.is_input()).len();
+        let inputs = (0..num_inputs)
+            .map(|ii| new_vars(unroller.input(0, ii), 1))
+            .collect::<bdd::Result<Vec<_>>>()?;
+        let states = (0..sys.states().count())
+            .map(|ii| new_vars(unroller.state(0, ii), 2))
+            .collect::<bdd::Result<Vec<_>>>()?;
+
+        let mut convert = |unroller: &mut Unroller, solver: &mut Solver, expr: ExprRef| {
+            let lits = unroller.expr(ctx, solver, 0, expr);
+            lits.iter()
+                .map(|lit| to_bdd(&mut bdds, &unroller.blaster, &mut bits, *lit))
+                .collect::<bdd::Result<Vec<_>>>()
+        };
+        let mut init_parts = Vec::new();
+        let mut next = Vec::new();
+        for ((_, state), vars) in sys.states().zip(states.iter()) {
+            if let Some(init) = state.init {
+                let values = convert(&mut unroller, &mut solver, init)?;
+                init_parts.extend(vars.iter().copied().zip(values));
+            }
+            next.push(match state.next {
+                Some(e) => convert(&mut unroller, &mut solver, e)?
+                    .into_iter()
+                    .map(Some)
+                    .collect(),
+                None => vec![None; vars.len()],
+            });
+        }
+        let constraints = sys
+            .constraints()
+            .into_iter()
+            .map(|(e, _)| Ok(convert(&mut unroller, &mut solver, e)?[0]))
+            .collect::<bdd::Result<Vec<_>>>()?;
+        let bad_states = sys
+            .bad_states()
+            .into_iter()
+            .map(|(e, _)| Ok(convert(&mut unroller, &mut solver, e)?[0]))
+            .collect::<bdd::Result<Vec<_>>>()?;
+
+        let mut constraint = TRUE;
+        for c in constraints {
+            constraint = bdds.and(constraint, c)?;
+        }
+        let mut any_bad = FALSE;
+        for b in bad_states.iter() {
+            any_bad = bdds.or(any_bad, *b)?;
+        }
+        // initial values may depend on inputs
+        let mut init = TRUE;
+        for (var, value) in init_parts {
+            let var = bdds.var(var)?;
+            let same = bdds.xor(var, value)?;
+            let same = bdds.not(same)?;
+            init = bdds.and(init, same)?;
+        }
+        let input_vars = inputs.iter().flatten().copied().collect::<Vec<_>>();
+        let input_cube = bdds.cube(&input_vars)?;
+        let init = bdds.exists(init, input_cube)?;
+
+        // every current state and input variable is quantified after the last relation it occurs in
+        let mut relations = Vec::new();
+        for (vars, functions) in states.iter().zip(next.iter()) {
+            for (var, function) in vars.iter().zip(functions.iter()) {
+                if let Some(function) = function {
+                    let next_var = bdds.var(var + 1)?;
+                    let same = bdds.xor(next_var, *function)?;
+                    relations.push(bdds.not(same)?);
+                }
+            }
+        }
+        let mut last_use = HashMap::new();
+        for (ii, relation) in relations.iter().enumerate() {
+            for var in bdds.support(*relation) {
+                last_use.insert(var, ii);
+            }
+        }
+        let mut quantify = vec![Vec::new(); relations.len()];
+        let mut unused = Vec::new();
+        let current = states.iter().flatten().copied();
+        for var in input_vars.iter().copied().chain(current) {
+            match last_use.get(&var) {
+                Some(ii) => quantify[*ii].push(var),
+                None => unused.push(var),
+            }
+        }
+        let partitions = relations
+            .into_iter()
+            .zip(quantify)
+            .map(|(relation, vars)| Ok((relation, bdds.cube(&vars)?)))
+            .collect::<bdd::Result<_>>()?;
+        let unused = bdds.cube(&unused)?;
+        let next_to_current = states.iter().flatten().map(|v| (v + 1, *v)).collect();
+
+        Ok(Self {
+            bdds,
+            state_symbols: sys.states().map(|(_, s)| s.symbol).collect(),
+            states,
+            inputs,
+            init,
+            next,
+            constraints: constraint,
+            bad_states,
+            any_bad,
+            unused,
+            partitions,
+            next_to_current,
+        })
+    }
+
+    /// States that can be reached in one step.
+    fn image(&mut self, states: Bdd) -> bdd::Result<Bdd> {
+        let product = self.bdds.and(states, self.constraints)?;
+        let mut product = self.bdds.exists(product, self.unused)?;
+        for (relation, cube) in self.partitions.iter() {
+            product = self.bdds.and_exists(product, *relation, *cube)?;
+        }
+        self.bdds.rename(product, &self.next_to_current)
+    }
+
+    /// Computes the reachable states breadth first. Returns the shortest counterexample,
+    /// `UnSat` with the reachable states as invariant or an error once the BDDs get too large.
+    pub fn run(
+        &mut self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+    ) -> Result<ModelCheckResult, String> {
+        let mut reached = self.init;
+        // states that were reached for the first time in each step
+        let mut frontiers = vec![self.init];
+        loop {
+            let frontier = *frontiers.last().unwrap();
+            let allowed = self.bdds.and(frontier, self.constraints)?;
+            let bad = self.bdds.and(allowed, self.any_bad)?;
+            if bad != FALSE {
+                return Ok(ModelCheckResult::Sat(self.witness(&frontiers, bad)?));
+            }
+            let image = self.image(frontier)?;
+            let not_reached = self.bdds.not(reached)?;
+            let new = self.bdds.and(image, not_reached)?;
+            if new == FALSE {
+                let mut ctx = ctx.clone();
+                let invariant = self.to_expr(&mut ctx, reached);
+                return Ok(ModelCheckResult::UnSat(Some(Certificate::invariant(
+                    &ctx, sys, invariant,
+                ))));
+            }
+            reached = self.bdds.or(reached, new)?;
+            frontiers.push(new);
+        }
+    }
+
+    /// Picks concrete states and inputs backwards from a bad state in the last frontier.
+    fn witness(&mut self, frontiers: &[Bdd], bad: Bdd) -> bdd::Result<Witness> {
+        let assignment = |bdds: &Manager, f: Bdd| -> HashMap<u32, bool> {
+            bdds.pick(f).unwrap().into_iter().collect()
+        };
+        let mut steps = vec![assignment(&self.bdds, bad)];
+        for frontier in frontiers.iter().rev().skip(1) {
+            let target = steps.last().unwrap();
+            let mut pre = self.bdds.and(*frontier, self.constraints)?;
+            for (vars, functions) in self.states.iter().zip(self.next.iter()) {
+                for (var, function) in vars.iter().zip(functions.iter()) {
+                    if let Some(function) = function {
+                        let value = target.get(var).copied().unwrap_or(false);
+                        let f = if value {
+                            *function
+                        } else {
+                            self.bdds.not(*function)?
+                        };
+                        pre = self.bdds.and(pre, f)?;
+                    }
+                }
+            }
+            steps.push(assignment(&self.bdds, pre));
+        }
+        steps.reverse();
+
+        let words = |values: &HashMap<u32, bool>, vars: &[u32]| -> Vec<Word> {
+            vars.chunks(Word::BITS as usize)
+                .map(|chunk| {
+                    chunk.iter().enumerate().fold(0, |word, (ii, var)| {
+                        word | ((values.get(var).copied().unwrap_or(false) as Word) << ii)
+                    })
+                })
+                .collect()
+        };
+        let state_init = self
+            .states
+            .iter()
+            .flat_map(|vars| words(&steps[0], vars))
+            .collect();
+        let input_data = steps
+            .iter()
+            .flat_map(|step| self.inputs.iter().flat_map(|vars| words(step, vars)))
+            .collect();
+        let last = steps.last().unwrap();
+        let failed_safety = (0..self.bad_states.len())
+            .filter(|ii| {
+                let value = |var: u32| last.get(&var).copied().unwrap_or(false);
+                self.bdds.eval(self.bad_states[*ii], value)
+            })
+            .collect();
+        Ok(Witness {
+            input_data,
+            state_init,
+            k: (steps.len() - 1) as StepInt,
+            failed_safety,
+        })
+    }
+
+    /// Expresses a set of states as nested if-then-else over the state bits.
+    fn to_expr(&self, ctx: &mut Context, states: Bdd) -> ExprRef {
+        let mut bits = HashMap::new();
+        for (symbol, vars) in self.state_symbols.iter().zip(self.states.iter()) {
+            for (ii, var) in vars.iter().enumerate() {
+                bits.insert(*var, (*symbol, ii as WidthInt));
+            }
+        }
+        let mut exprs = HashMap::from([(FALSE, ctx.zero(1)), (TRUE, ctx.one(1))]);
+        let mut todo = vec![states];
+        while let Some(f) = todo.last().copied() {
+            if exprs.contains_key(&f) {
+                todo.pop();
+                continue;
+            }
+            let (var, lo, hi) = self.bdds.node(f).unwrap();
+            match (exprs.get(&lo), exprs.get(&hi)) {
+                (Some(lo), Some(hi)) => {
+                    let (symbol, bit) = bits[&var];
+                    let cond = ctx.slice(symbol, bit, bit);
+                    let e = ctx.bv_ite(cond, *hi, *lo);
+                    exprs.insert(f, e);
+                    todo.pop();
+                }
+                _ => {
+                    todo.push(lo);
+                    todo.push(hi);
+                }
+            }
+        }
+        exprs[&states]
+    }
+}
+
+/// Builds the BDD of a literal from the gates that the bit blaster introduced. Fresh
+/// variables need to be mapped already.
+fn to_bdd(
+    bdds: &mut Manager,
+    blaster: &BitBlaster,
+    vars: &mut HashMap<u32, Bdd>,
+    lit: Lit,
+) -> bdd::Result<Bdd> {
+    let mut todo = vec![lit];
+    while let Some(lit) = todo.last().copied() {
+        if blaster.const_value(lit).is_some() || vars.contains_key(&lit.var()) {
+            todo.pop();
+            continue;
+        }
+        let gate = blaster
+            .gate(lit.var())
+            .expect("fresh variables need to be mapped");
+        let children = match gate {
+            Gate::And(a, b) | Gate::Xor(a, b) => vec![a, b],
+            Gate::Ite(c, t, f) => vec![c, t, f],
+        };
+        let missing = children
+            .iter()
+            .filter(|c| blaster.const_value(**c).is_none() && !vars.contains_key(&c.var()))
+            .copied()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            todo.extend(missing);
+            continue;
+        }
+        let mut get = |l: Lit| lit_bdd(bdds, blaster, vars, l);
+        let f = match gate {
+            Gate::And(a, b) => {
+                let (a, b) = (get(a)?, get(b)?);
+                bdds.and(a, b)?
+            }
+            Gate::Xor(a, b) => {
+                let (a, b) = (get(a)?, get(b)?);
+                bdds.xor(a, b)?
+            }
+            Gate::Ite(c, t, f) => {
+                let (c, t, f) = (get(c)?, get(t)?, get(f)?);
+                bdds.ite(c, t, f)?
+            }
+        };
+        vars.insert(lit.var(), f);
+        todo.pop();
+    }
+    lit_bdd(bdds, blaster, vars, lit)
+}
+
+fn lit_bdd(
+    bdds: &mut Manager,
+    blaster: &BitBlaster,
+    vars: &HashMap<u32, Bdd>,
+    lit: Lit,
+) -> bdd::Result<Bdd> {
+    if let Some(value) = blaster.const_value(lit) {
+        return Ok(if value { TRUE } else { FALSE });
+    }
+    let f = vars[&lit.var()];
+    if lit.is_negated() {
+        bdds.not(f)
+    } else {
+        Ok(f)
+    }
+}
+
+/// Computes the reachable states with BDDs until a bad state is found or a fixed point is reached.
+pub fn bdd_reachability(ctx: &Context, sys: &TransitionSystem) -> ModelCheckResult {
+    if sys.bad_states().is_empty() {
+        return ModelCheckResult::Unknown;
+    }
+    let result = Reachability::new(ctx, sys).and_then(|mut r| r.run(ctx, sys));
+    result.unwrap_or_else(|e| {
+        println!("BDD: {e}");
+        ModelCheckResult::Unknown
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmc::bmc;
+    use crate::certificate::check_certificate;
+    use patronus::mc::Simulator;
+    use patronus::sim::interpreter::{InitKind, Interpreter};
+    use rand::{Rng, SeedableRng};
+
+    /// Random expression over the symbols, all of width 3.
+    fn random_expr(
+        ctx: &mut Context,
+        rng: &mut impl Rng,
+        symbols: &[ExprRef],
+        depth: u32,
+    ) -> ExprRef {
+        if depth == 0 || rng.gen_bool(0.3) {
+            return if rng.gen_bool(0.8) {
+                symbols[rng.gen_range(0..symbols.len())]
+            } else {
+                ctx.bv_lit(rng.gen_range(0..8), 3)
+            };
+        }
+        let a = random_expr(ctx, rng, symbols, depth - 1);
+        let b = random_expr(ctx, rng, symbols, depth - 1);
+        match rng.gen_range(0..6) {
+            0 => ctx.add(a, b),
+            1 => ctx.xor(a, b),
+            2 => ctx.and(a, b),
+            3 => ctx.mul(a, b),
+            4 => ctx.shift_right(a, b),
+            _ => {
+                let cond = ctx.greater(a, b);
+                let c = random_expr(ctx, rng, symbols, depth - 1);
+                ctx.bv_ite(cond, c, a)
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_with_bmc() {
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        let (mut safe, mut unsafe_) = (0, 0);
+        for _ in 0..60 {
+            let mut ctx = Context::default();
+            let mut sys = TransitionSystem::new("test".to_string());
+            let input = ctx.bv_symbol("i", 3);
+            sys.add_input(&ctx, input);
+            let a = ctx.bv_symbol("a", 3);
+            let b = ctx.bv_symbol("b", 3);
+            let symbols = [input, a, b];
+            for symbol in [a, b] {
+                let state = sys.add_state(&ctx, symbol);
+                let next = random_expr(&mut ctx, &mut rng, &symbols, 3);
+                // some states start out with an arbitrary value
+                let init = rng
+                    .gen_bool(0.8)
+                    .then(|| ctx.bv_lit(rng.gen_range(0..8), 3));
+                sys.modify_state(state, |s| {
+                    s.next = Some(next);
+                    s.init = init;
+                });
+            }
+            let target = ctx.bv_lit(rng.gen_range(0..8), 3);
+            let at_target = ctx.bv_equal(a, target);
+            let other = ctx.bv_lit(rng.gen_range(0..8), 3);
+            let b_other = ctx.bv_equal(b, other);
+            let bad = ctx.and(at_target, b_other);
+            sys.add_signal(bad, SignalKind::Node, SignalLabels::bad(), None);
+
+            // with 64 states, every reachable state is reached in fewer than 64 steps
+            let expected = match bmc(&ctx, &sys, 64, false) {
+                ModelCheckResult::Sat(wit) => Some(wit.k),
+                _ => None,
+            };
+            match bdd_reachability(&ctx, &sys) {
+                ModelCheckResult::Sat(wit) => {
+                    assert_eq!(Some(wit.k), expected);
+                    // replay the witness
+                    let mut sim = Interpreter::new(&ctx, &sys);
+                    sim.init(InitKind::Zero);
+                    sim.set(a, ValueRef::new(&wit.state_init[0..1], 3));
+                    sim.set(b, ValueRef::new(&wit.state_init[1..2], 3));
+                    for k in 0..=wit.k as usize {
+                        sim.set(input, ValueRef::new(&wit.input_data[k..k + 1], 3));
+                        sim.update();
+                        let is_bad = sim.get(bad).unwrap().to_u64().unwrap() == 1;
+                        assert_eq!(is_bad, k == wit.k as usize);
+                        sim.step();
+                    }
+                    unsafe_ += 1;
+                }
+                ModelCheckResult::UnSat(cert) => {
+                    assert_eq!(expected, None);
+                    check_certificate(&ctx, &sys, &cert.unwrap()).unwrap();
+                    safe += 1;
+                }
+                ModelCheckResult::Unknown => panic!("expected a definite result"),
+            }
+        }
+        assert!(safe > 5 && unsafe_ > 5, "{safe} {unsafe_}");
+    }
+}
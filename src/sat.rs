@@ -0,0 +1,802 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// A small CDCL SAT solver in the style of MiniSat: two watched literals, first-UIP
+// clause learning with minimization, VSIDS branching with phase saving, Luby restarts
+// and learnt clause deletion. Supports incremental solving under assumptions.
+
+use std::ops::Not;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn new(var: u32, negated: bool) -> Self {
+        Lit((var << 1) | negated as u32)
+    }
+
+    pub fn var(self) -> u32 {
+        self.0 >> 1
+    }
+
+    pub fn is_negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+const FALSE: u8 = 0;
+const TRUE: u8 = 1;
+const UNDEF: u8 = 2;
+const NO_REASON: u32 = u32::MAX;
+/// Number of conflicts in the first restart interval, scaled by the Luby sequence.
+const RESTART_BASE: u64 = 100;
+const VAR_DECAY: f64 = 0.95;
+const CLAUSE_DECAY: f64 = 0.999;
+
+fn lit_value(assigns: &[u8], lit: Lit) -> u8 {
+    match assigns[lit.var() as usize] {
+        UNDEF => UNDEF,
+        value => value ^ lit.is_negated() as u8,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Clause {
+    /// the first two literals are watched, for reasons the first one is the implied literal
+    lits: Vec<Lit>,
+    learnt: bool,
+    activity: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Watcher {
+    clause: u32,
+    /// some other literal of the clause, if it is true we do not need to look at the clause
+    blocker: Lit,
+}
+
+/// Binary max-heap of variables ordered by their activity.
+#[derive(Debug, Clone, Default)]
+struct VarHeap {
+    heap: Vec<u32>,
+    /// position of every variable in the heap, `usize::MAX` if it is not in the heap
+    positions: Vec<usize>,
+}
+
+impl VarHeap {
+    fn contains(&self, var: u32) -> bool {
+        self.positions
+            .get(var as usize)
+            .is_some_and(|p| *p != usize::MAX)
+    }
+
+    fn insert(&mut self, var: u32, activity: &[f64]) {
+        if self.positions.len() <= var as usize {
+            self.positions.resize(var as usize + 1, usize::MAX);
+        }
+        if self.contains(var) {
+            return;
+        }
+        self.positions[var as usize] = self.heap.len();
+        self.heap.push(var);
+        self.up(self.heap.len() - 1, activity);
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<u32> {
+        let top = *self.heap.first()?;
+        let last = self.heap.pop().unwrap();
+        self.positions[top as usize] = usize::MAX;
+        if !self.heap.is_empty() {
+            self.heap[0] = last;
+            self.positions[last as usize] = 0;
+            self.down(0, activity);
+        }
+        Some(top)
+    }
+
+    /// Needs to be called after the activity of `var` increased.
+    fn increased(&mut self, var: u32, activity: &[f64]) {
+        if self.contains(var) {
+            self.up(self.positions[var as usize], activity);
+        }
+    }
+
+    fn up(&mut self, mut pos: usize, activity: &[f64]) {
+        let var = self.heap[pos];
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if activity[self.heap[parent] as usize] >= activity[var as usize] {
+                break;
+            }
+            self.heap[pos] = self.heap[parent];
+            self.positions[self.heap[pos] as usize] = pos;
+            pos = parent;
+        }
+        self.heap[pos] = var;
+        self.positions[var as usize] = pos;
+    }
+
+    fn down(&mut self, mut pos: usize, activity: &[f64]) {
+        let var = self.heap[pos];
+        loop {
+            let left = 2 * pos + 1;
+            if left >= self.heap.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < self.heap.len()
+                && activity[self.heap[right] as usize] > activity[self.heap[left] as usize]
+            {
+                right
+            } else {
+                left
+            };
+            if activity[self.heap[child] as usize] <= activity[var as usize] {
+                break;
+            }
+            self.heap[pos] = self.heap[child];
+            self.positions[self.heap[pos] as usize] = pos;
+            pos = child;
+        }
+        self.heap[pos] = var;
+        self.positions[var as usize] = pos;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Solver {
+    clauses: Vec<Clause>,
+    /// indices of all learnt clauses
+    learnts: Vec<u32>,
+    /// for every literal, the clauses that need to be visited once it becomes true
+    watches: Vec<Vec<Watcher>>,
+    assigns: Vec<u8>,
+    levels: Vec<u32>,
+    reasons: Vec<u32>,
+    trail: Vec<Lit>,
+    /// start of every decision level in the trail
+    trail_lim: Vec<usize>,
+    /// next literal in the trail to propagate
+    qhead: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    clause_inc: f64,
+    order: VarHeap,
+    /// last value of every variable
+    phase: Vec<bool>,
+    seen: Vec<bool>,
+    /// false once the clauses are unsatisfiable without any assumptions
+    ok: bool,
+    model: Vec<bool>,
+    /// assumptions that were responsible for the last unsatisfiable result
+    failed: Vec<Lit>,
+    max_learnts: f64,
+    conflicts: u64,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self {
+            clauses: Vec::new(),
+            learnts: Vec::new(),
+            watches: Vec::new(),
+            assigns: Vec::new(),
+            levels: Vec::new(),
+            reasons: Vec::new(),
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            qhead: 0,
+            activity: Vec::new(),
+            var_inc: 1.0,
+            clause_inc: 1.0,
+            order: VarHeap::default(),
+            phase: Vec::new(),
+            seen: Vec::new(),
+            ok: true,
+            model: Vec::new(),
+            failed: Vec::new(),
+            max_learnts: 0.0,
+            conflicts: 0,
+        }
+    }
+
+    pub fn num_vars(&self) -> u32 {
+        self.assigns.len() as u32
+    }
+
+    /// Returns the positive literal of a new variable.
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.num_vars();
+        self.assigns.push(UNDEF);
+        self.levels.push(0);
+        self.reasons.push(NO_REASON);
+        self.activity.push(0.0);
+        self.phase.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.order.insert(var, &self.activity);
+        Lit::new(var, false)
+    }
+
+    /// Adds a clause. Returns false if the clauses became unsatisfiable.
+    pub fn add_clause(&mut self, lits: &[Lit]) -> bool {
+        if !self.ok {
+            return false;
+        }
+        debug_assert_eq!(self.decision_level(), 0);
+        let mut lits = lits.to_vec();
+        lits.sort_unstable();
+        lits.dedup();
+        // skip tautologies and satisfied clauses, remove false literals
+        if lits.windows(2).any(|w| w[0] == !w[1]) {
+            return true;
+        }
+        if lits.iter().any(|l| lit_value(&self.assigns, *l) == TRUE) {
+            return true;
+        }
+        lits.retain(|l| lit_value(&self.assigns, *l) == UNDEF);
+        match lits.len() {
+            0 => {
+                self.ok = false;
+                false
+            }
+            1 => {
+                self.enqueue(lits[0], NO_REASON);
+                self.ok = self.propagate().is_none();
+                self.ok
+            }
+            _ => {
+                self.attach(lits, false);
+                true
+            }
+        }
+    }
+
+    /// Value of a literal in the last satisfying assignment.
+    pub fn value(&self, lit: Lit) -> bool {
+        self.model.get(lit.var() as usize).copied().unwrap_or(false) ^ lit.is_negated()
+    }
+
+    /// Subset of the assumptions that made the last call to `solve` unsatisfiable.
+    pub fn failed_assumptions(&self) -> &[Lit] {
+        &self.failed
+    }
+
+    /// Returns true iff the clauses are satisfiable under the assumptions.
+    pub fn solve(&mut self, assumptions: &[Lit]) -> bool {
+        self.failed.clear();
+        if !self.ok {
+            return false;
+        }
+        self.max_learnts = f64::max(self.clauses.len() as f64 / 3.0, 1000.0);
+        let mut restarts = 0;
+        let result = loop {
+            let budget = luby(restarts) * RESTART_BASE;
+            if let Some(result) = self.search(budget, assumptions) {
+                break result;
+            }
+            restarts += 1;
+            self.max_learnts *= 1.05;
+        };
+        self.cancel_until(0);
+        result
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn attach(&mut self, lits: Vec<Lit>, learnt: bool) -> u32 {
+        let index = self.clauses.len() as u32;
+        self.watches[(!lits[0]).index()].push(Watcher {
+            clause: index,
+            blocker: lits[1],
+        });
+        self.watches[(!lits[1]).index()].push(Watcher {
+            clause: index,
+            blocker: lits[0],
+        });
+        self.clauses.push(Clause {
+            lits,
+            learnt,
+            activity: 0.0,
+        });
+        if learnt {
+            self.learnts.push(index);
+        }
+        index
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: u32) {
+        let var = lit.var() as usize;
+        debug_assert_eq!(self.assigns[var], UNDEF);
+        self.assigns[var] = !lit.is_negated() as u8;
+        self.levels[var] = self.decision_level() as u32;
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Returns a conflicting clause, if any.
+    fn propagate(&mut self) -> Option<u32> {
+        while self.qhead < self.trail.len() {
+            let p = self.trail[self.qhead];
+            self.qhead += 1;
+            let false_lit = !p;
+            let mut watchers = std::mem::take(&mut self.watches[p.index()]);
+            let (mut i, mut j) = (0, 0);
+            let mut conflict = None;
+            while i < watchers.len() {
+                let w = watchers[i];
+                i += 1;
+                if lit_value(&self.assigns, w.blocker) == TRUE {
+                    watchers[j] = w;
+                    j += 1;
+                    continue;
+                }
+                let clause = &mut self.clauses[w.clause as usize];
+                if clause.lits[0] == false_lit {
+                    clause.lits.swap(0, 1);
+                }
+                let first = clause.lits[0];
+                let kept = Watcher {
+                    clause: w.clause,
+                    blocker: first,
+                };
+                if first != w.blocker && lit_value(&self.assigns, first) == TRUE {
+                    watchers[j] = kept;
+                    j += 1;
+                    continue;
+                }
+                // look for a new literal to watch
+                let replacement = (2..clause.lits.len())
+                    .find(|k| lit_value(&self.assigns, clause.lits[*k]) != FALSE);
+                if let Some(k) = replacement {
+                    clause.lits.swap(1, k);
+                    self.watches[(!clause.lits[1]).index()].push(kept);
+                    continue;
+                }
+                watchers[j] = kept;
+                j += 1;
+                if lit_value(&self.assigns, first) == FALSE {
+                    conflict = Some(w.clause);
+                    break;
+                }
+                self.enqueue(first, w.clause);
+            }
+            // keep the watchers that we did not get to because of a conflict
+            while i < watchers.len() {
+                watchers[j] = watchers[i];
+                i += 1;
+                j += 1;
+            }
+            watchers.truncate(j);
+            self.watches[p.index()] = watchers;
+            if conflict.is_some() {
+                self.qhead = self.trail.len();
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// First-UIP conflict analysis. Returns the learnt clause and the level to backtrack to.
+    fn analyze(&mut self, conflict: u32) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut implied: Option<Lit> = None;
+        let mut index = self.trail.len();
+        let mut clause = conflict;
+        loop {
+            self.bump_clause(clause);
+            let start = implied.is_some() as usize;
+            for k in start..self.clauses[clause as usize].lits.len() {
+                let q = self.clauses[clause as usize].lits[k];
+                let var = q.var() as usize;
+                if !self.seen[var] && self.levels[var] > 0 {
+                    self.bump_var(q.var());
+                    self.seen[var] = true;
+                    if self.levels[var] as usize >= self.decision_level() {
+                        pending += 1;
+                    } else {
+                        learnt.push(q);
+                    }
+                }
+            }
+            // continue with the most recently assigned literal of the current level
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].var() as usize] {
+                    break;
+                }
+            }
+            let p = self.trail[index];
+            implied = Some(p);
+            clause = self.reasons[p.var() as usize];
+            self.seen[p.var() as usize] = false;
+            pending -= 1;
+            if pending == 0 {
+                break;
+            }
+        }
+        learnt[0] = !implied.unwrap();
+
+        // drop literals that are implied by the other literals
+        let to_clear = learnt.clone();
+        let mut minimized = vec![learnt[0]];
+        for lit in learnt.iter().skip(1) {
+            let reason = self.reasons[lit.var() as usize];
+            let redundant = reason != NO_REASON
+                && self.clauses[reason as usize].lits[1..]
+                    .iter()
+                    .all(|q| self.seen[q.var() as usize] || self.levels[q.var() as usize] == 0);
+            if !redundant {
+                minimized.push(*lit);
+            }
+        }
+        for lit in to_clear {
+            self.seen[lit.var() as usize] = false;
+        }
+
+        // the literal with the highest level is watched together with the asserting one
+        let mut backtrack = 0;
+        if minimized.len() > 1 {
+            let max = (1..minimized.len())
+                .max_by_key(|k| self.levels[minimized[*k].var() as usize])
+                .unwrap();
+            minimized.swap(1, max);
+            backtrack = self.levels[minimized[1].var() as usize] as usize;
+        }
+        (minimized, backtrack)
+    }
+
+    /// Collects the assumptions that imply `!p`, where `p` is a falsified assumption.
+    fn analyze_final(&mut self, p: Lit) {
+        self.failed.clear();
+        self.failed.push(p);
+        if self.decision_level() == 0 {
+            return;
+        }
+        self.seen[p.var() as usize] = true;
+        for index in (self.trail_lim[0]..self.trail.len()).rev() {
+            let lit = self.trail[index];
+            let var = lit.var() as usize;
+            if !self.seen[var] {
+                continue;
+            }
+            let reason = self.reasons[var];
+            if reason == NO_REASON {
+                // all decisions at this point are assumptions
+                self.failed.push(lit);
+            } else {
+                for k in 1..self.clauses[reason as usize].lits.len() {
+                    let q = self.clauses[reason as usize].lits[k];
+                    if self.levels[q.var() as usize] > 0 {
+                        self.seen[q.var() as usize] = true;
+                    }
+                }
+            }
+            self.seen[var] = false;
+        }
+        self.seen[p.var() as usize] = false;
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+        let start = self.trail_lim[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var() as usize;
+            self.phase[var] = !lit.is_negated();
+            self.assigns[var] = UNDEF;
+            self.reasons[var] = NO_REASON;
+            self.order.insert(lit.var(), &self.activity);
+        }
+        self.qhead = start;
+        self.trail_lim.truncate(level);
+    }
+
+    fn bump_var(&mut self, var: u32) {
+        self.activity[var as usize] += self.var_inc;
+        if self.activity[var as usize] > 1e100 {
+            for a in self.activity.iter_mut() {
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        self.order.increased(var, &self.activity);
+    }
+
+    fn bump_clause(&mut self, clause: u32) {
+        let c = &mut self.clauses[clause as usize];
+        if !c.learnt {
+            return;
+        }
+        c.activity += self.clause_inc;
+        if c.activity > 1e20 {
+            for l in self.learnts.iter() {
+                self.clauses[*l as usize].activity *= 1e-20;
+            }
+            self.clause_inc *= 1e-20;
+        }
+    }
+
+    fn pick_branch_lit(&mut self) -> Option<Lit> {
+        while let Some(var) = self.order.pop(&self.activity) {
+            if self.assigns[var as usize] == UNDEF {
+                return Some(Lit::new(var, !self.phase[var as usize]));
+            }
+        }
+        None
+    }
+
+    /// Removes the less active half of the learnt clauses.
+    fn reduce_db(&mut self) {
+        let mut learnts = std::mem::take(&mut self.learnts);
+        learnts.sort_by(|a, b| {
+            let (a, b) = (&self.clauses[*a as usize], &self.clauses[*b as usize]);
+            a.activity.total_cmp(&b.activity)
+        });
+        let half = learnts.len() / 2;
+        let mut removed = vec![false; self.clauses.len()];
+        for (ii, index) in learnts.iter().enumerate() {
+            let clause = &self.clauses[*index as usize];
+            let first = clause.lits[0].var() as usize;
+            let locked =
+                self.reasons[first] == *index && lit_value(&self.assigns, clause.lits[0]) == TRUE;
+            if ii < half && clause.lits.len() > 2 && !locked {
+                removed[*index as usize] = true;
+            }
+        }
+        // compact the clause database and fix all references
+        let mut renamed = vec![NO_REASON; self.clauses.len()];
+        let mut kept = Vec::with_capacity(self.clauses.len());
+        for (index, clause) in std::mem::take(&mut self.clauses).into_iter().enumerate() {
+            if !removed[index] {
+                renamed[index] = kept.len() as u32;
+                kept.push(clause);
+            }
+        }
+        self.clauses = kept;
+        for reason in self.reasons.iter_mut() {
+            if *reason != NO_REASON {
+                *reason = renamed[*reason as usize];
+            }
+        }
+        self.learnts = learnts
+            .into_iter()
+            .filter(|l| !removed[*l as usize])
+            .map(|l| renamed[l as usize])
+            .collect();
+        for watchers in self.watches.iter_mut() {
+            watchers.clear();
+        }
+        for (index, clause) in self.clauses.iter().enumerate() {
+            for k in 0..2 {
+                self.watches[(!clause.lits[k]).index()].push(Watcher {
+                    clause: index as u32,
+                    blocker: clause.lits[1 - k],
+                });
+            }
+        }
+    }
+
+    /// Returns `None` once the conflict budget is exhausted.
+    fn search(&mut self, budget: u64, assumptions: &[Lit]) -> Option<bool> {
+        let mut conflicts = 0;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                conflicts += 1;
+                self.conflicts += 1;
+                if self.decision_level() == 0 {
+                    self.ok = false;
+                    return Some(false);
+                }
+                let (learnt, backtrack) = self.analyze(conflict);
+                self.cancel_until(backtrack);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], NO_REASON);
+                } else {
+                    let asserting = learnt[0];
+                    let index = self.attach(learnt, true);
+                    self.bump_clause(index);
+                    self.enqueue(asserting, index);
+                }
+                self.var_inc /= VAR_DECAY;
+                self.clause_inc /= CLAUSE_DECAY;
+            } else {
+                if conflicts >= budget {
+                    self.cancel_until(0);
+                    return None;
+                }
+                if self.learnts.len() as f64 - self.trail.len() as f64 >= self.max_learnts {
+                    self.reduce_db();
+                }
+                let mut next = None;
+                while self.decision_level() < assumptions.len() {
+                    let p = assumptions[self.decision_level()];
+                    match lit_value(&self.assigns, p) {
+                        // already true, we still open a level to keep levels and assumptions aligned
+                        TRUE => self.trail_lim.push(self.trail.len()),
+                        FALSE => {
+                            self.analyze_final(p);
+                            return Some(false);
+                        }
+                        _ => {
+                            next = Some(p);
+                            break;
+                        }
+                    }
+                }
+                let next = match next.or_else(|| self.pick_branch_lit()) {
+                    Some(next) => next,
+                    None => {
+                        self.model = self.assigns.iter().map(|a| *a == TRUE).collect();
+                        return Some(true);
+                    }
+                };
+                self.trail_lim.push(self.trail.len());
+                self.enqueue(next, NO_REASON);
+            }
+        }
+    }
+}
+
+/// The Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, ...
+fn luby(mut index: u64) -> u64 {
+    let (mut size, mut seq) = (1, 0);
+    while size < index + 1 {
+        seq += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != index {
+        size = (size - 1) >> 1;
+        seq -= 1;
+        index %= size;
+    }
+    1 << seq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_luby() {
+        let seq = (0..10).map(luby).collect::<Vec<_>>();
+        assert_eq!(seq, [1, 1, 2, 1, 1, 2, 4, 1, 1, 2]);
+    }
+
+    /// Encodes that every pigeon sits in one of the holes, but no two pigeons share a hole.
+    fn pigeon_hole(solver: &mut Solver, pigeons: usize, holes: usize) {
+        let x = (0..pigeons)
+            .map(|_| (0..holes).map(|_| solver.new_var()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        for p in x.iter() {
+            solver.add_clause(p);
+        }
+        for (a, holes_a) in x.iter().enumerate() {
+            for holes_b in x[a + 1..].iter() {
+                for (ha, hb) in holes_a.iter().zip(holes_b.iter()) {
+                    solver.add_clause(&[!*ha, !*hb]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pigeon_hole() {
+        // four pigeons do not fit into three holes
+        let mut solver = Solver::new();
+        pigeon_hole(&mut solver, 4, 3);
+        assert!(!solver.solve(&[]));
+    }
+
+    #[test]
+    fn test_large_pigeon_hole() {
+        // needs enough conflicts to restart and to shrink the learnt clauses
+        let mut solver = Solver::new();
+        pigeon_hole(&mut solver, 8, 7);
+        assert!(!solver.solve(&[]));
+        assert!(solver.conflicts > 2 * RESTART_BASE);
+        // every conflict learns a clause, many of which were removed again
+        assert!(3 * solver.learnts.len() < 2 * solver.conflicts as usize);
+    }
+
+    #[test]
+    fn test_planted_3sat() {
+        // random clauses close to the satisfiability threshold, all of which the planted
+        // assignment fulfills, thus the instance is known to be satisfiable
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        let vars = 400;
+        let planted = (0..vars).map(|_| rng.gen_bool(0.5)).collect::<Vec<_>>();
+        let mut solver = Solver::new();
+        for _ in 0..vars {
+            solver.new_var();
+        }
+        let mut clauses = Vec::new();
+        while clauses.len() < vars as usize * 42 / 10 {
+            let clause = (0..3)
+                .map(|_| Lit::new(rng.gen_range(0..vars), rng.gen_bool(0.5)))
+                .collect::<Vec<_>>();
+            if clause
+                .iter()
+                .any(|l| planted[l.var() as usize] != l.is_negated())
+            {
+                solver.add_clause(&clause);
+                clauses.push(clause);
+            }
+        }
+        assert!(solver.solve(&[]));
+        for c in clauses.iter() {
+            assert!(c.iter().any(|l| solver.value(*l)));
+        }
+        assert!(3 * solver.learnts.len() < 2 * solver.conflicts as usize);
+    }
+
+    #[test]
+    fn test_random_3sat() {
+        // compare against brute force on small random instances
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+        for _ in 0..200 {
+            let vars = 8;
+            let mut solver = Solver::new();
+            let lits = (0..vars).map(|_| solver.new_var()).collect::<Vec<_>>();
+            let clauses = (0..rng.gen_range(20..40))
+                .map(|_| {
+                    (0..3)
+                        .map(|_| Lit::new(rng.gen_range(0..vars), rng.gen_bool(0.5)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            for c in clauses.iter() {
+                solver.add_clause(c);
+            }
+            let assumptions = [!lits[0], lits[1], !lits[2]];
+            let satisfies = |assignment: u32, c: &[Lit]| {
+                c.iter()
+                    .any(|l| ((assignment >> l.var()) & 1 == 1) != l.is_negated())
+            };
+            let is_sat = |assumed: &[Lit]| {
+                (0..(1u32 << vars)).any(|a| {
+                    clauses.iter().all(|c| satisfies(a, c))
+                        && assumed.iter().all(|l| satisfies(a, &[*l]))
+                })
+            };
+            let expected = is_sat(&assumptions);
+            assert_eq!(solver.solve(&assumptions), expected);
+            if expected {
+                for l in assumptions.iter() {
+                    assert!(solver.value(*l));
+                }
+                for c in clauses.iter() {
+                    assert!(c.iter().any(|l| solver.value(*l)));
+                }
+            } else {
+                let failed = solver.failed_assumptions().to_vec();
+                assert!(failed.iter().all(|l| assumptions.contains(l)));
+                assert!(!is_sat(&failed));
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Adaptive scheduling of trace lengths and input dwell probabilities: multi-armed bandits
+// that are rewarded by how often a trace makes progress.
+
+use crate::distance::BadDistance;
+use crate::fuzz::{Coverage, CoverageMap};
+use crate::random::RandomOptions;
+use crate::StepInt;
+use patronus::ir::*;
+use patronus::sim::interpreter::Interpreter;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Weight of the latest reward once an arm has been tried often, lets old rewards
+/// fade since coverage saturates over time.
+const MIN_LEARNING_RATE: f64 = 0.05;
+/// Probabilities of keeping the previous input value that the dwell learner picks from.
+const DWELL_ARMS: [f64; 4] = [0.0, 0.5, 0.9, 0.99];
+/// Probability of the dwell learner trying a random arm, which makes sure that inputs
+/// do not all pick the same arm, since they share their rewards.
+const DWELL_EXPLORE_PROB: f64 = 0.2;
+
+/// Multi-armed bandit using the UCB1 strategy.
+#[derive(Debug, Clone)]
+pub struct Bandit {
+    pulls: Vec<u64>,
+    /// running average of the rewards received
+    values: Vec<f64>,
+    total_pulls: u64,
+}
+
+impl Bandit {
+    pub fn new(arms: usize) -> Self {
+        assert!(arms > 0, "a bandit needs at least one arm");
+        Self {
+            pulls: vec![0; arms],
+            values: vec![0.0; arms],
+            total_pulls: 0,
+        }
+    }
+
+    pub fn sample(&self) -> usize {
+        // try every arm once before trusting the estimates
+        if let Some(untried) = self.pulls.iter().position(|p| *p == 0) {
+            return untried;
+        }
+        let log_total = (self.total_pulls as f64).ln();
+        let score = |a: usize| self.values[a] + (2.0 * log_total / self.pulls[a] as f64).sqrt();
+        (0..self.pulls.len())
+            .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+            .unwrap()
+    }
+
+    /// Rewards an arm with a value between zero and one.
+    pub fn reward(&mut self, arm: usize, reward: f64) {
+        self.pulls[arm] += 1;
+        self.total_pulls += 1;
+        let rate = f64::max(1.0 / self.pulls[arm] as f64, MIN_LEARNING_RATE);
+        self.values[arm] += rate * (reward - self.values[arm]);
+    }
+}
+
+/// Picks trace lengths out of exponentially growing buckets `[1, 2], [3, 4], [5, 8], ...`
+#[derive(Debug, Clone)]
+pub struct LengthScheduler {
+    /// inclusive bounds of every bucket
+    buckets: Vec<(StepInt, StepInt)>,
+    bandit: Bandit,
+}
+
+impl LengthScheduler {
+    pub fn new(opts: &RandomOptions) -> Result<Self, String> {
+        if opts.large_k == 0 {
+            return Err("the maximum trace length needs to be at least one".to_string());
+        }
+        let mut buckets = Vec::new();
+        let mut lo = 1;
+        while lo <= opts.large_k {
+            let hi = std::cmp::min(std::cmp::max(2, (lo - 1) * 2), opts.large_k);
+            buckets.push((lo, hi));
+            lo = hi + 1;
+        }
+        Ok(Self {
+            bandit: Bandit::new(buckets.len()),
+            buckets,
+        })
+    }
+
+    /// Returns the bucket that was picked and a trace length from it.
+    pub fn sample(&self, rng: &mut impl Rng) -> (usize, StepInt) {
+        let bucket = self.bandit.sample();
+        let (lo, hi) = self.buckets[bucket];
+        (bucket, rng.gen_range(lo..=hi))
+    }
+
+    pub fn reward(&mut self, bucket: usize, reward: f64) {
+        self.bandit.reward(bucket, reward);
+    }
+}
+
+/// Learns for every input how likely it should keep its value from the previous cycle.
+#[derive(Debug, Clone)]
+pub struct DwellLearner {
+    inputs: Vec<(ExprRef, Bandit)>,
+    /// arms picked for the current trace
+    picked: Vec<usize>,
+}
+
+impl DwellLearner {
+    pub fn new(inputs: impl Iterator<Item = ExprRef>) -> Self {
+        let inputs = inputs
+            .map(|i| (i, Bandit::new(DWELL_ARMS.len())))
+            .collect::<Vec<_>>();
+        Self {
+            picked: vec![0; inputs.len()],
+            inputs,
+        }
+    }
+
+    /// Picks the probabilities for the next trace.
+    pub fn sample(&mut self, rng: &mut impl Rng) -> HashMap<ExprRef, f64> {
+        let mut out = HashMap::with_capacity(self.inputs.len());
+        for ((input, bandit), picked) in self.inputs.iter().zip(self.picked.iter_mut()) {
+            *picked = if rng.gen_bool(DWELL_EXPLORE_PROB) {
+                rng.gen_range(0..DWELL_ARMS.len())
+            } else {
+                bandit.sample()
+            };
+            out.insert(*input, DWELL_ARMS[*picked]);
+        }
+        out
+    }
+
+    /// Rewards all picks of the last trace.
+    pub fn reward(&mut self, reward: f64) {
+        for ((_, bandit), picked) in self.inputs.iter_mut().zip(self.picked.iter()) {
+            bandit.reward(*picked, reward);
+        }
+    }
+}
+
+/// Detects whether a trace made progress: reached a new coverage point or got closer
+/// to a bad state than any trace before.
+pub struct TraceFeedback {
+    coverage: Coverage,
+    map: CoverageMap,
+    distance: BadDistance,
+    best_fitness: f64,
+    prev_states: Vec<Word>,
+    /// number of cycles of the current trace that made progress
+    progress_cycles: u64,
+}
+
+/// Maps the number of cycles with progress to a reward between zero and one. Traces are
+/// not normalized by their length, since deep designs need long traces to make any progress.
+pub fn trace_reward(progress_cycles: u64) -> f64 {
+    progress_cycles as f64 / (progress_cycles + 1) as f64
+}
+
+impl TraceFeedback {
+    pub fn new(ctx: &mut Context, sys: &TransitionSystem) -> Self {
+        let coverage = Coverage::new(ctx, sys);
+        let map = CoverageMap::new(coverage.len());
+        Self {
+            coverage,
+            map,
+            distance: BadDistance::new(ctx, sys),
+            best_fitness: 0.0,
+            prev_states: Vec::new(),
+            progress_cycles: 0,
+        }
+    }
+
+    pub fn instrument(&self, ctx: &Context, sys: &mut TransitionSystem) {
+        self.coverage.instrument(ctx, sys);
+        self.distance.instrument(ctx, sys);
+    }
+
+    /// Needs to be called at the start of every trace.
+    pub fn start(&mut self, sim: &Interpreter) {
+        self.prev_states = self.coverage.state_values(sim);
+        self.progress_cycles = 0;
+    }
+
+    /// Returns true iff the current cycle made progress.
+    pub fn record(&mut self, ctx: &Context, sim: &Interpreter) -> bool {
+        let mut progress = false;
+        let map = &self.map;
+        self.coverage
+            .record(sim, &mut self.prev_states, |p| progress |= map.insert(p));
+        let fitness = self.distance.fitness(ctx, sim);
+        if fitness > self.best_fitness {
+            self.best_fitness = fitness;
+            progress = true;
+        }
+        self.progress_cycles += progress as u64;
+        progress
+    }
+
+    /// Reward for the cycles of the current trace that were executed so far.
+    pub fn reward(&self) -> f64 {
+        trace_reward(self.progress_cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_buckets() {
+        let opts = crate::RANDOM_OPTS.clone();
+        let scheduler = LengthScheduler::new(&opts).unwrap();
+        assert_eq!(scheduler.buckets[..4], [(1, 2), (3, 4), (5, 8), (9, 16)]);
+        assert_eq!(scheduler.buckets.last().unwrap().1, opts.large_k);
+        let opts = RandomOptions { large_k: 1, ..opts };
+        assert_eq!(LengthScheduler::new(&opts).unwrap().buckets, [(1, 1)]);
+        let opts = RandomOptions { large_k: 0, ..opts };
+        assert!(LengthScheduler::new(&opts).is_err());
+    }
+
+    #[test]
+    fn test_prefers_rewarded_bucket() {
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        let mut scheduler = LengthScheduler::new(&crate::RANDOM_OPTS).unwrap();
+        // only long traces make progress, in a single cycle close to their end
+        let mut long = 0;
+        for _ in 0..1000 {
+            let (bucket, k) = scheduler.sample(&mut rng);
+            let is_long = k > 5000;
+            long += is_long as u32;
+            scheduler.reward(bucket, trace_reward(is_long as u64));
+        }
+        assert!(long > 500, "{long}");
+    }
+}
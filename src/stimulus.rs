@@ -0,0 +1,236 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// User provided value distributions for inputs.
+//
+// Every input is specified as `NAME=DIST [hold N]` where `DIST` is one of:
+//   uniform | const(V) | range(LO, HI) | set(V[:WEIGHT], ...) | bits(P)
+// `hold N` keeps every sampled value for `N` cycles.
+// A stimulus file contains one specification per line, `#` starts a comment. We use this
+// line based format instead of TOML, since it is the same as on the command line.
+
+use patronus::ir::value::mask;
+use patronus::ir::*;
+use rand::Rng;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Const(u64),
+    /// inclusive range
+    Range(u64, u64),
+    /// values with their weights
+    Weighted(Vec<(u64, u64)>),
+    /// every bit is one with the given probability
+    Bits(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSpec {
+    pub dist: Distribution,
+    /// number of cycles for which a sampled value is held
+    pub hold: u64,
+}
+
+impl InputSpec {
+    pub fn sample(&self, rng: &mut impl Rng, width: WidthInt) -> u64 {
+        let value = match &self.dist {
+            Distribution::Uniform => rng.next_u64(),
+            Distribution::Const(value) => *value,
+            Distribution::Range(lo, hi) => rng.gen_range(*lo..=*hi),
+            Distribution::Weighted(values) => sample_weighted(rng, values),
+            Distribution::Bits(prob) => (0..width)
+                .filter(|_| rng.gen_bool(*prob))
+                .fold(0, |value, bit| value | (1 << bit)),
+        };
+        value & mask(width)
+    }
+
+    /// Largest value that this distribution can produce (ignoring masking).
+    fn max_value(&self) -> u64 {
+        match &self.dist {
+            Distribution::Uniform | Distribution::Bits(_) => 0,
+            Distribution::Const(value) => *value,
+            Distribution::Range(_, hi) => *hi,
+            Distribution::Weighted(values) => values.iter().map(|(v, _)| *v).max().unwrap(),
+        }
+    }
+
+    /// Returns an error if the distribution does not fit the input width.
+    pub fn check_width(&self, width: WidthInt) -> Result<(), String> {
+        if width > 64 {
+            Err(format!(
+                "inputs wider than 64-bit ({width}) are not supported"
+            ))
+        } else if !bv_value_fits_width(self.max_value(), width) {
+            Err(format!(
+                "{} does not fit into {width} bits",
+                self.max_value()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Picks one of the values with a probability proportional to its weight.
+pub fn sample_weighted(rng: &mut impl Rng, values: &[(u64, u64)]) -> u64 {
+    // the sum of the weights may not fit into 64 bits
+    let total = total_weight(values);
+    let mut pick = rng.gen_range(0..total);
+    values
+        .iter()
+        .find(|(_, weight)| {
+            let weight = *weight as u128;
+            let found = pick < weight;
+            pick = pick.saturating_sub(weight);
+            found
+        })
+        .unwrap()
+        .0
+}
+
+fn total_weight(values: &[(u64, u64)]) -> u128 {
+    values.iter().map(|(_, w)| *w as u128).sum()
+}
+
+/// Parses a `NAME=DIST [hold N]` specification.
+pub fn parse_input_spec(src: &str) -> Result<(String, InputSpec), String> {
+    let err = |msg: &str| format!("{msg} in stimulus `{src}`");
+    let (name, spec) = src.split_once('=').ok_or_else(|| err("missing `=`"))?;
+    // `hold` needs to be the second to last word
+    let words = spec.split_whitespace().collect::<Vec<_>>();
+    let (dist, hold) = match words.as_slice() {
+        [dist @ .., "hold", hold] => {
+            let hold = hold
+                .parse::<u64>()
+                .map_err(|_| err("invalid hold cycles"))?;
+            if hold == 0 {
+                return Err(err("hold needs to be at least one cycle"));
+            }
+            (dist.join(" "), hold)
+        }
+        _ => (words.join(" "), 1),
+    };
+    let dist = if dist.is_empty() {
+        // `NAME=hold N` holds uniformly distributed values
+        Distribution::Uniform
+    } else {
+        parse_distribution(&dist).ok_or_else(|| err("invalid distribution"))?
+    };
+    Ok((name.trim().to_string(), InputSpec { dist, hold }))
+}
+
+fn parse_distribution(src: &str) -> Option<Distribution> {
+    if src == "uniform" {
+        return Some(Distribution::Uniform);
+    }
+    let (func, args) = src.strip_suffix(')')?.split_once('(')?;
+    let args = args.split(',').map(|a| a.trim()).collect::<Vec<_>>();
+    let dist = match (func.trim(), args.as_slice()) {
+        ("const", [value]) => Distribution::Const(parse_num(value)?),
+        ("range", [lo, hi]) => {
+            let (lo, hi) = (parse_num(lo)?, parse_num(hi)?);
+            if lo > hi {
+                return None;
+            }
+            Distribution::Range(lo, hi)
+        }
+        ("set", values) => {
+            let values = values
+                .iter()
+                .map(|v| match v.split_once(':') {
+                    Some((value, weight)) => Some((parse_num(value)?, parse_num(weight)?)),
+                    None => Some((parse_num(v)?, 1)),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            if total_weight(&values) == 0 {
+                return None;
+            }
+            Distribution::Weighted(values)
+        }
+        ("bits", [prob]) => {
+            let prob = prob.parse::<f64>().ok()?;
+            if !(0.0..=1.0).contains(&prob) {
+                return None;
+            }
+            Distribution::Bits(prob)
+        }
+        _ => return None,
+    };
+    Some(dist)
+}
+
+fn parse_num(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        s.parse::<u64>().ok()
+    }
+}
+
+/// Parses a stimulus file with one specification per line.
+pub fn parse_stimulus_file(content: &str) -> Result<Vec<(String, InputSpec)>, String> {
+    content
+        .lines()
+        .map(|l| l.split('#').next().unwrap().trim())
+        .filter(|l| !l.is_empty())
+        .map(parse_input_spec)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_input_spec() {
+        let (name, spec) = parse_input_spec("A.d = range(0, 15) hold 4").unwrap();
+        assert_eq!(name, "A.d");
+        assert_eq!(spec.dist, Distribution::Range(0, 15));
+        assert_eq!(spec.hold, 4);
+        let (_, spec) = parse_input_spec("en=set(0:9, 1)").unwrap();
+        assert_eq!(spec.dist, Distribution::Weighted(vec![(0, 9), (1, 1)]));
+        let (_, spec) = parse_input_spec("mode=hold 100").unwrap();
+        assert_eq!(spec.dist, Distribution::Uniform);
+        assert_eq!(spec.hold, 100);
+        assert!(parse_input_spec("a=bits(2.0)").is_err());
+        assert!(parse_input_spec("a=range(3, 1)").is_err());
+        assert!(parse_input_spec("a=const(1) hold 0").is_err());
+        // only a separate `hold` word introduces the hold cycles
+        let (name, spec) = parse_input_spec("threshold=set(1, 2)").unwrap();
+        assert_eq!(name, "threshold");
+        assert_eq!(spec.dist, Distribution::Weighted(vec![(1, 1), (2, 1)]));
+        assert_eq!(spec.hold, 1);
+        assert!(parse_input_spec("a=uniformhold 3").is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        let range = InputSpec {
+            dist: Distribution::Range(3, 5),
+            hold: 1,
+        };
+        let weighted = InputSpec {
+            dist: Distribution::Weighted(vec![(7, 0), (2, 1)]),
+            hold: 1,
+        };
+        for _ in 0..100 {
+            assert!((3..=5).contains(&range.sample(&mut rng, 8)));
+            assert_eq!(weighted.sample(&mut rng, 8), 2);
+        }
+        let bits = InputSpec {
+            dist: Distribution::Bits(1.0),
+            hold: 1,
+        };
+        assert_eq!(bits.sample(&mut rng, 5), 0b11111);
+        // the sum of the weights does not fit into 64 bits
+        let heavy = [(1, u64::MAX), (2, u64::MAX)];
+        assert!((1..=2).contains(&sample_weighted(&mut rng, &heavy)));
+    }
+}
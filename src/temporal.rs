@@ -0,0 +1,786 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// A small temporal property language over signal names. Properties are compiled into
+// monitor states that get added to the transition system.
+//
+// Grammar:
+//   property := [("first" | "after") NUM ":"] expr [("|->" | "|=>") [delay] expr]
+//   delay    := "##" NUM | "##[" NUM ":" NUM "]"
+//   expr     := SystemVerilog-like expression over signal names, including
+//               `$past(e[, n])`, `$rose(e)`, `$fell(e)` and `$stable(e)`
+
+use patronus::ir::*;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemporalError(String);
+
+impl Display for TemporalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+type Result<T> = std::result::Result<T, TemporalError>;
+
+fn error<T>(msg: impl Into<String>) -> Result<T> {
+    Err(TemporalError(msg.into()))
+}
+
+/// A property that needs to hold in every cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    window: Window,
+    /// trigger and delay range of an implication
+    antecedent: Option<(Ast, u32, u32)>,
+    consequent: Ast,
+}
+
+/// Restricts the cycles in which a property is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+    Always,
+    /// only during the first n cycles
+    First(u64),
+    /// only after the first n cycles
+    After(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ast {
+    Signal(String),
+    Literal(u64),
+    Unary(UnaryOp, Box<Ast>),
+    Binary(BinOp, Box<Ast>, Box<Ast>),
+    Slice(Box<Ast>, WidthInt, WidthInt),
+    Past(Box<Ast>, u32),
+    Rose(Box<Ast>),
+    Fell(Box<Ast>),
+    Stable(Box<Ast>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    LogicNot,
+    Not,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    LogicOr,
+    LogicAnd,
+    Or,
+    Xor,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Sub,
+}
+
+impl BinOp {
+    /// binding strength, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::LogicOr => 1,
+            BinOp::LogicAnd => 2,
+            BinOp::Or => 3,
+            BinOp::Xor => 4,
+            BinOp::And => 5,
+            BinOp::Equal | BinOp::NotEqual => 6,
+            BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => 7,
+            BinOp::Add | BinOp::Sub => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Num(u64),
+    Op(&'static str),
+}
+
+/// longest operators first, so that we always match greedily
+const OPERATORS: [&str; 24] = [
+    "|->", "|=>", "##", "&&", "||", "==", "!=", "<=", ">=", "(", ")", "[", "]", ",", ":", "!", "~",
+    "-", "+", "&", "|", "^", "<", ">",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let mut out = Vec::new();
+    let mut rest = src.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let len = rest[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'))
+                .map(|l| l + 1)
+                .unwrap_or(rest.len());
+            out.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            out.push(Token::Num(parse_num(&rest[..len])?));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            out.push(Token::Op(op));
+            op.len()
+        } else {
+            return error(format!("unexpected character `{c}` in `{src}`"));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(out)
+}
+
+fn parse_num(s: &str) -> Result<u64> {
+    let s = s.replace('_', "");
+    let res = if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else {
+        s.parse::<u64>()
+    };
+    res.or_else(|_| error(format!("invalid number `{s}`")))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(o)) if *o == op)
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == name)
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<()> {
+        match self.next() {
+            Some(Token::Op(o)) if o == op => Ok(()),
+            other => error(format!("expected `{op}`, got {other:?}")),
+        }
+    }
+
+    fn expect_num(&mut self) -> Result<u64> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(n),
+            other => error(format!("expected a number, got {other:?}")),
+        }
+    }
+
+    /// Number of cycles, which needs to fit into 32 bits.
+    fn expect_cycles(&mut self) -> Result<u32> {
+        let n = self.expect_num()?;
+        u32::try_from(n).or_else(|_| error(format!("{n} cycles are too many")))
+    }
+
+    fn property(&mut self) -> Result<Property> {
+        let window = if self.is_ident("first") || self.is_ident("after") {
+            let is_first = self.is_ident("first");
+            self.pos += 1;
+            let n = self.expect_num()?;
+            self.expect_op(":")?;
+            if is_first {
+                Window::First(n)
+            } else {
+                Window::After(n)
+            }
+        } else {
+            Window::Always
+        };
+        let lhs = self.expr(0)?;
+        let implication = if self.is_op("|->") {
+            Some(0)
+        } else if self.is_op("|=>") {
+            Some(1)
+        } else {
+            None
+        };
+        let (antecedent, consequent) = if let Some(offset) = implication {
+            self.pos += 1;
+            let (lo, hi) = self.delay()?;
+            let rhs = self.expr(0)?;
+            let Some(hi) = hi.checked_add(offset) else {
+                return error(format!("{hi} cycles are too many"));
+            };
+            (Some((lhs, lo + offset, hi)), rhs)
+        } else {
+            (None, lhs)
+        };
+        if let Some(tok) = self.peek() {
+            return error(format!("unexpected {tok:?} at the end of the property"));
+        }
+        Ok(Property {
+            window,
+            antecedent,
+            consequent,
+        })
+    }
+
+    fn delay(&mut self) -> Result<(u32, u32)> {
+        if !self.is_op("##") {
+            return Ok((0, 0));
+        }
+        self.pos += 1;
+        let (lo, hi) = if self.is_op("[") {
+            self.pos += 1;
+            let lo = self.expect_cycles()?;
+            self.expect_op(":")?;
+            let hi = self.expect_cycles()?;
+            self.expect_op("]")?;
+            (lo, hi)
+        } else {
+            let n = self.expect_cycles()?;
+            (n, n)
+        };
+        if lo > hi {
+            return error(format!("invalid delay range [{lo}:{hi}]"));
+        }
+        Ok((lo, hi))
+    }
+
+    /// precedence climbing
+    fn expr(&mut self, min_precedence: u8) -> Result<Ast> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.binary_op() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(op.precedence())?;
+            lhs = Ast::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn binary_op(&self) -> Option<BinOp> {
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => return None,
+        };
+        let bin_op = match op {
+            "||" => BinOp::LogicOr,
+            "&&" => BinOp::LogicAnd,
+            "|" => BinOp::Or,
+            "^" => BinOp::Xor,
+            "&" => BinOp::And,
+            "==" => BinOp::Equal,
+            "!=" => BinOp::NotEqual,
+            "<" => BinOp::Less,
+            "<=" => BinOp::LessEqual,
+            ">" => BinOp::Greater,
+            ">=" => BinOp::GreaterEqual,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            _ => return None,
+        };
+        Some(bin_op)
+    }
+
+    fn unary(&mut self) -> Result<Ast> {
+        let op = if self.is_op("!") {
+            Some(UnaryOp::LogicNot)
+        } else if self.is_op("~") {
+            Some(UnaryOp::Not)
+        } else if self.is_op("-") {
+            Some(UnaryOp::Negate)
+        } else {
+            None
+        };
+        if let Some(op) = op {
+            self.pos += 1;
+            let e = self.unary()?;
+            Ok(Ast::Unary(op, Box::new(e)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Ast> {
+        let mut e = self.primary()?;
+        while self.is_op("[") {
+            self.pos += 1;
+            let hi = self.expect_num()? as WidthInt;
+            let lo = if self.is_op(":") {
+                self.pos += 1;
+                self.expect_num()? as WidthInt
+            } else {
+                hi
+            };
+            self.expect_op("]")?;
+            if lo > hi {
+                return error(format!("invalid slice [{hi}:{lo}]"));
+            }
+            e = Ast::Slice(Box::new(e), hi, lo);
+        }
+        Ok(e)
+    }
+
+    fn primary(&mut self) -> Result<Ast> {
+        match self.next() {
+            Some(Token::Num(value)) => Ok(Ast::Literal(value)),
+            Some(Token::Op("(")) => {
+                let e = self.expr(0)?;
+                self.expect_op(")")?;
+                Ok(e)
+            }
+            Some(Token::Ident(name)) if name.starts_with('$') => {
+                self.expect_op("(")?;
+                let e = Box::new(self.expr(0)?);
+                let out = match name.as_str() {
+                    "$past" => {
+                        let n = if self.is_op(",") {
+                            self.pos += 1;
+                            self.expect_cycles()?
+                        } else {
+                            1
+                        };
+                        Ast::Past(e, n)
+                    }
+                    "$rose" => Ast::Rose(e),
+                    "$fell" => Ast::Fell(e),
+                    "$stable" => Ast::Stable(e),
+                    other => return error(format!("unknown function `{other}`")),
+                };
+                self.expect_op(")")?;
+                Ok(out)
+            }
+            Some(Token::Ident(name)) => Ok(Ast::Signal(name)),
+            other => error(format!("expected an expression, got {other:?}")),
+        }
+    }
+}
+
+pub fn parse_property(src: &str) -> Result<Property> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    parser
+        .property()
+        .map_err(|e| TemporalError(format!("{e} (in `{src}`)")))
+}
+
+/// Adds all properties as environment assumptions, i.e., as constraints.
+/// Returns the number of monitor states that were added.
+pub fn add_assumptions(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    props: &[Property],
+) -> Result<usize> {
+    let mut compiler = Compiler::new(ctx, sys, "__assume");
+    for prop in props.iter() {
+        let fail = compiler.property(prop)?;
+        let constraint = compiler.ctx.not(fail);
+        add_label(compiler.sys, constraint, SignalLabels::constraint());
+    }
+    Ok(compiler.monitor_count)
+}
+
+/// Adds all properties as safety assertions, i.e., as bad states.
+/// Returns the number of monitor states that were added.
+pub fn add_assertions(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    props: &[Property],
+) -> Result<usize> {
+    let mut compiler = Compiler::new(ctx, sys, "__assert");
+    for prop in props.iter() {
+        let fail = compiler.property(prop)?;
+        add_label(compiler.sys, fail, SignalLabels::bad());
+    }
+    Ok(compiler.monitor_count)
+}
+
+/// Marks `expr` with `label` while retaining any existing signal information.
+fn add_label(sys: &mut TransitionSystem, expr: ExprRef, label: SignalLabels) {
+    let info = match sys.get_signal(expr) {
+        Some(info) => SignalInfo {
+            labels: info.labels.union(&label),
+            ..info.clone()
+        },
+        None => SignalInfo {
+            name: None,
+            kind: SignalKind::Node,
+            labels: label,
+        },
+    };
+    sys.add_signal(expr, info.kind, info.labels, info.name);
+}
+
+struct Compiler<'a> {
+    ctx: &'a mut Context,
+    sys: &'a mut TransitionSystem,
+    names: HashMap<String, ExprRef>,
+    prefix: &'static str,
+    monitor_count: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(ctx: &'a mut Context, sys: &'a mut TransitionSystem, prefix: &'static str) -> Self {
+        let names = sys.generate_name_to_ref(ctx);
+        Self {
+            ctx,
+            sys,
+            names,
+            prefix,
+            monitor_count: 0,
+        }
+    }
+
+    /// Returns an expression that is true iff the property is violated in the current cycle.
+    fn property(&mut self, prop: &Property) -> Result<ExprRef> {
+        let active = self.window(prop.window);
+        let consequent = self.expr(&prop.consequent, None)?;
+        let consequent = self.as_bool(consequent);
+        let Some((antecedent, lo, hi)) = &prop.antecedent else {
+            let not_consequent = self.ctx.not(consequent);
+            return Ok(self.ctx.and(active, not_consequent));
+        };
+        let antecedent = self.expr(antecedent, None)?;
+        let antecedent = self.as_bool(antecedent);
+        let trigger = self.ctx.and(active, antecedent);
+
+        // `pending[d]` is true iff the antecedent was true `d` cycles ago and the
+        // consequent has not been fulfilled in the meantime
+        let mut pending = vec![trigger];
+        for _ in 0..*hi {
+            let (symbol, _) = self.add_monitor(1);
+            pending.push(symbol);
+        }
+        for age in 0..(*hi as usize) {
+            let next = if age >= *lo as usize {
+                let not_consequent = self.ctx.not(consequent);
+                self.ctx.and(pending[age], not_consequent)
+            } else {
+                pending[age]
+            };
+            self.set_next(pending[age + 1], next);
+        }
+        let not_consequent = self.ctx.not(consequent);
+        Ok(self.ctx.and(pending[*hi as usize], not_consequent))
+    }
+
+    fn window(&mut self, window: Window) -> ExprRef {
+        let (n, first) = match window {
+            Window::Always => return self.ctx.one(1),
+            Window::First(n) => (n, true),
+            Window::After(n) => (n, false),
+        };
+        if n == 0 {
+            return if first {
+                self.ctx.zero(1)
+            } else {
+                self.ctx.one(1)
+            };
+        }
+        // saturating cycle counter
+        let width = (u64::BITS - n.leading_zeros()) as WidthInt;
+        let (count, _) = self.add_monitor(width);
+        let limit = self.ctx.bv_lit(n, width);
+        let one = self.ctx.one(width);
+        let is_saturated = self.ctx.bv_equal(count, limit);
+        let inc = self.ctx.add(count, one);
+        let next = self.ctx.bv_ite(is_saturated, count, inc);
+        self.set_next(count, next);
+        if first {
+            self.ctx.greater(limit, count)
+        } else {
+            is_saturated
+        }
+    }
+
+    /// Creates a new state that is initialized to zero.
+    fn add_monitor(&mut self, width: WidthInt) -> (ExprRef, StateRef) {
+        let name = loop {
+            let name = format!("{}_{}", self.prefix, self.monitor_count);
+            self.monitor_count += 1;
+            if !self.names.contains_key(&name) {
+                break name;
+            }
+        };
+        let symbol = self.ctx.bv_symbol(&name, width);
+        let init = self.ctx.zero(width);
+        let state = self.sys.add_state(self.ctx, symbol);
+        self.sys.modify_state(state, |s| s.init = Some(init));
+        self.names.insert(name, symbol);
+        (symbol, state)
+    }
+
+    fn set_next(&mut self, symbol: ExprRef, next: ExprRef) {
+        let (state, _) = self.sys.states().find(|(_, s)| s.symbol == symbol).unwrap();
+        self.sys.modify_state(state, |s| s.next = Some(next));
+    }
+
+    /// Returns a register that contains the value of `e` from `n` cycles ago.
+    fn past(&mut self, e: ExprRef, n: u32) -> ExprRef {
+        let width = e.get_bv_type(self.ctx).unwrap();
+        let mut prev = e;
+        for _ in 0..n {
+            let (symbol, _) = self.add_monitor(width);
+            self.set_next(symbol, prev);
+            prev = symbol;
+        }
+        prev
+    }
+
+    fn as_bool(&mut self, e: ExprRef) -> ExprRef {
+        let width = e.get_bv_type(self.ctx).unwrap();
+        if width == 1 {
+            e
+        } else {
+            let zero = self.ctx.zero(width);
+            let is_zero = self.ctx.bv_equal(e, zero);
+            self.ctx.not(is_zero)
+        }
+    }
+
+    fn extend_to(&mut self, e: ExprRef, width: WidthInt) -> ExprRef {
+        let by = width - e.get_bv_type(self.ctx).unwrap();
+        self.ctx.zero_extend(e, by)
+    }
+
+    /// Compiles a pair of operands to the same width. Literals adopt the width of the other side.
+    fn operands(
+        &mut self,
+        a: &Ast,
+        b: &Ast,
+        width_hint: Option<WidthInt>,
+    ) -> Result<(ExprRef, ExprRef)> {
+        let (a, b) = match (a, b) {
+            (Ast::Literal(_), Ast::Literal(_)) => {
+                (self.expr(a, width_hint)?, self.expr(b, width_hint)?)
+            }
+            (Ast::Literal(_), _) => {
+                let b = self.expr(b, width_hint)?;
+                let width = b.get_bv_type(self.ctx).unwrap();
+                (self.expr(a, Some(width))?, b)
+            }
+            _ => {
+                let a = self.expr(a, width_hint)?;
+                let width = a.get_bv_type(self.ctx).unwrap();
+                (a, self.expr(b, Some(width))?)
+            }
+        };
+        let width = std::cmp::max(
+            a.get_bv_type(self.ctx).unwrap(),
+            b.get_bv_type(self.ctx).unwrap(),
+        );
+        Ok((self.extend_to(a, width), self.extend_to(b, width)))
+    }
+
+    fn expr(&mut self, ast: &Ast, width_hint: Option<WidthInt>) -> Result<ExprRef> {
+        let e = match ast {
+            Ast::Signal(name) => match self.names.get(name) {
+                Some(e) if e.get_type(self.ctx).is_bit_vector() => *e,
+                Some(_) => return error(format!("array `{name}` cannot be used in properties")),
+                None => return error(format!("unknown signal `{name}`")),
+            },
+            Ast::Literal(value) => {
+                let min_width = std::cmp::max(1, u64::BITS - value.leading_zeros());
+                let width = width_hint.unwrap_or(min_width);
+                if !bv_value_fits_width(*value, width) {
+                    return error(format!("{value} does not fit into {width} bits"));
+                }
+                self.ctx.bv_lit(*value, width)
+            }
+            Ast::Unary(op, e) => {
+                let e = self.expr(e, width_hint)?;
+                match op {
+                    UnaryOp::LogicNot => {
+                        let b = self.as_bool(e);
+                        self.ctx.not(b)
+                    }
+                    UnaryOp::Not => self.ctx.not(e),
+                    UnaryOp::Negate => self.ctx.negate(e),
+                }
+            }
+            Ast::Binary(op, a, b) => self.binary(*op, a, b, width_hint)?,
+            Ast::Slice(e, hi, lo) => {
+                let e = self.expr(e, None)?;
+                let width = e.get_bv_type(self.ctx).unwrap();
+                if *hi >= width {
+                    return error(format!("slice [{hi}:{lo}] out of range for {width} bits"));
+                }
+                self.ctx.slice(e, *hi, *lo)
+            }
+            Ast::Past(e, n) => {
+                let e = self.expr(e, width_hint)?;
+                self.past(e, *n)
+            }
+            Ast::Rose(e) | Ast::Fell(e) => {
+                let e = self.expr(e, None)?;
+                let now = self.as_bool(e);
+                let before = self.past(now, 1);
+                let (high, low) = if matches!(ast, Ast::Rose(_)) {
+                    (now, before)
+                } else {
+                    (before, now)
+                };
+                let not_low = self.ctx.not(low);
+                self.ctx.and(high, not_low)
+            }
+            Ast::Stable(e) => {
+                let e = self.expr(e, None)?;
+                let before = self.past(e, 1);
+                self.ctx.bv_equal(e, before)
+            }
+        };
+        Ok(e)
+    }
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        a: &Ast,
+        b: &Ast,
+        width_hint: Option<WidthInt>,
+    ) -> Result<ExprRef> {
+        if matches!(op, BinOp::LogicAnd | BinOp::LogicOr) {
+            let a = self.expr(a, None)?;
+            let a = self.as_bool(a);
+            let b = self.expr(b, None)?;
+            let b = self.as_bool(b);
+            return Ok(if op == BinOp::LogicAnd {
+                self.ctx.and(a, b)
+            } else {
+                self.ctx.or(a, b)
+            });
+        }
+        let is_arithmetic = matches!(
+            op,
+            BinOp::Or | BinOp::Xor | BinOp::And | BinOp::Add | BinOp::Sub
+        );
+        let hint = if is_arithmetic { width_hint } else { None };
+        let (a, b) = self.operands(a, b, hint)?;
+        let e = match op {
+            BinOp::Or => self.ctx.or(a, b),
+            BinOp::Xor => self.ctx.xor(a, b),
+            BinOp::And => self.ctx.and(a, b),
+            BinOp::Add => self.ctx.add(a, b),
+            BinOp::Sub => self.ctx.sub(a, b),
+            BinOp::Equal => self.ctx.bv_equal(a, b),
+            BinOp::NotEqual => {
+                let eq = self.ctx.bv_equal(a, b);
+                self.ctx.not(eq)
+            }
+            BinOp::Less => self.ctx.greater(b, a),
+            BinOp::LessEqual => self.ctx.greater_or_equal(b, a),
+            BinOp::Greater => self.ctx.greater(a, b),
+            BinOp::GreaterEqual => self.ctx.greater_or_equal(a, b),
+            BinOp::LogicAnd | BinOp::LogicOr => unreachable!(),
+        };
+        Ok(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::mc::Simulator;
+    use patronus::sim::interpreter::{InitKind, Interpreter};
+
+    #[test]
+    fn test_parse_property() {
+        let p = parse_property("first 3: rst").unwrap();
+        assert_eq!(p.window, Window::First(3));
+        assert_eq!(p.consequent, Ast::Signal("rst".to_string()));
+        let p = parse_property("valid && !ready |=> valid").unwrap();
+        let (_, lo, hi) = p.antecedent.unwrap();
+        assert_eq!((lo, hi), (1, 1));
+        let p = parse_property("req |-> ##[1:4] ack").unwrap();
+        let (_, lo, hi) = p.antecedent.unwrap();
+        assert_eq!((lo, hi), (1, 4));
+        assert!(parse_property("a |-> ##[4:1] b").is_err());
+        // delays that do not fit into 32 bits are rejected instead of truncated
+        assert!(parse_property("a |-> ##[1:4294967297] b").is_err());
+        assert!(parse_property("a |=> ##4294967295 b").is_err());
+        assert!(parse_property("$past(a, 4294967296)").is_err());
+        assert!(parse_property("a +").is_err());
+    }
+
+    #[test]
+    fn test_stay_valid_until_ready() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let valid = ctx.bv_symbol("valid", 1);
+        let ready = ctx.bv_symbol("ready", 1);
+        sys.add_input(&ctx, valid);
+        sys.add_input(&ctx, ready);
+        let prop = parse_property("valid && !ready |=> valid").unwrap();
+        assert_eq!(add_assumptions(&mut ctx, &mut sys, &[prop]), Ok(1));
+        let (constraint, _) = sys.constraints()[0];
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut run = |valid_value: u64, ready_value: u64| {
+            sim.set(valid, ValueRef::new(&[valid_value], 1));
+            sim.set(ready, ValueRef::new(&[ready_value], 1));
+            sim.update();
+            let ok = sim.get(constraint).unwrap().to_u64().unwrap() == 1;
+            sim.step();
+            ok
+        };
+        assert!(run(1, 0));
+        // valid may not drop before ready
+        assert!(!run(0, 0));
+        assert!(run(1, 1));
+        assert!(run(0, 0));
+    }
+
+    #[test]
+    fn test_bounded_response() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let req = ctx.bv_symbol("req", 1);
+        let ack = ctx.bv_symbol("ack", 1);
+        sys.add_input(&ctx, req);
+        sys.add_input(&ctx, ack);
+        let prop = parse_property("req |-> ##[1:2] ack").unwrap();
+        assert_eq!(add_assertions(&mut ctx, &mut sys, &[prop]), Ok(2));
+        let (bad, _) = sys.bad_states()[0];
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        let mut run = |trace: &[(u64, u64)]| {
+            sim.init(InitKind::Zero);
+            let mut failed = false;
+            for (req_value, ack_value) in trace.iter() {
+                sim.set(req, ValueRef::new(&[*req_value], 1));
+                sim.set(ack, ValueRef::new(&[*ack_value], 1));
+                sim.update();
+                failed |= sim.get(bad).unwrap().to_u64().unwrap() == 1;
+                sim.step();
+            }
+            failed
+        };
+        assert!(!run(&[(1, 0), (0, 0), (0, 1)]));
+        assert!(!run(&[(1, 0), (0, 1), (0, 0)]));
+        // an acknowledgement in the same cycle does not count
+        assert!(run(&[(1, 1), (0, 0), (0, 0)]));
+        assert!(run(&[(1, 0), (1, 1), (0, 0), (0, 0)]));
+    }
+}
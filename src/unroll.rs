@@ -0,0 +1,202 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Unrolls a transition system into a SAT solver, one time frame at a time.
+
+use crate::bitblast::{check_supported, words, BitBlaster, Bits};
+use crate::sat::{Lit, Solver};
+use crate::{StepInt, Witness};
+use patronus::ir::*;
+use std::collections::{HashMap, HashSet};
+
+pub struct Unroller {
+    states: Vec<State>,
+    inputs: Vec<ExprRef>,
+    bad_states: Vec<ExprRef>,
+    constraints: Vec<ExprRef>,
+    /// states with an initial value, ordered such that every init only depends on earlier ones
+    init_order: Vec<usize>,
+    /// the first frame starts in the initial states, otherwise in any state
+    use_init: bool,
+    /// bits of every expression that was converted in a time frame
+    frames: Vec<HashMap<ExprRef, Bits>>,
+    pub blaster: BitBlaster,
+}
+
+impl Unroller {
+    pub fn new(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        solver: &mut Solver,
+        use_init: bool,
+    ) -> Result<Self, String> {
+        check_supported(ctx, sys)?;
+        let states = sys.states().map(|(_, s)| s.clone()).collect::<Vec<_>>();
+        Ok(Self {
+            init_order: init_order(ctx, &states)?,
+            states,
+            inputs: signals(sys, |s| s.is_input()),
+            bad_states: signals(sys, |s| s.labels.is_bad()),
+            constraints: signals(sys, |s| s.labels.is_constraint()),
+            use_init,
+            frames: Vec::new(),
+            blaster: BitBlaster::new(solver),
+        })
+    }
+
+    /// Adds the next time frame and asserts the constraints in it.
+    pub fn add_frame(&mut self, ctx: &Context, solver: &mut Solver) {
+        let mut frame = HashMap::new();
+        for input in self.inputs.iter() {
+            let width = input.get_bv_type(ctx).unwrap();
+            frame.insert(*input, self.blaster.fresh(solver, width));
+        }
+        match self.frames.last_mut() {
+            None => {
+                for state in self.states.iter() {
+                    if !self.use_init || state.init.is_none() {
+                        let width = state.symbol.get_bv_type(ctx).unwrap();
+                        frame.insert(state.symbol, self.blaster.fresh(solver, width));
+                    }
+                }
+                if self.use_init {
+                    for ii in self.init_order.iter() {
+                        let state = &self.states[*ii];
+                        let init = state.init.unwrap();
+                        let bits = self.blaster.blast(ctx, solver, &mut frame, init);
+                        frame.insert(state.symbol, bits);
+                    }
+                }
+            }
+            Some(prev) => {
+                for state in self.states.iter() {
+                    let bits = match state.next {
+                        Some(next) => self.blaster.blast(ctx, solver, prev, next),
+                        None => {
+                            let width = state.symbol.get_bv_type(ctx).unwrap();
+                            self.blaster.fresh(solver, width)
+                        }
+                    };
+                    frame.insert(state.symbol, bits);
+                }
+            }
+        }
+        for constraint in self.constraints.iter() {
+            let bits = self.blaster.blast(ctx, solver, &mut frame, *constraint);
+            solver.add_clause(&bits);
+        }
+        self.frames.push(frame);
+    }
+
+    /// Bits of a state in time frame `k`.
+    pub fn state(&self, k: usize, state: usize) -> &[Lit] {
+        &self.frames[k][&self.states[state].symbol]
+    }
+
+    /// Bits of an input in time frame `k`.
+    pub fn input(&self, k: usize, input: usize) -> &[Lit] {
+        &self.frames[k][&self.inputs[input]]
+    }
+
+    /// Bits of an expression over the states and inputs in time frame `k`.
+    pub fn expr(&mut self, ctx: &Context, solver: &mut Solver, k: usize, expr: ExprRef) -> Bits {
+        self.blaster.blast(ctx, solver, &mut self.frames[k], expr)
+    }
+
+    pub fn bad(&mut self, ctx: &Context, solver: &mut Solver, k: usize, bad: usize) -> Lit {
+        let expr = self.bad_states[bad];
+        self.expr(ctx, solver, k, expr)[0]
+    }
+
+    /// Literal that is true iff any bad state is reached in time frame `k`.
+    pub fn any_bad(&mut self, ctx: &Context, solver: &mut Solver, k: usize) -> Lit {
+        let bads = (0..self.bad_states.len())
+            .map(|ii| self.bad(ctx, solver, k, ii))
+            .collect::<Vec<_>>();
+        self.blaster.or_all(solver, &bads)
+    }
+
+    /// Literal that is true iff the states in frames `a` and `b` are equal.
+    pub fn same_states(&mut self, solver: &mut Solver, a: usize, b: usize) -> Lit {
+        let same = (0..self.states.len())
+            .map(|ii| {
+                let (a, b) = (self.state(a, ii).to_vec(), self.state(b, ii).to_vec());
+                self.blaster.equal(solver, &a, &b)
+            })
+            .collect::<Vec<_>>();
+        self.blaster.and_all(solver, &same)
+    }
+
+    /// Reads a witness from a satisfying assignment in which a bad state is reached in frame `k`.
+    pub fn witness(&mut self, ctx: &Context, solver: &mut Solver, k: usize) -> Witness {
+        let state_init = (0..self.states.len())
+            .flat_map(|ii| words(solver, self.state(0, ii)))
+            .collect();
+        let input_data = self.frames[..=k]
+            .iter()
+            .flat_map(|frame| self.inputs.iter().flat_map(|i| words(solver, &frame[i])))
+            .collect();
+        let bads = (0..self.bad_states.len())
+            .map(|ii| self.bad(ctx, solver, k, ii))
+            .collect::<Vec<_>>();
+        let failed_safety = (0..bads.len())
+            .filter(|ii| solver.value(bads[*ii]))
+            .collect();
+        Witness {
+            input_data,
+            state_init,
+            k: k as StepInt,
+            failed_safety,
+        }
+    }
+}
+
+fn signals(sys: &TransitionSystem, filter: fn(&SignalInfo) -> bool) -> Vec<ExprRef> {
+    sys.get_signals(filter)
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect()
+}
+
+/// Sorts the states with an initial value by the other initial values that they depend on.
+fn init_order(ctx: &Context, states: &[State]) -> Result<Vec<usize>, String> {
+    let with_init = states
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.init.is_some())
+        .map(|(ii, s)| (s.symbol, ii))
+        .collect::<HashMap<_, _>>();
+    let mut deps = HashMap::new();
+    for (_, ii) in with_init.iter() {
+        let mut todo = vec![states[*ii].init.unwrap()];
+        let mut visited = HashSet::new();
+        let mut depends_on = Vec::new();
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            if let Some(other) = with_init.get(&e) {
+                depends_on.push(*other);
+            }
+            ctx.get(e).for_each_child(|c| todo.push(*c));
+        }
+        deps.insert(*ii, depends_on);
+    }
+    let mut order: Vec<usize> = Vec::new();
+    let mut done = HashSet::new();
+    while order.len() < with_init.len() {
+        let mut ready = deps
+            .iter()
+            .filter(|(ii, d)| !done.contains(*ii) && d.iter().all(|d| done.contains(d)))
+            .map(|(ii, _)| *ii)
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            return Err("the initial values of some states depend on each other".to_string());
+        }
+        ready.sort_unstable();
+        done.extend(ready.iter().copied());
+        order.extend(ready);
+    }
+    Ok(order)
+}
@@ -0,0 +1,210 @@
+// Copyright 2024 Cornell University
+// released under MIT License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//
+// Minimal reader for value change dumps (VCD) of testbench stimulus.
+// We only support bit-vector variables of up to 64 bits, `x` and `z` are read as zero.
+
+use patronus::ir::WidthInt;
+use std::collections::HashMap;
+
+/// Values of all variables, sampled once per cycle.
+#[derive(Debug, Clone, Default)]
+pub struct VcdTrace {
+    /// hierarchical names, separated by `.`
+    pub names: Vec<String>,
+    pub widths: Vec<WidthInt>,
+    /// values of all variables in every cycle
+    pub cycles: Vec<Vec<u64>>,
+}
+
+impl VcdTrace {
+    /// Finds a variable by its full hierarchical name or by a suffix of it that starts
+    /// at a scope boundary, since the top-level scopes are named by the testbench.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        let suffix = format!(".{name}");
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .or_else(|| self.names.iter().position(|n| n.ends_with(&suffix)))
+    }
+
+    /// Returns the values of a variable in every cycle.
+    pub fn values(&self, var: usize) -> impl Iterator<Item = u64> + '_ {
+        self.cycles.iter().map(move |c| c[var])
+    }
+}
+
+/// Parses a VCD file. If a clock is given, values are sampled right before every rising
+/// edge of the clock, otherwise every time step is a cycle.
+pub fn parse_vcd(src: &str, clock: Option<&str>) -> Result<VcdTrace, String> {
+    let mut trace = VcdTrace::default();
+    // variables that share an identifier code are aliases
+    let mut ids: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut scopes: Vec<&str> = Vec::new();
+    let mut tokens = src.split_whitespace();
+
+    // header
+    let mut in_definitions = true;
+    while in_definitions {
+        let Some(token) = tokens.next() else {
+            return Err("missing `$enddefinitions`".to_string());
+        };
+        let args = read_until_end(&mut tokens)?;
+        match token {
+            "$scope" => scopes.push(args.get(1).ok_or("missing scope name")?),
+            "$upscope" => {
+                scopes.pop();
+            }
+            "$var" => {
+                let [_, width, id, name, ..] = args.as_slice() else {
+                    return Err(format!("invalid variable definition `{}`", args.join(" ")));
+                };
+                let width = width
+                    .parse::<WidthInt>()
+                    .map_err(|_| format!("invalid width of `{name}`"))?;
+                if width > 64 {
+                    return Err(format!("`{name}` is wider than 64-bit ({width})"));
+                }
+                let mut full_name = scopes.join(".");
+                if !full_name.is_empty() {
+                    full_name.push('.');
+                }
+                full_name.push_str(name);
+                ids.entry(id).or_default().push(trace.names.len());
+                trace.names.push(full_name);
+                trace.widths.push(width);
+            }
+            "$enddefinitions" => in_definitions = false,
+            _ => {} // comments, date, version, timescale
+        }
+    }
+
+    let clock = match clock {
+        Some(name) => {
+            let var = trace
+                .find(name)
+                .ok_or_else(|| format!("unknown clock `{name}`"))?;
+            if trace.widths[var] != 1 {
+                return Err(format!("clock `{name}` is not a 1-bit signal"));
+            }
+            Some(var)
+        }
+        None => None,
+    };
+
+    // value changes
+    let mut values = vec![0u64; trace.names.len()];
+    // values at the start of the current time step
+    let mut before = values.clone();
+    let mut started = false;
+    let end_step = |values: &[u64], before: &mut Vec<u64>, trace: &mut VcdTrace| {
+        match clock {
+            Some(clock) => {
+                if before[clock] == 0 && values[clock] == 1 {
+                    trace.cycles.push(before.clone());
+                }
+            }
+            None => trace.cycles.push(values.to_vec()),
+        }
+        before.copy_from_slice(values);
+    };
+    while let Some(token) = tokens.next() {
+        let mut set = |id: &str, value: u64| -> Result<(), String> {
+            let vars = ids
+                .get(id)
+                .ok_or_else(|| format!("unknown identifier `{id}`"))?;
+            for var in vars.iter() {
+                values[*var] = value;
+            }
+            Ok(())
+        };
+        if token.starts_with('#') {
+            if started {
+                end_step(&values, &mut before, &mut trace);
+            }
+            started = true;
+        } else if token == "$comment" {
+            read_until_end(&mut tokens)?;
+        } else if let Some(bits) = token.strip_prefix(['b', 'B']) {
+            let id = tokens.next().ok_or("missing identifier")?;
+            set(id, parse_bits(bits))?;
+        } else if token.starts_with(['r', 'R']) {
+            // real values are not supported and thus ignored
+            tokens.next();
+        } else if let Some(id) = token.strip_prefix(['0', '1', 'x', 'X', 'z', 'Z']) {
+            set(id, (token.as_bytes()[0] == b'1') as u64)?;
+        }
+        // $dumpvars and friends only wrap value changes
+    }
+    if started {
+        end_step(&values, &mut before, &mut trace);
+    }
+    Ok(trace)
+}
+
+/// Collects all tokens until `$end`.
+fn read_until_end<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<Vec<&'a str>, String> {
+    let mut out = Vec::new();
+    for token in tokens.by_ref() {
+        if token == "$end" {
+            return Ok(out);
+        }
+        out.push(token);
+    }
+    Err("missing `$end`".to_string())
+}
+
+/// Reads a binary value, unknown bits are treated as zero.
+fn parse_bits(bits: &str) -> u64 {
+    bits.bytes()
+        .fold(0, |value, bit| (value << 1) | (bit == b'1') as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VCD: &str = r#"
+$timescale 1ns $end
+$scope module tb $end
+$var wire 1 ! clk $end
+$scope module dut $end
+$var wire 1 ! clk $end
+$var wire 4 " data [3:0] $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+bx "
+$end
+#5
+1!
+#7
+b101 "
+#10
+0!
+#15
+1!
+b11 "
+#20
+0!
+"#;
+
+    #[test]
+    fn test_parse_vcd() {
+        let trace = parse_vcd(VCD, None).unwrap();
+        assert_eq!(trace.names, ["tb.clk", "tb.dut.clk", "tb.dut.data"]);
+        assert_eq!(trace.find("data"), Some(2));
+        assert_eq!(trace.find("clk"), Some(0));
+        assert_eq!(trace.cycles.len(), 6);
+        assert_eq!(trace.values(2).collect::<Vec<_>>(), [0, 0, 5, 5, 3, 3]);
+
+        // sampled right before the rising edges at 5 and 15
+        let trace = parse_vcd(VCD, Some("clk")).unwrap();
+        assert_eq!(trace.values(2).collect::<Vec<_>>(), [0, 5]);
+        assert!(parse_vcd(VCD, Some("data")).is_err());
+    }
+}